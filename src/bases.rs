@@ -0,0 +1,300 @@
+use crate::seen::HaveSeen;
+use rsbwapi::{TilePosition, UnitType, WeaponType};
+
+// anything this many tiles from a base location is at that base
+pub(crate) const BASE_RADIUS: u32 = 10;
+// enemy army this many pixels from a base is there to defend it
+const DEFENSE_RADIUS: u32 = 32 * 12;
+// only count defenders we've seen this recently
+const DEFENSE_FRAMES: i32 = 24 * 30;
+// how much more a base with a resource depot is worth killing
+const DEPOT_VALUE: f32 = 4.0;
+// static defense counts as this much supply of army
+const STATIC_DEFENSE_SUPPLY: f32 = 4.0;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Owner {
+    /// haven't had a look
+    Unknown,
+    /// nobody there when we last looked
+    Neutral,
+    Ours,
+    Enemy,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Base {
+    pub tile: TilePosition,
+    pub owner: Owner,
+    /// the last frame we knew who owned it
+    pub confirmed: i32,
+}
+
+/// Who owns each base location
+pub(crate) struct BaseMap {
+    bases: Vec<Base>,
+}
+
+impl BaseMap {
+    pub fn new() -> Self {
+        BaseMap { bases: vec![] }
+    }
+
+    pub fn bases(&self) -> &[Base] {
+        &self.bases
+    }
+
+    /// Work out who owns `locations` from where our hatcheries are, the enemy
+    /// buildings we remember and which locations are in sight
+    pub fn update(
+        &mut self,
+        frame: i32,
+        locations: &[TilePosition],
+        ours: &[TilePosition],
+        seen: &HaveSeen,
+        is_visible: impl Fn(TilePosition) -> bool,
+    ) {
+        for tile in locations {
+            if !self.bases.iter().any(|b| b.tile == *tile) {
+                self.bases.push(Base {
+                    tile: *tile,
+                    owner: Owner::Unknown,
+                    confirmed: 0,
+                });
+            }
+        }
+        for base in self.bases.iter_mut() {
+            let buildings = seen.buildings_at(base.tile);
+            let (owner, confirmed) = if ours
+                .iter()
+                .any(|h| h.chebyshev_distance(base.tile) <= BASE_RADIUS)
+            {
+                (Owner::Ours, frame)
+            } else if let Some(last) = buildings.iter().map(|b| b.frame).max() {
+                (Owner::Enemy, last)
+            } else if is_visible(base.tile) {
+                (Owner::Neutral, frame)
+            } else if matches!(base.owner, Owner::Ours | Owner::Enemy) {
+                // it's gone but we didn't see who's there now
+                (Owner::Unknown, base.confirmed)
+            } else {
+                (base.owner, base.confirmed)
+            };
+            if owner != base.owner {
+                println!(
+                    "frame {} :: base at {:?} {:?} -> {:?}",
+                    frame, base.tile, base.owner, owner
+                );
+            }
+            base.owner = owner;
+            base.confirmed = confirmed;
+        }
+    }
+
+    /// The closest base nobody has taken that `is_safe`, for mining from
+    /// without a hatchery
+    pub fn long_distance_base(
+        &self,
+        home: TilePosition,
+        is_safe: impl Fn(TilePosition) -> bool,
+    ) -> Option<TilePosition> {
+        self.bases
+            .iter()
+            .filter(|b| matches!(b.owner, Owner::Neutral | Owner::Unknown) && is_safe(b.tile))
+            .map(|b| b.tile)
+            .min_by_key(|tile| (tile.distance_squared(home), tile.x, tile.y))
+    }
+
+    /// The enemy base most worth attacking with `army`: the most buildings for
+    /// the least defense, closest to `home` if it's a tie
+    pub fn attack_target(
+        &self,
+        home: TilePosition,
+        army: &[UnitType],
+        seen: &HaveSeen,
+    ) -> Option<TilePosition> {
+        // spores and turrets don't matter to lings
+        let hits_army = |defense: UnitType| {
+            army.iter().any(|u| match defense {
+                UnitType::Terran_Bunker => true,
+                _ if u.is_flyer() => defense.air_weapon() != WeaponType::None,
+                _ => defense.ground_weapon() != WeaponType::None,
+            })
+        };
+        let score = |base: &Base| {
+            let buildings = seen.buildings_at(base.tile);
+            let value: f32 = buildings
+                .iter()
+                .map(|b| match b.unit_type {
+                    t if t.is_resource_depot() => DEPOT_VALUE,
+                    _ => 1.0,
+                })
+                .sum();
+            let static_defense = buildings.iter().filter(|b| hits_army(b.unit_type)).count() as f32;
+            let defenders: f32 = seen
+                .enemy_army_near(base.tile.to_position(), DEFENSE_RADIUS, DEFENSE_FRAMES)
+                .iter()
+                // BW doubles supply
+                .map(|su| su.unit_type.supply_required() as f32 / 2.0)
+                .sum();
+            value / (1.0 + defenders + static_defense * STATIC_DEFENSE_SUPPLY)
+        };
+        self.bases
+            .iter()
+            .filter(|b| b.owner == Owner::Enemy)
+            .map(|b| (score(b), b.tile))
+            .max_by(|(a, ta), (b, tb)| {
+                a.total_cmp(b)
+                    .then(tb.distance_squared(home).cmp(&ta.distance_squared(home)))
+            })
+            .map(|(_, tile)| tile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seen::SeenUnit;
+    use crate::test_util::tp;
+    use rsbwapi::UnitId;
+
+    const HOME: TilePosition = TilePosition { x: 8, y: 8 };
+    const NATURAL: TilePosition = TilePosition { x: 30, y: 10 };
+    const THEIR_MAIN: TilePosition = TilePosition { x: 100, y: 100 };
+    const THEIR_NATURAL: TilePosition = TilePosition { x: 80, y: 100 };
+    const LOCATIONS: [TilePosition; 4] = [HOME, NATURAL, THEIR_MAIN, THEIR_NATURAL];
+    const LINGS: [UnitType; 2] = [UnitType::Zerg_Zergling, UnitType::Zerg_Zergling];
+    const MUTAS: [UnitType; 2] = [UnitType::Zerg_Mutalisk, UnitType::Zerg_Mutalisk];
+
+    fn at(unit_type: UnitType, frame: i32, tile: TilePosition) -> SeenUnit {
+        let mut unit = SeenUnit::fake(unit_type, frame);
+        unit.position = tile.to_position();
+        unit
+    }
+
+    fn owners(map: &BaseMap) -> Vec<Owner> {
+        map.bases().iter().map(|b| b.owner).collect()
+    }
+
+    #[test]
+    fn test_ownership() {
+        let mut seen = HaveSeen::new();
+        let mut map = BaseMap::new();
+        map.update(0, &LOCATIONS, &[HOME], &seen, |_| false);
+        use Owner::*;
+        assert_eq!(owners(&map), vec![Ours, Unknown, Unknown, Unknown]);
+
+        // the scout gets to their main and has a look at the natural
+        let nexus = at(UnitType::Protoss_Nexus, 100, THEIR_MAIN);
+        seen.update(100, vec![(1, nexus)]);
+        map.update(100, &LOCATIONS, &[HOME], &seen, |t| t == THEIR_NATURAL);
+        assert_eq!(owners(&map), vec![Ours, Unknown, Enemy, Neutral]);
+
+        // we expand, they expand while we're not looking
+        let natural = at(UnitType::Protoss_Nexus, 500, THEIR_NATURAL);
+        seen.update(500, vec![(2, natural)]);
+        map.update(600, &LOCATIONS, &[HOME, NATURAL], &seen, |_| false);
+        assert_eq!(owners(&map), vec![Ours, Ours, Enemy, Enemy]);
+        assert_eq!(map.bases()[3].confirmed, 500, "when we saw it");
+
+        // we kill their natural, they kill ours
+        seen.on_unit_destroy(2);
+        map.update(700, &LOCATIONS, &[HOME], &seen, |t| t == THEIR_NATURAL);
+        assert_eq!(owners(&map), vec![Ours, Unknown, Enemy, Neutral]);
+        assert_eq!(map.bases()[1].confirmed, 600);
+    }
+
+    #[test]
+    fn test_long_distance_base() {
+        let mut seen = HaveSeen::new();
+        let mut map = BaseMap::new();
+        map.update(0, &LOCATIONS, &[HOME], &seen, |_| false);
+        assert_eq!(map.long_distance_base(HOME, |_| true), Some(NATURAL));
+        assert_eq!(
+            map.long_distance_base(HOME, |t| t != NATURAL),
+            Some(THEIR_NATURAL),
+            "not where the enemy army is"
+        );
+
+        let nexus = at(UnitType::Protoss_Nexus, 100, THEIR_NATURAL);
+        seen.update(100, vec![(1, nexus)]);
+        map.update(100, &LOCATIONS, &[HOME, NATURAL], &seen, |_| false);
+        assert_eq!(map.long_distance_base(HOME, |_| true), Some(THEIR_MAIN));
+        assert_eq!(
+            map.long_distance_base(HOME, |t| t != THEIR_MAIN),
+            None,
+            "everywhere's taken or unsafe"
+        );
+    }
+
+    #[test]
+    fn test_attack_target() {
+        let mut seen = HaveSeen::new();
+        let mut map = BaseMap::new();
+        map.update(0, &LOCATIONS, &[HOME], &seen, |_| false);
+        assert_eq!(
+            map.attack_target(HOME, &LINGS, &seen),
+            None,
+            "nowhere to attack"
+        );
+
+        let buildings = vec![
+            (1, at(UnitType::Protoss_Nexus, 100, THEIR_MAIN)),
+            (2, at(UnitType::Protoss_Gateway, 100, THEIR_MAIN)),
+            (3, at(UnitType::Protoss_Nexus, 100, THEIR_NATURAL)),
+        ];
+        seen.update(100, buildings);
+        map.update(100, &LOCATIONS, &[HOME], &seen, |_| false);
+        assert_eq!(
+            map.attack_target(HOME, &LINGS, &seen),
+            Some(THEIR_MAIN),
+            "more to kill there"
+        );
+
+        let cannon = at(UnitType::Protoss_Photon_Cannon, 200, THEIR_MAIN);
+        seen.update(200, vec![(4, cannon)]);
+        map.update(200, &LOCATIONS, &[HOME], &seen, |_| false);
+        assert_eq!(
+            map.attack_target(HOME, &LINGS, &seen),
+            Some(THEIR_NATURAL),
+            "the natural is undefended"
+        );
+
+        let zealots: Vec<(UnitId, SeenUnit)> = (5..8)
+            .map(|id| (id, at(UnitType::Protoss_Zealot, 300, tp(80, 98))))
+            .collect();
+        seen.update(300, zealots);
+        assert_eq!(
+            map.attack_target(HOME, &LINGS, &seen),
+            Some(THEIR_MAIN),
+            "the army went to the natural"
+        );
+    }
+
+    #[test]
+    fn test_static_defense() {
+        let mut seen = HaveSeen::new();
+        let mut map = BaseMap::new();
+        let buildings = vec![
+            (1, at(UnitType::Terran_Command_Center, 100, THEIR_MAIN)),
+            (2, at(UnitType::Terran_Command_Center, 100, THEIR_NATURAL)),
+            (3, at(UnitType::Terran_Missile_Turret, 100, THEIR_NATURAL)),
+        ];
+        seen.update(100, buildings);
+        map.update(100, &LOCATIONS, &[HOME], &seen, |_| false);
+        assert_eq!(
+            map.attack_target(HOME, &LINGS, &seen),
+            Some(THEIR_NATURAL),
+            "turrets can't hit lings"
+        );
+        assert_eq!(map.attack_target(HOME, &MUTAS, &seen), Some(THEIR_MAIN));
+
+        let bunker = at(UnitType::Terran_Bunker, 200, THEIR_MAIN);
+        seen.update(200, vec![(4, bunker)]);
+        assert_eq!(
+            map.attack_target(HOME, &MUTAS, &seen),
+            Some(THEIR_NATURAL),
+            "the marines in it can"
+        );
+    }
+}
@@ -1,47 +1,97 @@
 use std::{thread, time::Duration};
 
 use crate::{
-    build_order::BuildOrder,
-    build_position::{cartesian_center, position_building, tile_position_towards},
-    counts::Counts,
+    bases::{BaseMap, BASE_RADIUS},
+    build_order::{BuildOrder, PlannedBuilding},
+    build_position::{
+        cartesian_center, depot_estimate, position_building, position_macro_hatch,
+        tile_position_towards,
+    },
+    builder::{travel_frames, BuilderAction, BuilderEvent, BuilderPool},
+    composition::CompPlanner,
+    counts::{Counts, Income},
     drones::{DroneManager, DroneRole},
+    economy::{drone_target, Economy, Threat},
     gas::GasManager,
+    larva::LarvaPlanner,
     minerals::MineralManager,
-    scouting::Scout,
+    neutrals::blockers_in_the_way,
+    overlords::{overlord_posts, OverlordManager, OverlordOrder},
+    production::{Production, GOALS_PATH},
+    research::Research,
+    scout_micro::{DroneScout, ScoutAction},
+    scouting::{natural_base, ScoutGoal, ScoutManager},
     seen::HaveSeen,
+    sim::{Matchup, SimUnit},
+    strategy::{recognize, Strategy},
+    threat::{FleeOrder, ThreatMap},
     unit_comp::UnitComp,
+    worker_defense::{DefenseOrder, WorkerDefense},
 };
 use rsbwapi::*;
 
 pub struct BotCallbacks {
     build: BuildOrder,
+    composition: CompPlanner,
     gasses: GasManager,
+    larva: LarvaPlanner,
     minerals: MineralManager,
+    production: Production,
+    research: Research,
     drones: DroneManager,
-    drone_scout: Option<Scout>,
-    drone_builder: Option<Unit>,
+    scouts: ScoutManager,
+    drone_scout: Option<DroneScout>,
+    // the drone mining out a mineral wall and the wall
+    clearing: Option<(UnitId, UnitId)>,
+    overlords: OverlordManager,
+    builders: BuilderPool,
+    worker_defense: WorkerDefense,
     seen: HaveSeen,
+    bases: BaseMap,
+    strategy: Strategy,
+    threats: ThreatMap,
     counts: Counts,
+    income: Income,
     start_position: TilePosition,
     enemy_race: Race,
-    enemy_id: PlayerId,
 }
 
 const ONE_TILE: TilePosition = TilePosition { x: 1, y: 1 };
+// go after enemy units we've seen this recently
+const ARMY_MEMORY_FRAMES: i32 = 24 * 30;
+// the army is back home once it's this close to the rally point
+const RETREAT_DISTANCE: u32 = 32 * 4;
+// only block their gas with money to spare, it costs us the drone too
+const GAS_BLOCK_MINERALS: i32 = 150;
+// spare a drone to mine out a mineral wall once this many are mining
+const CLEARING_MINERS: usize = 12;
+// mine out walls at the bases we'll take next, not counting our main
+const NEXT_BASES: usize = 2;
 
 impl BotCallbacks {
     pub fn new() -> Self {
         BotCallbacks {
             build: BuildOrder::new(),
+            composition: CompPlanner::new(),
             gasses: GasManager::new(),
+            larva: LarvaPlanner::new(),
             minerals: MineralManager::new(),
+            production: Production::load(GOALS_PATH),
+            research: Research::new(),
             seen: HaveSeen::new(),
+            bases: BaseMap::new(),
+            strategy: Strategy::unknown(),
+            threats: ThreatMap::new(0, 0),
             counts: Counts::new_fake(0),
+            income: Income::new(),
             drones: DroneManager::new(),
+            scouts: ScoutManager::new(),
             drone_scout: None,
-            drone_builder: None,
+            clearing: None,
+            overlords: OverlordManager::new(),
+            builders: BuilderPool::new(),
+            worker_defense: WorkerDefense::new(),
             enemy_race: Race::Random,
-            enemy_id: PlayerId::default(),
             start_position: TilePosition::default(),
         }
     }
@@ -51,7 +101,6 @@ impl BotCallbacks {
     fn set_enemy_race(&mut self, game: &Game) {
         if let Some(enemy) = game.enemy() {
             self.enemy_race = enemy.get_race();
-            self.enemy_id = enemy.get_id();
             println!("playing against {:?}", self.enemy_race);
         }
     }
@@ -60,54 +109,194 @@ impl BotCallbacks {
         for p in game.get_start_locations() {
             if game.is_visible(p) {
                 self.start_position = p;
+                self.seen.set_start_locations(p, game.get_start_locations());
                 println!("Our starting position is {:?}", p);
                 break;
             }
         }
     }
 
-    fn build_new_building(&mut self, game: &Game, to_build: UnitType) {
-        if self.drone_builder.is_none() {
-            if let Some(drone_id) = self.drones.grab_and_assign(DroneRole::Builder) {
-                self.drone_builder = game.get_unit(drone_id);
-                println!("after grabbing a builder drone: {:?}", self.drone_builder);
+    fn find_tile(
+        &self,
+        game: &Game,
+        planned: PlannedBuilding,
+        drone: &Unit,
+    ) -> Option<TilePosition> {
+        let reserved = self.builders.reserved(planned.unit_type);
+        if planned.macro_hatch {
+            position_macro_hatch(game, drone, &self.seen, &reserved)
+        } else {
+            position_building(game, planned.unit_type, drone, &self.seen, &reserved)
+        }
+    }
+
+    /// the mining drone closest to where the building goes and how long it'd
+    /// take to get there
+    fn closest_builder(
+        &self,
+        game: &Game,
+        my_units: &Vec<Unit>,
+        planned: PlannedBuilding,
+    ) -> Option<(UnitId, i32)> {
+        let miners: Vec<&Unit> = my_units
+            .iter()
+            .filter(|u| {
+                u.get_type() == UnitType::Zerg_Drone
+                    && self.drones.check_role(&u.get_id()) == DroneRole::Minerals
+            })
+            .collect();
+        // any drone will do to find the spot
+        let tile = miners
+            .first()
+            .and_then(|d| self.find_tile(game, planned, d))?;
+        miners
+            .into_iter()
+            .min_by_key(|d| d.get_position().get_approx_distance(tile.to_position()))
+            .map(|d| {
+                (
+                    d.get_id(),
+                    travel_frames(d.get_position(), tile.to_position()),
+                )
+            })
+    }
+
+    /// send builders for everything due in the build order, setting aside
+    /// money for each building in turn
+    fn place_buildings(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        let planned = self.build.next_buildings(game, &self.counts);
+        self.builders.forget(self.counts.frame());
+        for drone in self.builders.release_unplanned(&planned) {
+            if let Some(drone) = game.get_unit(drone) {
+                drone.stop().ok();
             }
+            self.drones.idle(drone);
         }
 
-        game.draw_text_screen((10, 10), &format!("building {:?}", to_build));
+        let types: Vec<UnitType> = planned.iter().map(|p| p.unit_type).collect();
+        game.draw_text_screen((10, 10), &format!("building {:?}", types));
+        if let Some(report) = self.seen.scout_report() {
+            game.draw_text_screen((10, 30), &format!("scouted {:?}", report));
+        }
 
-        if let Some(builder_drone) = self.drone_builder.as_ref() {
-            if let Some(tp) = position_building(game, to_build, builder_drone, &self.seen) {
-                game.draw_box_map(
-                    tp.to_position(),
-                    (tp + to_build.tile_size()).to_position(),
-                    Color::White,
-                    false,
+        for p in planned.iter() {
+            match p.unit_type {
+                UnitType::Zerg_Lair | UnitType::Zerg_Hive | UnitType::Zerg_Greater_Spire => {
+                    self.upgrade_building(my_units, p.unit_type)
+                }
+                to_build if to_build.is_building() => self.build_new_building(game, my_units, *p),
+                not_a_building => {
+                    println!("saw non-building {:?} from build order", not_a_building)
+                }
+            }
+        }
+        // buildings the plan has moved past still have to start
+        for p in self.builders.unplanned(&planned) {
+            self.step_builder(game, p);
+        }
+    }
+
+    fn build_new_building(&mut self, game: &Game, my_units: &Vec<Unit>, planned: PlannedBuilding) {
+        if !self.builders.contains(&planned) {
+            let closest = self.closest_builder(game, my_units, planned);
+            if let Some(drone) = self.builders.dispatch(planned, &self.counts, closest) {
+                println!(
+                    "frame {} :: sending drone {} to build {:?}",
+                    self.counts.frame(),
+                    drone,
+                    planned
                 );
-                if self.counts.can_afford(to_build) {
-                    println!("placing a {:?} at {:?}", to_build, tp);
+                self.drones.assign(drone, DroneRole::Builder);
+            }
+        }
+        self.step_builder(game, planned);
+        // buildings spend when they start, don't spend the building's
+        // money on units or other buildings in the meantime
+        self.counts.reserve(planned.unit_type);
+    }
 
-                    let res = builder_drone.build(to_build, tp);
+    fn step_builder(&mut self, game: &Game, planned: PlannedBuilding) {
+        let mut builder = match self.builders.take(&planned) {
+            Some(builder) => builder,
+            None => return,
+        };
+        let drone = game.get_unit(builder.drone).filter(|d| d.exists());
+        let event = match drone.as_ref() {
+            // zerg buildings are the drone that made them
+            Some(d) if d.get_type().is_building() => BuilderEvent::Started,
+            Some(d) => BuilderEvent::Frame {
+                frame: self.counts.frame(),
+                position: d.get_position(),
+                idle: d.is_idle(),
+                affordable: self.counts.can_afford(builder.building),
+            },
+            None => BuilderEvent::Died,
+        };
+        let mut action = builder.on_event(event, &mut self.builders.bad_tiles);
+        if action == BuilderAction::FindTile {
+            let tile = drone
+                .as_ref()
+                .and_then(|d| self.find_tile(game, planned, d));
+            action = builder.on_event(BuilderEvent::Tile(tile), &mut self.builders.bad_tiles);
+        }
+        if let Some(drone) = drone {
+            match action {
+                BuilderAction::Move(tile) => {
+                    drone.move_((tile + ONE_TILE).to_position()).ok();
+                }
+                BuilderAction::Build(tile) => {
+                    println!("placing a {:?} at {:?}", builder.building, tile);
+                    let res = drone.build(builder.building, tile);
                     if let Ok(true) = res {
-                        self.build
-                            .placed_building(to_build, Some(builder_drone.clone()));
-                        self.drone_builder = None;
+                        if planned.macro_hatch {
+                            self.build.placed_macro_hatch(Some(drone.clone()));
+                        } else {
+                            self.build
+                                .placed_building(builder.building, Some(drone.clone()));
+                        }
                     } else {
                         println!(
                             "placing {:?} failed: {:?} - {:?}",
-                            to_build,
+                            builder.building,
                             res,
-                            builder_drone.get_order()
+                            drone.get_order()
                         );
+                        // only the tile's fault if it's somewhere we can't build,
+                        // otherwise our count of the bank was off
+                        let event = match res {
+                            Err(
+                                Error::Unbuildable_Location
+                                | Error::Invalid_Tile_Position
+                                | Error::Unreachable_Location,
+                            ) => BuilderEvent::Rejected,
+                            _ => BuilderEvent::NotReady,
+                        };
+                        builder.on_event(event, &mut self.builders.bad_tiles);
+                    }
+                }
+                BuilderAction::Release => {
+                    println!(
+                        "frame {} :: giving up on building {:?}",
+                        self.counts.frame(),
+                        builder.building
+                    );
+                    drone.stop().ok();
+                    self.drones.idle(builder.drone);
+                    if planned.macro_hatch {
+                        self.build.macro_hatch_failed();
                     }
-                } else {
-                    builder_drone.move_((tp + ONE_TILE).to_position()).ok();
                 }
+                BuilderAction::FindTile | BuilderAction::Wait => {}
             }
         }
-        // buildings spend when they start, don't spend the building's
-        // money on units in the meantime
-        self.counts.bought(to_build);
+        if let Some(tp) = builder.tile() {
+            game.draw_box_map(
+                tp.to_position(),
+                (tp + builder.building.tile_size()).to_position(),
+                Color::White,
+                false,
+            );
+        }
+        self.builders.put_back(planned, builder);
     }
 
     fn upgrade_building(&mut self, my_units: &Vec<Unit>, to_build: UnitType) {
@@ -123,83 +312,354 @@ impl BotCallbacks {
                 self.build.upgraded_building(to_upgrade.clone(), to_build);
             }
             // set aside money for the upgrade whether it built or not
-            self.counts.bought(to_build);
+            self.counts.reserve(to_build);
         }
     }
 
-    fn send_drone_scount(&mut self, game: &Game) {
+    /// find scouts for the goals without one and move them along
+    fn scout(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        self.scouts.on_frame(game, &self.seen);
         let scout_timing = match self.enemy_race {
             Race::Random => 14,
             _ => 24,
         };
-        // TODO: this supply used number seems to be buggy (too high)
-        if self.counts.supply_used() >= scout_timing && self.drone_scout.is_none() {
-            let drone = self.drones.grab_and_assign(DroneRole::Scout);
-            if let Some(drone_id) = drone {
-                if let Some(drone) = game.get_unit(drone_id) {
-                    println!("assigned a drone scout {:?}", drone);
-                    drone.stop().ok();
-                    let mut scout = Scout::new(drone);
-                    for s in game.get_start_locations() {
-                        if !game.is_visible(s) {
-                            scout.go_later(s);
-                        }
+        for goal in self.scouts.unscouted() {
+            let rescout = self.scouts.is_rescout(goal);
+            let scout = goal.scout_types(rescout).iter().find_map(|t| match *t {
+                UnitType::Zerg_Drone
+                    if self.counts.supply_used() >= scout_timing && self.drone_scout.is_none() =>
+                {
+                    self.drones.grab_and_assign(DroneRole::Scout)
+                }
+                UnitType::Zerg_Drone => None,
+                type_ => my_units
+                    .iter()
+                    .find(|u| {
+                        u.get_type() == type_
+                            && u.is_completed()
+                            && !self.scouts.is_scouting(u.get_id())
+                            && !self.overlords.is_retreating(u.get_id())
+                    })
+                    .map(|u| u.get_id()),
+            });
+            if let Some(scout) = scout {
+                println!(
+                    "frame {} :: scout {} for {:?}",
+                    self.counts.frame(),
+                    scout,
+                    goal
+                );
+                self.scouts.assign(goal, scout);
+            }
+        }
+        for id in self.scouts.scouts() {
+            let unit = match game.get_unit(id).filter(|u| u.exists()) {
+                Some(unit) => unit,
+                None => {
+                    self.scouts.lost(id);
+                    continue;
+                }
+            };
+            let goal = self.scouts.goal_of(id);
+            let next = self
+                .scouts
+                .next_destination(game, &self.seen, id, unit.get_position());
+            match next {
+                Some(dest) => {
+                    unit.move_(dest.to_position()).ok();
+                }
+                None => match self.seen.enemy_main() {
+                    // the drone that found them has a look around before
+                    // coming home
+                    Some(main)
+                        if goal == Some(ScoutGoal::FindMain)
+                            && unit.get_type() == UnitType::Zerg_Drone
+                            && self.drone_scout.is_none() =>
+                    {
+                        let bases: Vec<TilePosition> =
+                            self.seen.get_gas_locs().into_iter().copied().collect();
+                        let map = TilePosition {
+                            x: game.map_width(),
+                            y: game.map_height(),
+                        };
+                        self.drone_scout = Some(DroneScout::new(
+                            id,
+                            unit.get_position(),
+                            main,
+                            natural_base(main, &bases)
+                                .map(|gas| depot_estimate(gas, &self.seen.get_mineral_locs())),
+                            self.start_position,
+                            map,
+                        ));
                     }
-                    for g in self.seen.get_gas_locs() {
-                        if !game.is_visible(*g) {
-                            scout.go_later(*g);
+                    _ => {
+                        unit.stop().ok();
+                        if unit.get_type() == UnitType::Zerg_Drone {
+                            self.drones.idle(id);
                         }
                     }
-                    self.drone_scout = Some(scout);
+                },
+            }
+        }
+        self.micro_drone_scout(game);
+    }
+
+    fn micro_drone_scout(&mut self, game: &Game) {
+        let mut scout = match self.drone_scout.take() {
+            Some(scout) => scout,
+            None => return,
+        };
+        // dead, or it's an extractor now
+        let drone = match game
+            .get_unit(scout.drone)
+            .filter(|d| d.exists() && d.get_type() == UnitType::Zerg_Drone)
+        {
+            Some(drone) => drone,
+            None => return,
+        };
+        let free_geyser = match self.seen.enemy_main() {
+            Some(main) if self.counts.minerals() >= GAS_BLOCK_MINERALS => game
+                .get_geysers()
+                .into_iter()
+                .map(|g| g.get_tile_position())
+                .find(|g| g.chebyshev_distance(main) <= BASE_RADIUS),
+            _ => None,
+        };
+        let enemies = game.enemy().map(|e| e.get_units()).unwrap_or_default();
+        match scout.on_frame(self.counts.frame(), &drone, &enemies, free_geyser) {
+            ScoutAction::Move(to) => {
+                drone.move_(to).ok();
+            }
+            ScoutAction::BlockGas(geyser) => {
+                drone.build(UnitType::Zerg_Extractor, geyser).ok();
+            }
+            ScoutAction::Report(report) => self.seen.record_scout_report(report),
+            ScoutAction::Done => {
+                drone.stop().ok();
+                self.drones.idle(scout.drone);
+                return;
+            }
+        }
+        self.drone_scout = Some(scout);
+    }
+
+    /// overlords out where they can see the enemy coming
+    fn place_overlords(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        let enemy_main = self.seen.likely_enemy_main();
+        let bases: Vec<TilePosition> = self.seen.get_gas_locs().into_iter().copied().collect();
+        let posts = overlord_posts(self.start_position, enemy_main, &bases);
+        let mut overlords: Vec<(UnitId, TilePosition)> = vec![];
+        for u in my_units
+            .iter()
+            .filter(|u| u.get_type() == UnitType::Zerg_Overlord && u.is_completed())
+        {
+            let (id, tile) = (u.get_id(), u.get_tile_position());
+            if self.scouts.is_scouting(id) {
+                if self.threats.air(tile) <= 0.0 {
+                    continue;
                 }
+                // someone else can finish scouting, this one runs home
+                self.scouts.lost(id);
+            }
+            overlords.push((id, tile));
+        }
+        let orders = self.overlords.on_frame(
+            self.counts.frame(),
+            self.start_position,
+            &overlords,
+            &posts,
+            &self.threats,
+        );
+        for order in orders {
+            let (id, tile) = match order {
+                OverlordOrder::Move(id, tile) | OverlordOrder::Retreat(id, tile) => (id, tile),
+            };
+            if let Some(overlord) = game.get_unit(id) {
+                overlord.move_(tile.to_position()).ok();
+            }
+        }
+    }
+
+    /// pull drones together against worker rushes and proxies
+    fn defend_with_drones(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        let bases: Vec<Position> = my_units
+            .iter()
+            .filter(|u| u.get_type().is_successor_of(UnitType::Zerg_Hatchery))
+            .map(|h| h.get_position())
+            .collect();
+        let drones = my_units
+            .iter()
+            .filter(|u| u.get_type() == UnitType::Zerg_Drone && !u.is_morphing());
+        let mut miners = vec![];
+        let mut defenders = vec![];
+        for d in drones {
+            match self.drones.check_role(&d.get_id()) {
+                DroneRole::Minerals | DroneRole::Idle => miners.push(d.clone()),
+                DroneRole::Defending => defenders.push(d.clone()),
+                _ => {}
             }
         }
-        if let Some(scout) = &mut self.drone_scout {
-            if scout.is_done() {
-                if self.drones.count_role(&DroneRole::Scout) > 0 {
-                    self.drones.idle(scout.get_id());
+        let army: Vec<Unit> = my_units
+            .iter()
+            .filter(|u| {
+                let type_ = u.get_type();
+                type_.can_attack() && !type_.is_worker() && !type_.is_building()
+            })
+            .cloned()
+            .collect();
+        let enemies = game.enemy().map(|e| e.get_units()).unwrap_or_default();
+        let orders = self.worker_defense.on_frame(
+            self.counts.frame(),
+            &bases,
+            &miners,
+            &defenders,
+            &enemies,
+            &army,
+        );
+        for order in orders {
+            match order {
+                DefenseOrder::Attack(drone, target) => {
+                    if let (Some(drone), Some(target)) =
+                        (game.get_unit(drone), game.get_unit(target))
+                    {
+                        self.drones.assign(drone.get_id(), DroneRole::Defending);
+                        drone.attack(&target).ok();
+                    }
                 }
-            } else {
-                if scout.is_alive() {
-                    scout.on_frame(game);
-                } else {
-                    // TODO: keep scout's state with new drone
-                    println!("need a new scout");
-                    self.drone_scout = None; // RIP drone, assign a new one
+                DefenseOrder::Release(drone) => {
+                    self.drones.idle(drone);
+                    if let Some(drone) = game.get_unit(drone) {
+                        drone.stop().ok();
+                    }
                 }
             }
         }
     }
 
-    fn check_drones_attacked(&mut self, my_units: &Vec<Unit>) {
+    /// get drones out of trouble they can't fight, back to work once it's
+    /// safe
+    fn check_drones_attacked(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        let lines = self.minerals.mineral_lines();
+        let blocked = self.seen.blocked_tiles();
+        let walkable =
+            |tp: TilePosition| game.is_walkable(tp.to_walk_position()) && !blocked.contains(&tp);
         let drones = my_units
             .iter()
             .filter(|u| u.get_type() == UnitType::Zerg_Drone && !u.is_morphing());
+        let mut paths = None;
         for d in drones {
-            if d.is_attacking() || d.is_starting_attack() {
+            let fleeing = match self.drones.check_role(&d.get_id()) {
+                // worker defense decides when these are done
+                DroneRole::Defending => continue,
+                // the scout knows when to run
+                DroneRole::Scout => continue,
+                DroneRole::Fleeing => true,
+                _ => false,
+            };
+            let in_danger = d.is_under_storm() || d.is_irradiated() || d.is_under_attack();
+            let tile = d.get_tile_position();
+            if fleeing && !in_danger && self.threats.ground(tile) <= 0.0 {
+                println!(
+                    "frame {} :: drone {} is safe",
+                    self.counts.frame(),
+                    d.get_id()
+                );
+                self.drones.idle(d.get_id());
                 continue;
             }
-            let mut run_away = d.is_under_storm() || d.is_irradiated();
-            if !run_away && d.is_under_attack() {
-                let enemy = d
-                    .get_units_in_radius(64, |u: &Unit| u.get_player().get_id() == self.enemy_id)
-                    .into_iter()
-                    .next();
-                if let Some(enemy) = enemy {
-                    // println!("drone {} attacking back", d.get_id());
-                    d.attack(&enemy).ok();
-                    self.drones.assign(d.get_id(), DroneRole::Defending);
-                } else {
-                    run_away = true;
+            // on to the next waypoint once it's reached the last one
+            let run = if fleeing { d.is_idle() } else { in_danger };
+            if !run {
+                continue;
+            }
+            // one search covers every drone that has to run this frame
+            let paths = paths.get_or_insert_with(|| self.threats.flee_paths(&lines, &walkable));
+            match self.threats.flee_order(tile, paths) {
+                FleeOrder::Move(to) => {
+                    d.move_(to.to_position() + Position { x: 16, y: 16 }).ok();
                 }
+                FleeOrder::MineralWalk(patch) => {
+                    if let Some(patch) = game.get_unit(patch) {
+                        d.gather(&patch).ok();
+                    }
+                }
+                FleeOrder::Stay => {}
             }
-            if run_away {
-                // TODO run away better
-                // println!("drone {} running away", d.get_id());
-                d.move_((d.get_tile_position() + ONE_TILE + ONE_TILE).to_position())
-                    .ok();
-                self.drones.assign(d.get_id(), DroneRole::Fleeing);
+            self.drones.assign(d.get_id(), DroneRole::Fleeing);
+        }
+    }
+
+    /// mine out mineral walls in the way of our next bases or the enemy main
+    fn clear_blockers(&mut self, game: &Game) {
+        let mut bases: Vec<TilePosition> = self.seen.get_gas_locs().into_iter().copied().collect();
+        let mut next_bases = vec![];
+        while next_bases.len() < NEXT_BASES {
+            match natural_base(self.start_position, &bases) {
+                Some(base) => {
+                    bases.retain(|b| *b != base);
+                    next_bases.push(base);
+                }
+                None => break,
+            }
+        }
+        let targets: Vec<TilePosition> = self.seen.likely_enemy_main().into_iter().collect();
+        let in_the_way = blockers_in_the_way(
+            &self.seen.blocking_minerals(),
+            self.start_position,
+            &next_bases,
+            &targets,
+        );
+
+        if let Some((drone, blocker)) = self.clearing {
+            let working = self.drones.check_role(&drone) == DroneRole::Clearing
+                && game.get_unit(drone).map_or(false, |d| d.exists());
+            if working && in_the_way.contains(&blocker) {
+                return;
             }
+            if working {
+                self.drones.idle(drone);
+            }
+            self.clearing = None;
+        }
+        if self.drones.count_role(&DroneRole::Minerals) < CLEARING_MINERS {
+            return;
+        }
+        let wall = match in_the_way.first().and_then(|b| game.get_unit(*b)) {
+            Some(wall) => wall,
+            None => return,
+        };
+        if let Some(drone) = self.drones.grab_and_assign(DroneRole::Clearing) {
+            println!(
+                "frame {} :: drone {} mining out {:?} at {:?}",
+                self.counts.frame(),
+                drone,
+                wall.get_type(),
+                wall.get_tile_position()
+            );
+            if let Some(d) = game.get_unit(drone) {
+                d.gather(&wall).ok();
+            }
+            self.clearing = Some((drone, wall.get_id()));
+        }
+    }
+
+    /// who owns each base, drawn on the map
+    fn track_bases(&mut self, game: &Game, my_units: &Vec<Unit>) {
+        let locations = self.seen.base_locations();
+        let ours: Vec<TilePosition> = my_units
+            .iter()
+            .filter(|u| u.get_type().is_successor_of(UnitType::Zerg_Hatchery))
+            .map(|h| h.get_tile_position())
+            .collect();
+        self.bases
+            .update(self.counts.frame(), &locations, &ours, &self.seen, |tile| {
+                game.is_visible(tile)
+            });
+        for base in self.bases.bases() {
+            game.draw_text_map(
+                base.tile.to_position(),
+                &format!("{:?} @{}", base.owner, base.confirmed),
+            );
         }
     }
 
@@ -215,31 +675,83 @@ impl BotCallbacks {
         outermost_hatch.into_iter().next().map(|u| u.clone())
     }
 
+    /// how many drones we want given our bases and the enemy army
+    fn plan_drones(&mut self, my_units: &Vec<Unit>) {
+        let ours: Vec<SimUnit> = my_units
+            .iter()
+            .filter(|u| {
+                let type_ = u.get_type();
+                type_.can_attack() && !type_.is_worker() && !type_.is_building()
+            })
+            .map(|u| SimUnit::from_unit(u))
+            .collect();
+        let theirs: Vec<SimUnit> = self
+            .seen
+            .get_enemy_army()
+            .iter()
+            .map(|su| SimUnit::from_seen(su))
+            .collect();
+        let drones = my_units
+            .iter()
+            .filter(|u| {
+                u.get_type() == UnitType::Zerg_Drone || u.get_build_type() == UnitType::Zerg_Drone
+            })
+            .count();
+        let eco = Economy {
+            patches: self.minerals.owned_patches() as i32,
+            geysers: self.gasses.extractors() as i32,
+            drones: drones as i32,
+            supply_used: self.counts.supply_used(),
+            frame: self.counts.frame(),
+        };
+        let threat = Threat::assess(&theirs, &ours);
+        self.production.set_drone_target(drone_target(&eco, threat));
+    }
+
     // attack the center of the enemy units
     // or if we have enough go attack their base
     // TODO this is a stopgap obviously
     fn do_attacking(&self, game: &Game, my_units: &Vec<Unit>) {
-        // attack with zerglings and mutas
+        // attack with everything that fights
         let army: Vec<&Unit> = my_units
             .iter()
             .filter(|u| {
-                u.get_type() == UnitType::Zerg_Zergling || u.get_type() == UnitType::Zerg_Mutalisk
+                let type_ = u.get_type();
+                type_.can_attack()
+                    && !type_.is_worker()
+                    && !type_.is_building()
+                    && type_ != UnitType::Zerg_Overlord
+                    && u.is_completed()
+                    && !self.scouts.is_scouting(u.get_id())
             })
             .collect();
-        let enemy_units = self.seen.get_enemy_army();
+        let enemy_units = self.seen.get_recent_enemy_army(ARMY_MEMORY_FRAMES);
         let enemy_positions = enemy_units
             .iter()
             .map(|su| su.position.to_tile_position())
             .collect();
-        let enemy = cartesian_center(&enemy_positions).or_else(|| {
-            if army.len() > 8 {
-                self.seen
-                    .get_enemy_building()
-                    .map(|su| su.position.to_tile_position())
-            } else {
-                None
-            }
-        });
+        // only take fights we expect to win
+        let ours: Vec<SimUnit> = army.iter().map(|u| SimUnit::from_unit(u)).collect();
+        let theirs: Vec<SimUnit> = enemy_units
+            .iter()
+            .map(|su| SimUnit::from_seen(su))
+            .collect();
+        let we_win = Matchup::evaluate(&ours, &theirs).we_win();
+        let army_types: Vec<UnitType> = army.iter().map(|u| u.get_type()).collect();
+        let enemy = if we_win {
+            cartesian_center(&enemy_positions).or_else(|| {
+                if army.len() > 8 {
+                    self.bases
+                        .attack_target(self.start_position, &army_types, &self.seen)
+                        .or_else(|| self.seen.likely_enemy_main())
+                } else {
+                    None
+                }
+            })
+        } else {
+            // their army would beat ours wherever we went
+            None
+        };
         if let Some(enemy) = enemy {
             let mut first = true;
             for army in army {
@@ -274,7 +786,11 @@ impl BotCallbacks {
             );
 
             for a in army {
-                if a.is_idle() && a.get_position().chebyshev_distance(rally.to_position()) > 3 {
+                let distance = a.get_position().chebyshev_distance(rally.to_position());
+                if !we_win && distance > RETREAT_DISTANCE && a.get_order() != Order::Move {
+                    // get out of fights we'd lose
+                    a.move_(rally.to_position()).ok();
+                } else if a.is_idle() && distance > 3 {
                     a.attack(rally.to_position()).ok();
                 }
             }
@@ -303,44 +819,83 @@ impl AiModule for BotCallbacks {
         self.seen.on_unit_discover(game, &unit);
     }
 
+    fn on_unit_morph(&mut self, game: &Game, unit: Unit) {
+        self.seen.on_unit_morph(game, &unit);
+    }
+
     fn on_unit_destroy(&mut self, _game: &Game, unit: Unit) {
         self.seen.on_unit_destroy(unit.get_id());
         self.drones.on_unit_destroy(unit.get_id());
+        self.scouts.lost(unit.get_id());
     }
 
     fn on_frame(&mut self, game: &Game) {
         self.seen.on_frame(game);
+        self.threats = ThreatMap::from_seen(game, &self.seen);
         self.build.on_frame(game);
-        self.counts = Counts::new(game, &self.build);
+        self.income.on_frame(game, &self.drones);
+        self.counts = Counts::new(game, &self.build, &self.income);
+        let strategy = recognize(&self.seen.building_timeline());
+        if strategy.opening != self.strategy.opening {
+            println!(
+                "frame {} :: enemy opening looks like {:?}",
+                self.counts.frame(),
+                strategy
+            );
+        }
+        self.strategy = strategy;
+        self.build.against(&self.strategy);
         self.drones.on_frame(game);
         self.drones.print_stats(self.counts.frame());
-        self.gasses.on_frame(game, &self.counts, &mut self.drones);
-        self.minerals.on_frame(game, &self.counts, &mut self.drones);
+        let gas_needed = self.build.upcoming_gas(&self.counts)
+            + self.research.upcoming_gas()
+            + self.composition.current().gas_per_set();
+        self.gasses
+            .on_frame(game, self.counts.gas(), gas_needed, &mut self.drones);
+        let threats = &self.threats;
+        let long_distance = self
+            .bases
+            .long_distance_base(self.start_position, |tile| threats.ground(tile) <= 0.0);
+        self.minerals
+            .on_frame(game, &mut self.drones, long_distance);
         self.build.release_drones(&mut self.drones);
 
         let self_ = game.self_().unwrap();
         let my_units = self_.get_units();
 
-        // place our next building
-        match self.build.get_next_building(game, &self.counts) {
-            Some(
-                to_build @ (UnitType::Zerg_Lair
-                | UnitType::Zerg_Hive
-                | UnitType::Zerg_Greater_Spire),
-            ) => self.upgrade_building(&my_units, to_build),
-            Some(to_build) if to_build.is_building() => self.build_new_building(game, to_build),
-            Some(not_a_building) => {
-                println!("saw non-building {:?} from build order", not_a_building)
-            }
-            None => {}
-        }
+        self.track_bases(game, &my_units);
+        self.place_buildings(game, &my_units);
 
+        self.research.on_frame(game, &mut self.counts);
+        self.composition
+            .on_frame(self.counts.frame(), &self.seen, game);
+        // drones where they're needed, the army at the front
+        let drone_rally = self.minerals.least_saturated_base();
+        let army_rally = self
+            .get_outermost_hatch(&my_units)
+            .map(|h| h.get_tile_position());
+        self.larva.on_frame(game);
+        self.larva.set_rallies(drone_rally, army_rally);
+        if self.larva.is_starved(self.counts.minerals()) {
+            self.build.request_macro_hatch();
+        }
+        self.plan_drones(&my_units);
         let mut comp = UnitComp::new(game);
-        comp.spawn_units(game, &mut self.counts, &self.seen);
+        comp.spawn_units(
+            game,
+            &mut self.counts,
+            &mut self.larva,
+            &self.production,
+            self.composition.current(),
+            &self.strategy,
+        );
 
         self.do_attacking(game, &my_units);
-        self.send_drone_scount(game);
-        self.check_drones_attacked(&my_units);
+        self.scout(game, &my_units);
+        self.place_overlords(game, &my_units);
+        self.defend_with_drones(game, &my_units);
+        self.check_drones_attacked(game, &my_units);
+        self.clear_blockers(game);
 
         // makes games watchable
         if self.counts.supply_used() > 20 {
@@ -1,7 +1,14 @@
-use crate::{counts::Counts, drones::DroneManager};
+use crate::{counts::Counts, drones::DroneManager, strategy::Strategy};
 use rsbwapi::*;
 use std::collections::{HashMap, HashSet};
 
+// steps this much supply ahead of us count towards the gas we need
+const UPCOMING_SUPPLY: i32 = 8;
+// cancel a trick extractor by now even if we didn't use the supply
+const TRICK_FRAMES: i32 = 24 * 10;
+// a macro hatch for every base we have at most
+const MACRO_HATCHES_PER_BASE: i8 = 1;
+
 pub(crate) trait TechChecker {
     fn has_prereqs(&self, unit_type: &UnitType) -> bool;
 }
@@ -9,21 +16,30 @@ pub(crate) trait TechChecker {
 impl TechChecker for &Game {
     fn has_prereqs(&self, unit_type: &UnitType) -> bool {
         if let Some(self_) = self.self_() {
-            unit_type
-                .required_units()
-                .into_iter()
-                .all(|(unit, amount)| self_.has_unit_type_requirement(*unit, *amount))
+            (&self_).has_prereqs(unit_type)
         } else {
             false
         }
     }
 }
 
+impl TechChecker for &Player {
+    fn has_prereqs(&self, unit_type: &UnitType) -> bool {
+        let tech = unit_type.required_tech();
+        unit_type
+            .required_units()
+            .into_iter()
+            .all(|(unit, amount)| self.has_unit_type_requirement(*unit, *amount))
+            && (tech == TechType::None || self.has_researched(tech))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct BuildStep {
     unit_type: UnitType,
     min_supply: i32,
     building_type_count: i8,
+    trick: bool,
 }
 
 impl BuildStep {
@@ -32,8 +48,43 @@ impl BuildStep {
             unit_type,
             min_supply,
             building_type_count,
+            trick: false,
         }
     }
+
+    /// start an extractor while supply blocked, make a unit with the drone's
+    /// supply then cancel the extractor to get the drone back
+    fn extractor_trick(min_supply: i32) -> Self {
+        BuildStep {
+            unit_type: UnitType::Zerg_Extractor,
+            min_supply,
+            building_type_count: 0,
+            trick: true,
+        }
+    }
+}
+
+/// Where we are with the extractor trick
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Trick {
+    Waiting,
+    Placed,
+    Started {
+        extractor: UnitId,
+        frame: i32,
+        supply: Option<i32>,
+    },
+    Cancelled(UnitId),
+    Done,
+}
+
+/// The nth building of a type, what a builder is placing
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub(crate) struct PlannedBuilding {
+    pub unit_type: UnitType,
+    pub nth: i8,
+    /// an extra hatchery in the main rather than a new base
+    pub macro_hatch: bool,
 }
 
 #[derive(Debug)]
@@ -41,6 +92,21 @@ struct PlacedBuilding {
     placed_frame: i32,
     building_type: UnitType,
     builder: Option<Unit>,
+    trick: bool,
+    macro_hatch: bool,
+    /// cancelled, the money's coming back rather than going out
+    refund: bool,
+}
+
+impl PlacedBuilding {
+    /// what it costs us, or gives back once cancelled
+    fn price(&self, price: i32) -> i32 {
+        if self.refund {
+            -price * 3 / 4
+        } else {
+            price
+        }
+    }
 }
 
 pub struct BuildOrder {
@@ -50,6 +116,11 @@ pub struct BuildOrder {
     placed_buildings: Vec<PlacedBuilding>,
     building_ids: HashSet<usize>,
     stuck_drones: Vec<UnitId>,
+    macro_hatches: i8,
+    macro_hatch_wanted: bool,
+    // hatcheries still morphing, new ones don't help with larva until done
+    hatcheries_morphing: usize,
+    trick: Trick,
 }
 
 impl BuildOrder {
@@ -58,6 +129,7 @@ impl BuildOrder {
             frame: 0,
             to_build: vec![
                 // NB: these supplies written like they'd show up in build orders
+                BuildStep::extractor_trick(9),
                 BuildStep::new(UnitType::Zerg_Hatchery, 11, 2),
                 BuildStep::new(UnitType::Zerg_Spawning_Pool, 10, 1),
                 BuildStep::new(UnitType::Zerg_Extractor, 9, 1),
@@ -77,12 +149,24 @@ impl BuildOrder {
             placed_buildings: vec![],
             building_ids: HashSet::new(),
             stuck_drones: vec![],
+            macro_hatches: 0,
+            macro_hatch_wanted: false,
+            hatcheries_morphing: 0,
+            trick: Trick::Waiting,
         }
     }
 
     pub fn on_frame(&mut self, game: &Game) {
         self.frame = game.get_frame_count();
         if let Some(self_) = game.self_() {
+            self.hatcheries_morphing = self_
+                .get_units()
+                .iter()
+                .filter(|u| {
+                    (u.get_type() == UnitType::Zerg_Hatchery && !u.is_completed())
+                        || (u.is_morphing() && u.get_build_type() == UnitType::Zerg_Hatchery)
+                })
+                .count();
             let buildings = self_
                 .get_units()
                 .into_iter()
@@ -99,6 +183,69 @@ impl BuildOrder {
                 })
                 .collect();
             self.check_placed_buildings(buildings);
+            if let Some(extractor) = self.update_trick(self_.supply_used()) {
+                if let Some(extractor) = game.get_unit(extractor) {
+                    if let Ok(true) = extractor.cancel_construction() {
+                        println!("frame {} :: cancelling trick extractor", self.frame);
+                        self.cancelled_trick();
+                    }
+                }
+            }
+        }
+    }
+
+    /// The extractor to cancel once the supply it freed up has been used
+    fn update_trick(&mut self, supply_used: i32) -> Option<UnitId> {
+        let window = self
+            .to_build
+            .iter()
+            .find(|step| step.trick)
+            .map(|step| 2 * step.min_supply);
+        match self.trick {
+            // only worth doing when we're blocked at exactly this supply
+            Trick::Waiting if window.map_or(true, |w| supply_used > w) => {
+                self.trick = Trick::Done;
+                None
+            }
+            Trick::Started {
+                extractor,
+                frame,
+                supply: None,
+            } => {
+                self.trick = Trick::Started {
+                    extractor,
+                    frame,
+                    supply: Some(supply_used),
+                };
+                None
+            }
+            Trick::Started {
+                extractor,
+                frame,
+                supply: Some(before),
+            } if supply_used > before || self.frame - frame > TRICK_FRAMES => Some(extractor),
+            _ => None,
+        }
+    }
+
+    fn cancelled_trick(&mut self) {
+        if let Trick::Started { extractor, .. } = self.trick {
+            self.trick = Trick::Cancelled(extractor);
+            self.placed_buildings.push(PlacedBuilding {
+                placed_frame: self.frame,
+                building_type: UnitType::Zerg_Extractor,
+                builder: None,
+                trick: true,
+                macro_hatch: false,
+                refund: true,
+            });
+        }
+    }
+
+    fn is_trick_extractor(&self, id: UnitId) -> bool {
+        match self.trick {
+            Trick::Started { extractor, .. } | Trick::Cancelled(extractor) => extractor == id,
+            _ => false,
         }
     }
 
@@ -109,25 +256,75 @@ impl BuildOrder {
         self.stuck_drones.clear();
     }
 
-    pub fn get_next_building(&self, tech: impl TechChecker, counts: &Counts) -> Option<UnitType> {
+    /// The buildings that are due in build order, each one a separate
+    /// placement so several can go down at once
+    pub fn next_buildings(&self, tech: impl TechChecker, counts: &Counts) -> Vec<PlannedBuilding> {
+        let count = |unit_type: &UnitType| *self.building_counts.get(unit_type).unwrap_or(&0);
         let supply_used = counts.supply_used();
+        let mut planned: Vec<PlannedBuilding> = vec![];
+        let mut trick = None;
         for step in self.to_build.iter() {
-            let count = self.building_counts.get(&step.unit_type).unwrap_or(&0);
-            if *count < step.building_type_count {
-                // remember that BW doubles supplies
-                if supply_used >= 2 * step.min_supply {
-                    if !tech.has_prereqs(&step.unit_type) {
-                        return None;
-                    }
-                    return Some(step.unit_type.clone());
-                } else if counts.minerals() > 1_000 {
-                    return Some(UnitType::Zerg_Hatchery);
-                } else {
-                    return None;
+            if step.trick {
+                // the trick extractor isn't one we keep, it's never the nth
+                if self.trick == Trick::Waiting && supply_used == 2 * step.min_supply {
+                    trick = Some(PlannedBuilding {
+                        unit_type: step.unit_type,
+                        nth: 0,
+                        macro_hatch: false,
+                    });
                 }
+                continue;
+            }
+            // macro hatches don't count towards the hatcheries we expand to
+            let wanted = match step.unit_type {
+                UnitType::Zerg_Hatchery => step.building_type_count + self.macro_hatches,
+                _ => step.building_type_count,
+            };
+            // earlier steps may already be placing some of this type
+            let mut have = count(&step.unit_type)
+                + planned
+                    .iter()
+                    .filter(|p| p.unit_type == step.unit_type)
+                    .count() as i8;
+            if have >= wanted {
+                continue;
+            }
+            // remember that BW doubles supplies
+            if supply_used < 2 * step.min_supply {
+                if counts.minerals() > 1_000 {
+                    planned.push(PlannedBuilding {
+                        unit_type: UnitType::Zerg_Hatchery,
+                        nth: count(&UnitType::Zerg_Hatchery) + 1,
+                        macro_hatch: false,
+                    });
+                }
+                break;
+            }
+            if !tech.has_prereqs(&step.unit_type) {
+                break;
+            }
+            while have < wanted {
+                have += 1;
+                planned.push(PlannedBuilding {
+                    unit_type: step.unit_type,
+                    nth: have,
+                    macro_hatch: false,
+                });
             }
         }
-        None
+        // on top of whatever else is due
+        if self.macro_hatch_wanted {
+            let hatcheries = planned
+                .iter()
+                .filter(|p| p.unit_type == UnitType::Zerg_Hatchery)
+                .count() as i8;
+            planned.push(PlannedBuilding {
+                unit_type: UnitType::Zerg_Hatchery,
+                nth: count(&UnitType::Zerg_Hatchery) + hatcheries + 1,
+                macro_hatch: true,
+            });
+        }
+        trick.into_iter().chain(planned).collect()
     }
 
     /**
@@ -135,12 +332,100 @@ impl BuildOrder {
      * started morphing yet
      */
     pub fn placed_building(&mut self, building_type: UnitType, builder: Option<Unit>) {
+        self.place(building_type, builder, false);
+    }
+
+    pub fn placed_macro_hatch(&mut self, builder: Option<Unit>) {
+        self.macro_hatch_wanted = false;
+        self.macro_hatches += 1;
+        self.place(UnitType::Zerg_Hatchery, builder, true);
+    }
+
+    fn place(&mut self, building_type: UnitType, builder: Option<Unit>, macro_hatch: bool) {
+        // the trick comes first so the first extractor we place is for it
+        let trick = building_type == UnitType::Zerg_Extractor && self.trick == Trick::Waiting;
+        if trick {
+            self.trick = Trick::Placed;
+        }
         self.placed_buildings.push(PlacedBuilding {
             building_type,
             builder,
             placed_frame: self.frame,
+            trick,
+            macro_hatch,
+            refund: false,
         });
-        self.count_type(building_type);
+        if !trick {
+            self.count_type(building_type);
+        }
+    }
+
+    /// an extra hatchery in the main for larva rather than at a new base,
+    /// one at a time and only so many per base
+    pub fn request_macro_hatch(&mut self) {
+        let hatcheries = *self
+            .building_counts
+            .get(&UnitType::Zerg_Hatchery)
+            .unwrap_or(&0);
+        let bases = hatcheries - self.macro_hatches;
+        let building = self.hatcheries_morphing > 0
+            || self
+                .placed_buildings
+                .iter()
+                .any(|pb| pb.building_type == UnitType::Zerg_Hatchery);
+        if self.macro_hatch_wanted
+            || building
+            || self.macro_hatches >= bases * MACRO_HATCHES_PER_BASE
+        {
+            return;
+        }
+        println!("frame {} :: requesting a macro hatch", self.frame);
+        self.macro_hatch_wanted = true;
+    }
+
+    /// nowhere to put the macro hatch, ask again when we're starved again
+    pub fn macro_hatch_failed(&mut self) {
+        if self.macro_hatch_wanted {
+            println!("frame {} :: couldn't place a macro hatch", self.frame);
+            self.macro_hatch_wanted = false;
+        }
+    }
+
+    /// Against a rush there's no time for the extractor trick and the pool
+    /// goes down before the natural
+    pub fn against(&mut self, strategy: &Strategy) {
+        if !strategy.is_rush() {
+            return;
+        }
+        if self.trick == Trick::Waiting {
+            println!(
+                "frame {} :: no extractor trick against {:?}",
+                self.frame, strategy.opening
+            );
+            self.trick = Trick::Done;
+        }
+        let find = |unit_type: UnitType| {
+            self.to_build
+                .iter()
+                .position(|step| step.unit_type == unit_type && !step.trick)
+        };
+        let pool_placed = self
+            .building_counts
+            .contains_key(&UnitType::Zerg_Spawning_Pool);
+        match (
+            find(UnitType::Zerg_Spawning_Pool),
+            find(UnitType::Zerg_Hatchery),
+        ) {
+            (Some(pool), Some(hatch)) if pool > hatch && !pool_placed => {
+                println!(
+                    "frame {} :: pool first against {:?}",
+                    self.frame, strategy.opening
+                );
+                let step = self.to_build.remove(pool);
+                self.to_build.insert(hatch, step);
+            }
+            _ => {}
+        }
     }
 
     pub fn upgraded_building(&mut self, building: Unit, building_type: UnitType) {
@@ -158,9 +443,24 @@ impl BuildOrder {
     // so we don't double-count their cost
     fn check_placed_buildings(&mut self, buildings: Vec<(UnitId, UnitType)>) {
         self.building_counts.clear();
+        let ids: HashSet<UnitId> = buildings.iter().map(|(id, _)| *id).collect();
 
         for (id, bt) in buildings {
-            self.count_type(bt);
+            if bt == UnitType::Zerg_Extractor
+                && self.trick == Trick::Placed
+                && !self.building_ids.contains(&id)
+            {
+                println!("frame {} :: trick extractor started", self.frame);
+                self.trick = Trick::Started {
+                    extractor: id,
+                    frame: self.frame,
+                    supply: None,
+                };
+            }
+            // the trick extractor isn't one we're keeping
+            if !self.is_trick_extractor(id) {
+                self.count_type(bt);
+            }
             if bt == UnitType::Zerg_Lair || bt == UnitType::Zerg_Hive {
                 self.count_type(UnitType::Zerg_Hatchery);
             }
@@ -177,7 +477,7 @@ impl BuildOrder {
                 let index = self
                     .placed_buildings
                     .iter()
-                    .position(|t| t.building_type == bt);
+                    .position(|t| t.building_type == bt && !t.refund);
                 if let Some(index) = index {
                     let pb = self.placed_buildings.swap_remove(index);
                     println!(
@@ -196,9 +496,17 @@ impl BuildOrder {
                         "frame {} :: {:?} failed to build, builder died",
                         self.frame, pb.building_type
                     );
+                    if pb.macro_hatch {
+                        self.macro_hatches -= 1;
+                    }
+                    // free to try the trick again
+                    if pb.trick && self.trick == Trick::Placed {
+                        self.trick = Trick::Waiting;
+                    }
                     return false;
                 }
                 // TODO: similar check for building upgrades if we see them fail?
+                // the builder tries somewhere else, it's not placed anymore
                 if self.frame > pb.placed_frame + 10 && builder.is_idle() {
                     println!(
                         "frame {} :: {:?} has failed to build after {} frames",
@@ -206,7 +514,13 @@ impl BuildOrder {
                         pb.building_type,
                         self.frame - pb.placed_frame
                     );
-                    self.stuck_drones.push(builder.get_id());
+                    if pb.macro_hatch {
+                        self.macro_hatches -= 1;
+                    }
+                    // free to try the trick again
+                    if pb.trick && self.trick == Trick::Placed {
+                        self.trick = Trick::Waiting;
+                    }
                     return false;
                 }
                 if builder.get_type().is_building()
@@ -226,9 +540,19 @@ impl BuildOrder {
             true
         });
 
+        // the drone comes back once the trick extractor is gone
+        if let Trick::Started { extractor, .. } | Trick::Cancelled(extractor) = self.trick {
+            if !ids.contains(&extractor) {
+                self.trick = Trick::Done;
+                // the game's given the money back
+                self.placed_buildings.retain(|pb| !pb.refund);
+                self.stuck_drones.push(extractor);
+            }
+        }
+
         // count placed buildings in our builder too
         // replacing this with the method angers the borrow checker :-(
-        for pb in self.placed_buildings.iter() {
+        for pb in self.placed_buildings.iter().filter(|pb| !pb.trick) {
             self.building_counts
                 .entry(pb.building_type)
                 .and_modify(|c| *c += 1)
@@ -236,17 +560,30 @@ impl BuildOrder {
         }
     }
 
+    /// gas for the steps we'll get to soon
+    pub fn upcoming_gas(&self, counts: &Counts) -> i32 {
+        self.to_build
+            .iter()
+            .filter(|step| {
+                let count = self.building_counts.get(&step.unit_type).unwrap_or(&0);
+                *count < step.building_type_count
+                    && 2 * step.min_supply <= counts.supply_used() + UPCOMING_SUPPLY
+            })
+            .map(|step| step.unit_type.gas_price())
+            .sum()
+    }
+
     pub fn spent_minerals(&self) -> i32 {
         self.placed_buildings
             .iter()
-            .map(|b| b.building_type.mineral_price())
+            .map(|b| b.price(b.building_type.mineral_price()))
             .sum()
     }
 
     pub fn spent_gas(&self) -> i32 {
         self.placed_buildings
             .iter()
-            .map(|b| b.building_type.gas_price())
+            .map(|b| b.price(b.building_type.gas_price()))
             .sum()
     }
 }
@@ -254,12 +591,11 @@ impl BuildOrder {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::strategy::Opening;
+    use crate::test_util::AllTech;
 
-    struct AllTech;
-    impl TechChecker for &AllTech {
-        fn has_prereqs(&self, _unit_type: &UnitType) -> bool {
-            true
-        }
+    fn next(bo: &BuildOrder, tech: &AllTech, counts: &Counts) -> Option<UnitType> {
+        bo.next_buildings(tech, counts).first().map(|p| p.unit_type)
     }
 
     #[test]
@@ -270,21 +606,17 @@ mod test {
         bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
 
         let c = Counts::new_fake(8);
-        assert_eq!(
-            bo.get_next_building(&tech, &c),
-            None,
-            "saw building too early"
-        );
+        assert_eq!(next(&bo, &tech, &c), None, "saw building too early");
         let c = Counts::new_fake(22);
         assert_eq!(
-            bo.get_next_building(&tech, &c),
+            next(&bo, &tech, &c),
             Some(UnitType::Zerg_Hatchery),
             "got hatch first"
         );
         // no-op to place a building not in the order
         bo.placed_building(UnitType::Terran_Barracks, None);
         assert_eq!(
-            bo.get_next_building(&tech, &c),
+            next(&bo, &tech, &c),
             Some(UnitType::Zerg_Hatchery),
             "still got hatch"
         );
@@ -296,12 +628,150 @@ mod test {
 
         bo.placed_building(UnitType::Zerg_Hatchery, None);
         assert_eq!(
-            bo.get_next_building(&tech, &c),
+            next(&bo, &tech, &c),
             Some(UnitType::Zerg_Spawning_Pool),
             "pool after hatch"
         );
     }
 
+    #[test]
+    fn test_parallel_buildings() {
+        let tech = AllTech {};
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        let planned = |unit_type, nth| PlannedBuilding {
+            unit_type,
+            nth,
+            macro_hatch: false,
+        };
+        let c = Counts::new_fake(22);
+        assert_eq!(
+            bo.next_buildings(&tech, &c),
+            vec![
+                planned(UnitType::Zerg_Hatchery, 2),
+                planned(UnitType::Zerg_Spawning_Pool, 1),
+                planned(UnitType::Zerg_Extractor, 1),
+            ],
+            "everything we're late on at once"
+        );
+
+        // too late for the trick, the extractor is a real one
+        bo.update_trick(22);
+        bo.placed_building(UnitType::Zerg_Hatchery, None);
+        bo.placed_building(UnitType::Zerg_Spawning_Pool, None);
+        bo.placed_building(UnitType::Zerg_Extractor, None);
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        let c = Counts::new_fake(100);
+        assert_eq!(
+            bo.next_buildings(&tech, &c)[..2],
+            [
+                planned(UnitType::Zerg_Lair, 1),
+                planned(UnitType::Zerg_Extractor, 2)
+            ],
+            "placed buildings count"
+        );
+        let hatcheries: Vec<i8> = bo
+            .next_buildings(&tech, &c)
+            .into_iter()
+            .filter(|p| p.unit_type == UnitType::Zerg_Hatchery)
+            .map(|p| p.nth)
+            .collect();
+        assert_eq!(hatcheries, vec![3, 4], "the third and fourth hatcheries");
+    }
+
+    #[test]
+    fn test_against_rush() {
+        let tech = AllTech {};
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        let c = Counts::new_fake(20);
+        assert_eq!(next(&bo, &tech, &c), None, "hatch first");
+
+        bo.against(&Strategy::unknown());
+        assert_eq!(next(&bo, &tech, &c), None, "nothing to worry about");
+
+        let nine_pool = Strategy {
+            opening: Opening::NinePool,
+            confidence: 1.0,
+        };
+        bo.against(&nine_pool);
+        assert_eq!(bo.trick, Trick::Done);
+        assert_eq!(next(&bo, &tech, &c), Some(UnitType::Zerg_Spawning_Pool));
+
+        bo.placed_building(UnitType::Zerg_Spawning_Pool, None);
+        bo.against(&nine_pool);
+        let c = Counts::new_fake(22);
+        assert_eq!(
+            next(&bo, &tech, &c),
+            Some(UnitType::Zerg_Hatchery),
+            "then the natural"
+        );
+    }
+
+    #[test]
+    fn test_macro_hatch() {
+        let tech = AllTech {};
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        let c = Counts::new_fake(8);
+        assert_eq!(next(&bo, &tech, &c), None, "too early");
+
+        bo.request_macro_hatch();
+        assert!(bo.macro_hatch_wanted);
+        assert_eq!(
+            bo.next_buildings(&tech, &c),
+            vec![PlannedBuilding {
+                unit_type: UnitType::Zerg_Hatchery,
+                nth: 2,
+                macro_hatch: true,
+            }],
+            "macro hatch right away"
+        );
+        let c = Counts::new_fake(22);
+        let planned: Vec<(UnitType, bool)> = bo
+            .next_buildings(&tech, &c)
+            .iter()
+            .map(|p| (p.unit_type, p.macro_hatch))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                (UnitType::Zerg_Hatchery, false),
+                (UnitType::Zerg_Spawning_Pool, false),
+                (UnitType::Zerg_Extractor, false),
+                (UnitType::Zerg_Hatchery, true),
+            ],
+            "the rest of the build carries on"
+        );
+        bo.placed_macro_hatch(None);
+        assert!(!bo.macro_hatch_wanted, "macro hatch placed");
+
+        assert_eq!(
+            next(&bo, &tech, &c),
+            Some(UnitType::Zerg_Hatchery),
+            "macro hatch doesn't replace the expansion"
+        );
+
+        bo.request_macro_hatch();
+        assert!(!bo.macro_hatch_wanted, "one at a time");
+        bo.check_placed_buildings(vec![
+            (10, UnitType::Zerg_Hatchery),
+            (11, UnitType::Zerg_Hatchery),
+        ]);
+        bo.request_macro_hatch();
+        assert!(!bo.macro_hatch_wanted, "one per base");
+
+        bo.check_placed_buildings(vec![
+            (10, UnitType::Zerg_Hatchery),
+            (11, UnitType::Zerg_Hatchery),
+            (12, UnitType::Zerg_Hatchery),
+        ]);
+        bo.request_macro_hatch();
+        assert!(bo.macro_hatch_wanted, "we expanded");
+        bo.macro_hatch_failed();
+        assert!(!bo.macro_hatch_wanted, "ask again next time");
+    }
+
     #[test]
     fn test_spent_resources() {
         let mut bo = BuildOrder::new();
@@ -309,4 +779,125 @@ mod test {
         assert_eq!(bo.spent_minerals(), UnitType::Zerg_Spire.mineral_price());
         assert_eq!(bo.spent_gas(), UnitType::Zerg_Spire.gas_price());
     }
+
+    #[test]
+    fn test_upcoming_gas() {
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![
+            (10, UnitType::Zerg_Hatchery),
+            (11, UnitType::Zerg_Hatchery),
+            (12, UnitType::Zerg_Spawning_Pool),
+            (13, UnitType::Zerg_Extractor),
+        ]);
+        assert_eq!(
+            bo.upcoming_gas(&Counts::new_fake(20)),
+            0,
+            "lair's a while off"
+        );
+        assert_eq!(
+            bo.upcoming_gas(&Counts::new_fake(24)),
+            UnitType::Zerg_Lair.gas_price() + UnitType::Zerg_Spire.gas_price(),
+            "lair and spire coming up"
+        );
+    }
+
+    #[test]
+    fn test_extractor_trick() {
+        let tech = AllTech {};
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        assert_eq!(bo.update_trick(16), None);
+        assert_eq!(next(&bo, &tech, &Counts::new_fake(16)), None);
+        let rich = Counts::new_fake_bank(1_200, 0, 16, 18);
+        assert_eq!(
+            next(&bo, &tech, &rich),
+            Some(UnitType::Zerg_Hatchery),
+            "waiting for the trick doesn't hold anything up"
+        );
+
+        let blocked = Counts::new_fake(18);
+        assert_eq!(
+            next(&bo, &tech, &blocked),
+            Some(UnitType::Zerg_Extractor),
+            "trick when supply blocked at 9"
+        );
+        bo.placed_building(UnitType::Zerg_Extractor, None);
+        assert_eq!(bo.trick, Trick::Placed);
+        assert_eq!(
+            bo.spent_minerals(),
+            UnitType::Zerg_Extractor.mineral_price()
+        );
+        assert_eq!(
+            next(&bo, &tech, &blocked),
+            None,
+            "nothing else until the hatch"
+        );
+
+        // the drone starts morphing
+        let with_extractor = vec![
+            (10, UnitType::Zerg_Hatchery),
+            (20, UnitType::Zerg_Extractor),
+        ];
+        bo.check_placed_buildings(with_extractor.clone());
+        assert_eq!(bo.spent_minerals(), 0, "paid for once it's started");
+        assert_eq!(
+            bo.building_counts.get(&UnitType::Zerg_Extractor),
+            None,
+            "trick extractor doesn't count"
+        );
+        assert_eq!(bo.update_trick(16), None, "drone's supply is free");
+        bo.check_placed_buildings(with_extractor);
+        assert_eq!(bo.update_trick(16), None, "haven't used it yet");
+        assert_eq!(bo.update_trick(18), Some(20), "made a unit, cancel");
+
+        bo.cancelled_trick();
+        assert_eq!(
+            bo.spent_minerals(),
+            -UnitType::Zerg_Extractor.mineral_price() * 3 / 4,
+            "refund on the way"
+        );
+
+        // a real extractor starts before the refund comes in
+        bo.placed_building(UnitType::Zerg_Extractor, None);
+        bo.check_placed_buildings(vec![
+            (10, UnitType::Zerg_Hatchery),
+            (20, UnitType::Zerg_Extractor),
+            (30, UnitType::Zerg_Extractor),
+        ]);
+        assert_eq!(
+            bo.spent_minerals(),
+            -UnitType::Zerg_Extractor.mineral_price() * 3 / 4,
+            "still on the way"
+        );
+        bo.check_placed_buildings(vec![
+            (10, UnitType::Zerg_Hatchery),
+            (30, UnitType::Zerg_Extractor),
+        ]);
+        assert_eq!(bo.trick, Trick::Done);
+        assert_eq!(bo.spent_minerals(), 0, "refund arrived");
+        assert_eq!(bo.stuck_drones, vec![20], "drone goes back to work");
+        assert_eq!(
+            next(&bo, &tech, &Counts::new_fake(22)),
+            Some(UnitType::Zerg_Hatchery)
+        );
+    }
+
+    #[test]
+    fn test_extractor_trick_missed() {
+        let tech = AllTech {};
+        let mut bo = BuildOrder::new();
+        bo.check_placed_buildings(vec![(10, UnitType::Zerg_Hatchery)]);
+        assert_eq!(bo.update_trick(20), None);
+        assert_eq!(bo.trick, Trick::Done, "too late for the trick");
+        bo.placed_building(UnitType::Zerg_Extractor, None);
+        assert_eq!(
+            bo.building_counts.get(&UnitType::Zerg_Extractor),
+            Some(&1),
+            "a real extractor"
+        );
+        assert_eq!(
+            next(&bo, &tech, &Counts::new_fake(22)),
+            Some(UnitType::Zerg_Hatchery)
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use crate::seen::HaveSeen;
 use rsbwapi::*;
+use std::collections::HashSet;
 
 trait CanBuild {
     fn can_build_at(&self, loc: TilePosition) -> bool;
@@ -15,13 +16,28 @@ struct GameCanBuild<'a> {
     game: &'a Game,
     builder: &'a Unit,
     building_type: UnitType,
+    // tiles we failed to build on before
+    blacklist: &'a HashSet<TilePosition>,
+    // tiles neutral buildings and mineral walls are on, seen or not
+    blocked: HashSet<TilePosition>,
+}
+
+impl<'a> GameCanBuild<'a> {
+    fn covers_blocked(&self, loc: TilePosition) -> bool {
+        let size = self.building_type.tile_size();
+        (0..size.y)
+            .any(|y| (0..size.x).any(|x| self.blocked.contains(&(loc + TilePosition { x, y }))))
+    }
 }
 
 impl<'a> CanBuild for GameCanBuild<'a> {
     fn can_build_at(&self, loc: TilePosition) -> bool {
-        self.game
-            .can_build_here(self.builder, loc, self.building_type, false)
-            .unwrap_or(false)
+        !self.blacklist.contains(&loc)
+            && !self.covers_blocked(loc)
+            && self
+                .game
+                .can_build_here(self.builder, loc, self.building_type, false)
+                .unwrap_or(false)
     }
 
     fn bounds(&self) -> (TilePosition, TilePosition) {
@@ -52,20 +68,45 @@ pub fn position_building(
     bt: UnitType,
     builder: &Unit,
     seen: &HaveSeen,
+    blacklist: &HashSet<TilePosition>,
 ) -> Option<TilePosition> {
     let checker = GameCanBuild {
         game,
         builder,
         building_type: bt,
+        blacklist,
+        blocked: seen.blocked_tiles(),
     };
     match bt {
-        UnitType::Zerg_Hatchery => position_new_base(game, builder, seen),
+        UnitType::Zerg_Hatchery => position_new_base(game, builder, seen, blacklist),
         _ if bt.is_building() => position_near_hatch(game, &checker),
         _ => None,
     }
 }
 
-fn position_new_base(game: &Game, builder: &Unit, seen: &HaveSeen) -> Option<TilePosition> {
+/// a hatchery next to our existing ones for the extra larva
+pub fn position_macro_hatch(
+    game: &Game,
+    builder: &Unit,
+    seen: &HaveSeen,
+    blacklist: &HashSet<TilePosition>,
+) -> Option<TilePosition> {
+    let checker = GameCanBuild {
+        game,
+        builder,
+        building_type: UnitType::Zerg_Hatchery,
+        blacklist,
+        blocked: seen.blocked_tiles(),
+    };
+    position_near_hatch(game, &checker)
+}
+
+fn position_new_base(
+    game: &Game,
+    builder: &Unit,
+    seen: &HaveSeen,
+    blacklist: &HashSet<TilePosition>,
+) -> Option<TilePosition> {
     let hatches = get_hatches(game);
     let bt = UnitType::Zerg_Hatchery;
     // sort geysers by how far they are from our hatcheries
@@ -88,6 +129,8 @@ fn position_new_base(game: &Game, builder: &Unit, seen: &HaveSeen) -> Option<Til
         game,
         builder,
         building_type: bt,
+        blacklist,
+        blocked: seen.blocked_tiles(),
     };
 
     let mineral_locs = seen.get_mineral_locs();
@@ -97,17 +140,9 @@ fn position_new_base(game: &Game, builder: &Unit, seen: &HaveSeen) -> Option<Til
         if dist <= 7 {
             continue;
         }
-        let mut mins_near_gas: Vec<TilePosition> = mineral_locs
-            .iter()
-            .filter(|m| m.chebyshev_distance(*gas) < 12)
-            .map(|p| (*p).clone())
-            .collect();
-        mins_near_gas.push(gas.clone());
-        let center_mins_gas = cartesian_center(&mins_near_gas).expect("gas locs always present");
-
+        let center_mins_gas = resource_center(*gas, &mineral_locs);
         // be near the gas & also the average position of the mins
-        let locs = vec![gas.clone(), center_mins_gas];
-        let center_locs = cartesian_center(&locs).expect("has gas and cmg");
+        let center_locs = depot_estimate(*gas, &mineral_locs);
         checker.debug_rect(
             center_locs.to_position(),
             (center_locs + TilePosition { x: 1, y: 1 }).to_position(),
@@ -128,6 +163,24 @@ fn position_new_base(game: &Game, builder: &Unit, seen: &HaveSeen) -> Option<Til
     position_near_hatch(game, &checker)
 }
 
+/// the middle of a geyser and the minerals around it
+fn resource_center(gas: TilePosition, minerals: &[&TilePosition]) -> TilePosition {
+    let mut resources: Vec<TilePosition> = minerals
+        .iter()
+        .filter(|m| m.chebyshev_distance(gas) < 12)
+        .map(|m| **m)
+        .collect();
+    resources.push(gas);
+    cartesian_center(&resources).expect("gas locs always present")
+}
+
+/// roughly the top left of the resource depot for the base at `gas`, where
+/// we'd start looking to place a hatchery
+pub(crate) fn depot_estimate(gas: TilePosition, minerals: &[&TilePosition]) -> TilePosition {
+    let locs = vec![gas, resource_center(gas, minerals)];
+    cartesian_center(&locs).expect("has gas and cmg")
+}
+
 pub fn get_hatches(game: &Game) -> Vec<Unit> {
     if let Some(self_) = game.self_() {
         self_
@@ -280,7 +333,8 @@ pub(crate) fn tile_position_towards(
 #[cfg(test)]
 mod test {
     use super::{
-        building_pos_search, cartesian_center, position_near, tile_position_towards, CanBuild,
+        building_pos_search, cartesian_center, depot_estimate, position_near,
+        tile_position_towards, CanBuild,
     };
     use rsbwapi::TilePosition;
 
@@ -387,6 +441,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_depot_estimate() {
+        let gas = TilePosition { x: 40, y: 20 };
+        let mut minerals: Vec<TilePosition> = (0..5)
+            .map(|i| TilePosition {
+                x: 32,
+                y: 16 + i * 2,
+            })
+            .collect();
+        // some other base's minerals
+        minerals.push(TilePosition { x: 80, y: 80 });
+        let minerals: Vec<&TilePosition> = minerals.iter().collect();
+        assert_eq!(
+            depot_estimate(gas, &minerals),
+            TilePosition { x: 36, y: 20 },
+            "between the geyser and the mineral line"
+        );
+    }
+
     #[test]
     fn test_tile_position_towards() {
         assert_eq!(
@@ -0,0 +1,634 @@
+use crate::{build_order::PlannedBuilding, counts::Counts};
+use rsbwapi::{Position, TilePosition, UnitId, UnitType};
+use std::collections::{HashMap, HashSet};
+
+// a drone this close to the middle of its building has arrived
+const ARRIVED_DISTANCE: u32 = 32 * 2;
+// a drone that's idle this long after placing didn't start the building
+const PLACE_FRAMES: i32 = 10;
+// give up walking to a tile we can't get to
+const TRAVEL_FRAMES: i32 = 24 * 60;
+// tiles to try before giving up on the building
+const MAX_RETRIES: u8 = 3;
+// forget failures after this long, whatever was in the way may have moved
+const FORGET_FRAMES: i32 = 24 * 60;
+
+// drones move a bit under 5 pixels a frame
+const DRONE_SPEED: i32 = 5;
+// leave a little early, the drone has to path around things
+const DISPATCH_SLACK: i32 = 24 * 2;
+
+/// Frames for a drone to walk between two places
+pub(crate) fn travel_frames(from: Position, to: Position) -> i32 {
+    from.get_approx_distance(to) / DRONE_SPEED
+}
+
+/// Send a drone once it'll arrive about when we can afford the building
+pub(crate) fn should_dispatch(frames_until_affordable: Option<i32>, travel: i32) -> bool {
+    frames_until_affordable.map_or(false, |frames| frames <= travel + DISPATCH_SLACK)
+}
+
+/// Tiles we failed to build each type of building on, and when
+pub(crate) struct BadTiles {
+    failed: HashMap<(UnitType, TilePosition), i32>,
+}
+
+impl BadTiles {
+    pub fn new() -> Self {
+        BadTiles {
+            failed: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, building: UnitType, tile: TilePosition, frame: i32) {
+        self.failed.insert((building, tile), frame);
+    }
+
+    /// where `building` didn't work out
+    pub fn tiles(&self, building: UnitType) -> HashSet<TilePosition> {
+        self.failed
+            .keys()
+            .filter(|(t, _)| *t == building)
+            .map(|(_, tile)| *tile)
+            .collect()
+    }
+
+    fn forget(&mut self, frame: i32) {
+        self.failed
+            .retain(|_, failed| frame - *failed < FORGET_FRAMES);
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum BuilderState {
+    /// has a drone, needs somewhere to build
+    Assigned,
+    Travelling {
+        tile: TilePosition,
+        since: i32,
+    },
+    /// there and waiting for the money
+    Waiting(TilePosition),
+    Placed {
+        tile: TilePosition,
+        frame: i32,
+    },
+    Started,
+    Failed,
+}
+
+/// What happened to the builder since the last event
+#[derive(Debug, PartialEq)]
+pub(crate) enum BuilderEvent {
+    /// where to build, None if there's nowhere left
+    Tile(Option<TilePosition>),
+    Frame {
+        frame: i32,
+        position: Position,
+        idle: bool,
+        affordable: bool,
+    },
+    /// the build command didn't go through, the tile's no good
+    Rejected,
+    /// the build command didn't go through for money, supply or tech, the
+    /// tile's fine
+    NotReady,
+    /// the drone is morphing into the building
+    Started,
+    Died,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum BuilderAction {
+    /// send a Tile event back
+    FindTile,
+    Move(TilePosition),
+    Build(TilePosition),
+    /// give up and send the drone back to work
+    Release,
+    Wait,
+}
+
+/// A drone on its way to put down a building
+pub(crate) struct Builder {
+    pub building: UnitType,
+    pub drone: UnitId,
+    state: BuilderState,
+    frame: i32,
+    // how many tiles have failed for this building, whichever drone tried
+    retries: u8,
+}
+
+impl Builder {
+    pub fn new(building: UnitType, drone: UnitId) -> Self {
+        Builder {
+            building,
+            drone,
+            state: BuilderState::Assigned,
+            frame: 0,
+            retries: 0,
+        }
+    }
+
+    pub fn state(&self) -> BuilderState {
+        self.state
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, BuilderState::Started | BuilderState::Failed)
+    }
+
+    /// where we're going to build
+    pub fn tile(&self) -> Option<TilePosition> {
+        match self.state {
+            BuilderState::Travelling { tile, .. }
+            | BuilderState::Waiting(tile)
+            | BuilderState::Placed { tile, .. } => Some(tile),
+            _ => None,
+        }
+    }
+
+    fn middle(&self, tile: TilePosition) -> Position {
+        tile.to_position()
+            + Position {
+                x: self.building.tile_width() * 16,
+                y: self.building.tile_height() * 16,
+            }
+    }
+
+    /// that tile didn't work out, try another unless we've tried too many
+    fn retry(&mut self, tile: TilePosition, blacklist: &mut BadTiles) -> BuilderAction {
+        println!(
+            "frame {} :: couldn't build {:?} at {:?}",
+            self.frame, self.building, tile
+        );
+        blacklist.insert(self.building, tile, self.frame);
+        self.retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.state = BuilderState::Failed;
+            BuilderAction::Release
+        } else {
+            self.state = BuilderState::Assigned;
+            BuilderAction::FindTile
+        }
+    }
+
+    pub fn on_event(&mut self, event: BuilderEvent, blacklist: &mut BadTiles) -> BuilderAction {
+        use BuilderEvent as E;
+        use BuilderState as S;
+        if let E::Frame { frame, .. } = event {
+            self.frame = frame;
+        }
+        match (self.state, event) {
+            (S::Started | S::Failed, _) => BuilderAction::Wait,
+            (_, E::Died) => {
+                self.state = S::Failed;
+                BuilderAction::Wait
+            }
+            (_, E::Started) => {
+                self.state = S::Started;
+                BuilderAction::Wait
+            }
+            (S::Assigned, E::Tile(Some(tile))) => {
+                self.state = S::Travelling {
+                    tile,
+                    since: self.frame,
+                };
+                BuilderAction::Move(tile)
+            }
+            (S::Assigned, E::Tile(None)) => {
+                self.state = S::Failed;
+                BuilderAction::Release
+            }
+            (S::Assigned, E::Frame { .. }) => BuilderAction::FindTile,
+            (
+                S::Travelling { tile, .. } | S::Waiting(tile),
+                E::Frame {
+                    affordable: true, ..
+                },
+            ) => {
+                self.state = S::Placed {
+                    tile,
+                    frame: self.frame,
+                };
+                BuilderAction::Build(tile)
+            }
+            (
+                S::Travelling { tile, since },
+                E::Frame {
+                    frame,
+                    position,
+                    idle,
+                    ..
+                },
+            ) => {
+                if position.chebyshev_distance(self.middle(tile)) <= ARRIVED_DISTANCE {
+                    self.state = S::Waiting(tile);
+                    BuilderAction::Wait
+                } else if frame - since > TRAVEL_FRAMES {
+                    self.retry(tile, blacklist)
+                } else if idle {
+                    // something interrupted the move
+                    BuilderAction::Move(tile)
+                } else {
+                    BuilderAction::Wait
+                }
+            }
+            (S::Placed { tile, .. }, E::Rejected) => self.retry(tile, blacklist),
+            (S::Placed { tile, .. }, E::NotReady) => {
+                self.state = S::Waiting(tile);
+                BuilderAction::Wait
+            }
+            (
+                S::Placed {
+                    tile,
+                    frame: placed,
+                },
+                E::Frame {
+                    frame, idle: true, ..
+                },
+            ) if frame - placed > PLACE_FRAMES => self.retry(tile, blacklist),
+            _ => BuilderAction::Wait,
+        }
+    }
+}
+
+/// A builder for each building we're placing
+pub(crate) struct BuilderPool {
+    builders: HashMap<PlannedBuilding, Builder>,
+    pub bad_tiles: BadTiles,
+    // how many tiles each building has failed on and when it last did
+    failures: HashMap<PlannedBuilding, (u8, i32)>,
+}
+
+impl BuilderPool {
+    pub fn new() -> Self {
+        BuilderPool {
+            builders: HashMap::new(),
+            bad_tiles: BadTiles::new(),
+            failures: HashMap::new(),
+        }
+    }
+
+    /// give tiles and buildings that failed a while ago another go
+    pub fn forget(&mut self, frame: i32) {
+        self.bad_tiles.forget(frame);
+        self.failures
+            .retain(|_, (_, failed)| frame - *failed < FORGET_FRAMES);
+    }
+
+    pub fn contains(&self, planned: &PlannedBuilding) -> bool {
+        self.builders.contains_key(planned)
+    }
+
+    pub fn take(&mut self, planned: &PlannedBuilding) -> Option<Builder> {
+        self.builders.remove(planned)
+    }
+
+    pub fn put_back(&mut self, planned: PlannedBuilding, builder: Builder) {
+        if builder.state() == BuilderState::Started {
+            self.failures.remove(&planned);
+        } else if builder.retries > 0 {
+            self.failures
+                .insert(planned, (builder.retries, builder.frame));
+        }
+        if !builder.is_done() {
+            self.builders.insert(planned, builder);
+        }
+    }
+
+    /// Send the closest drone, with how long it'll take to get there, if it'll
+    /// arrive about when we can afford the building
+    pub fn dispatch(
+        &mut self,
+        planned: PlannedBuilding,
+        counts: &Counts,
+        closest: Option<(UnitId, i32)>,
+    ) -> Option<UnitId> {
+        let (drone, travel) = closest?;
+        let busy = self.builders.values().any(|b| b.drone == drone);
+        let retries = self
+            .failures
+            .get(&planned)
+            .map_or(0, |(retries, _)| *retries);
+        if busy
+            || self.contains(&planned)
+            || retries > MAX_RETRIES
+            || !should_dispatch(counts.frames_until_affordable(planned.unit_type), travel)
+        {
+            return None;
+        }
+        let mut builder = Builder::new(planned.unit_type, drone);
+        builder.retries = retries;
+        self.builders.insert(planned, builder);
+        Some(drone)
+    }
+
+    /// Drones to send back to work since their building isn't planned
+    /// anymore, builders that have placed keep going until it starts
+    pub fn release_unplanned(&mut self, planned: &[PlannedBuilding]) -> Vec<UnitId> {
+        let mut released = vec![];
+        self.builders.retain(|key, builder| {
+            let keep =
+                planned.contains(key) || matches!(builder.state(), BuilderState::Placed { .. });
+            if !keep {
+                released.push(builder.drone);
+            }
+            keep
+        });
+        released
+    }
+
+    /// builders that placed something that's no longer in the plan
+    pub fn unplanned(&self, planned: &[PlannedBuilding]) -> Vec<PlannedBuilding> {
+        self.builders
+            .keys()
+            .filter(|key| !planned.contains(key))
+            .copied()
+            .collect()
+    }
+
+    /// Tiles a building of this type can't go on, because we failed there
+    /// before or it'd overlap where another builder is going
+    pub fn reserved(&self, building: UnitType) -> HashSet<TilePosition> {
+        let mut reserved = self.bad_tiles.tiles(building);
+        for other in self.builders.values() {
+            if let Some(tile) = other.tile() {
+                let size = other.building.tile_size();
+                for y in tile.y - building.tile_height() + 1..tile.y + size.y {
+                    for x in tile.x - building.tile_width() + 1..tile.x + size.x {
+                        reserved.insert(TilePosition { x, y });
+                    }
+                }
+            }
+        }
+        reserved
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+
+    fn frame(frame: i32, tile: TilePosition, idle: bool, affordable: bool) -> BuilderEvent {
+        BuilderEvent::Frame {
+            frame,
+            position: tile.to_position(),
+            idle,
+            affordable,
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let travel = travel_frames(Position { x: 0, y: 0 }, Position { x: 32 * 40, y: 0 });
+        assert_eq!(travel, 256);
+        assert!(!should_dispatch(None, travel), "can't afford it at all");
+        assert!(!should_dispatch(Some(24 * 30), travel), "way too early");
+        assert!(should_dispatch(Some(24 * 10), travel));
+        assert!(should_dispatch(Some(0), 0), "right next to it");
+    }
+
+    #[test]
+    fn test_build() {
+        let mut blacklist = BadTiles::new();
+        let mut builder = Builder::new(UnitType::Zerg_Spawning_Pool, 1);
+        let pool = tp(20, 20);
+        let far = tp(0, 0);
+
+        assert_eq!(
+            builder.on_event(frame(100, far, false, false), &mut blacklist),
+            BuilderAction::FindTile
+        );
+        assert_eq!(
+            builder.on_event(BuilderEvent::Tile(Some(pool)), &mut blacklist),
+            BuilderAction::Move(pool)
+        );
+        assert_eq!(
+            builder.on_event(frame(101, far, false, false), &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert_eq!(
+            builder.on_event(frame(102, far, true, false), &mut blacklist),
+            BuilderAction::Move(pool),
+            "keep going"
+        );
+        assert_eq!(
+            builder.on_event(frame(200, pool, true, false), &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert_eq!(builder.state(), BuilderState::Waiting(pool));
+        assert_eq!(
+            builder.on_event(frame(250, pool, true, true), &mut blacklist),
+            BuilderAction::Build(pool)
+        );
+        assert_eq!(
+            builder.on_event(frame(255, pool, true, true), &mut blacklist),
+            BuilderAction::Wait,
+            "give it a few frames to start"
+        );
+        builder.on_event(BuilderEvent::Started, &mut blacklist);
+        assert!(builder.is_done());
+        assert_eq!(builder.state(), BuilderState::Started);
+        assert!(blacklist.failed.is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut blacklist = BadTiles::new();
+        let mut builder = Builder::new(UnitType::Zerg_Hatchery, 1);
+        let blocked = tp(20, 20);
+        let other = tp(30, 20);
+        builder.on_event(BuilderEvent::Tile(Some(blocked)), &mut blacklist);
+        builder.on_event(frame(0, blocked, false, true), &mut blacklist);
+        assert_eq!(
+            builder.on_event(BuilderEvent::Rejected, &mut blacklist),
+            BuilderAction::FindTile
+        );
+        assert!(blacklist.tiles(UnitType::Zerg_Hatchery).contains(&blocked));
+
+        // the money wasn't there after all
+        builder.on_event(BuilderEvent::Tile(Some(other)), &mut blacklist);
+        builder.on_event(frame(5, other, false, true), &mut blacklist);
+        assert_eq!(
+            builder.on_event(BuilderEvent::NotReady, &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert_eq!(builder.state(), BuilderState::Waiting(other));
+        assert!(!blacklist.tiles(UnitType::Zerg_Hatchery).contains(&other));
+
+        // an enemy unit sitting on the spot
+        builder.on_event(frame(10, other, false, true), &mut blacklist);
+        assert_eq!(
+            builder.on_event(frame(15, other, true, true), &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert_eq!(
+            builder.on_event(frame(30, other, true, true), &mut blacklist),
+            BuilderAction::FindTile,
+            "idle too long after placing"
+        );
+        assert!(blacklist.tiles(UnitType::Zerg_Hatchery).contains(&other));
+
+        assert_eq!(
+            builder.on_event(BuilderEvent::Tile(None), &mut blacklist),
+            BuilderAction::Release,
+            "nowhere left to build"
+        );
+        assert_eq!(builder.state(), BuilderState::Failed);
+    }
+
+    #[test]
+    fn test_give_up() {
+        let mut blacklist = BadTiles::new();
+        let mut builder = Builder::new(UnitType::Zerg_Hatchery, 1);
+        for i in 0..MAX_RETRIES {
+            builder.on_event(BuilderEvent::Tile(Some(tp(i as i32, 0))), &mut blacklist);
+            builder.on_event(frame(0, tp(0, 0), false, true), &mut blacklist);
+            assert_eq!(
+                builder.on_event(BuilderEvent::Rejected, &mut blacklist),
+                BuilderAction::FindTile
+            );
+        }
+        builder.on_event(BuilderEvent::Tile(Some(tp(50, 50))), &mut blacklist);
+        assert_eq!(
+            builder.on_event(frame(0, tp(0, 0), false, false), &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert_eq!(
+            builder.on_event(
+                frame(TRAVEL_FRAMES + 1, tp(0, 0), false, false),
+                &mut blacklist
+            ),
+            BuilderAction::Release,
+            "never got there"
+        );
+        assert_eq!(blacklist.failed.len(), MAX_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn test_died() {
+        let mut blacklist = BadTiles::new();
+        let mut builder = Builder::new(UnitType::Zerg_Extractor, 1);
+        builder.on_event(BuilderEvent::Tile(Some(tp(5, 5))), &mut blacklist);
+        builder.on_event(BuilderEvent::Died, &mut blacklist);
+        assert_eq!(builder.state(), BuilderState::Failed);
+        assert_eq!(
+            builder.on_event(frame(1, tp(5, 5), true, true), &mut blacklist),
+            BuilderAction::Wait
+        );
+        assert!(blacklist.failed.is_empty(), "nothing wrong with the tile");
+    }
+
+    fn planned(unit_type: UnitType, nth: i8) -> PlannedBuilding {
+        PlannedBuilding {
+            unit_type,
+            nth,
+            macro_hatch: false,
+        }
+    }
+
+    #[test]
+    fn test_two_placements() {
+        let hatch = planned(UnitType::Zerg_Hatchery, 3);
+        let evo = planned(UnitType::Zerg_Evolution_Chamber, 1);
+        let mut pool = BuilderPool::new();
+
+        // money for the hatch, the evo chamber is a while off
+        let mut counts = Counts::new_fake_bank(300, 0, 60, 80).with_income(0.5, 0.0);
+        for (plan, drone) in [(hatch, 1), (evo, 2)] {
+            pool.dispatch(plan, &counts, Some((drone, 24 * 4)));
+            counts.reserve(plan.unit_type);
+        }
+        assert!(pool.contains(&hatch));
+        assert!(!pool.contains(&evo), "hatch money comes first");
+
+        let mut counts = Counts::new_fake_bank(340, 0, 60, 80).with_income(0.5, 0.0);
+        for (plan, drone) in [(hatch, 1), (evo, 2)] {
+            pool.dispatch(plan, &counts, Some((drone, 24 * 4)));
+            counts.reserve(plan.unit_type);
+        }
+        assert!(pool.contains(&evo), "there in time for the money");
+        assert_eq!(
+            pool.dispatch(planned(UnitType::Zerg_Spire, 1), &counts, Some((2, 0))),
+            None,
+            "drone's already busy"
+        );
+
+        // the hatch gets placed, the evo chamber's plans change
+        let mut builder = pool.take(&hatch).unwrap();
+        builder.on_event(BuilderEvent::Tile(Some(tp(20, 20))), &mut pool.bad_tiles);
+        builder.on_event(frame(0, tp(20, 20), false, true), &mut pool.bad_tiles);
+        pool.put_back(hatch, builder);
+        assert_eq!(pool.release_unplanned(&[]), vec![2]);
+        assert_eq!(pool.unplanned(&[]), vec![hatch], "still placing the hatch");
+    }
+
+    #[test]
+    fn test_reserved() {
+        let mut pool = BuilderPool::new();
+        let hatch = planned(UnitType::Zerg_Hatchery, 2);
+        pool.bad_tiles
+            .insert(UnitType::Zerg_Evolution_Chamber, tp(0, 0), 0);
+        pool.dispatch(hatch, &Counts::new_fake_bank(300, 0, 0, 0), Some((1, 0)));
+        let mut builder = pool.take(&hatch).unwrap();
+        builder.on_event(BuilderEvent::Tile(Some(tp(10, 10))), &mut pool.bad_tiles);
+        pool.put_back(hatch, builder);
+
+        let reserved = pool.reserved(UnitType::Zerg_Evolution_Chamber);
+        assert!(reserved.contains(&tp(0, 0)));
+        assert!(reserved.contains(&tp(9, 9)), "would overlap the top left");
+        assert!(reserved.contains(&tp(13, 12)));
+        assert!(!reserved.contains(&tp(8, 10)), "right next to it");
+        assert!(!reserved.contains(&tp(14, 10)));
+        assert!(!reserved.contains(&tp(10, 13)));
+        assert!(
+            !pool.reserved(UnitType::Zerg_Hatchery).contains(&tp(0, 0)),
+            "only evo chambers failed there"
+        );
+    }
+
+    #[test]
+    fn test_failures_per_plan() {
+        let mut pool = BuilderPool::new();
+        let hatch = planned(UnitType::Zerg_Hatchery, 2);
+        let counts = Counts::new_fake_bank(300, 0, 0, 0);
+        let reject = |builder: &mut Builder, bad_tiles: &mut BadTiles, tile| {
+            builder.on_event(BuilderEvent::Tile(Some(tile)), bad_tiles);
+            builder.on_event(frame(0, tile, false, true), bad_tiles);
+            builder.on_event(BuilderEvent::Rejected, bad_tiles)
+        };
+        pool.dispatch(hatch, &counts, Some((1, 0)));
+        let mut builder = pool.take(&hatch).unwrap();
+        reject(&mut builder, &mut pool.bad_tiles, tp(0, 0));
+        reject(&mut builder, &mut pool.bad_tiles, tp(10, 0));
+        builder.on_event(BuilderEvent::Died, &mut pool.bad_tiles);
+        pool.put_back(hatch, builder);
+
+        // the next drone carries on from there
+        assert_eq!(pool.dispatch(hatch, &counts, Some((2, 0))), Some(2));
+        let mut builder = pool.take(&hatch).unwrap();
+        assert_eq!(
+            reject(&mut builder, &mut pool.bad_tiles, tp(20, 0)),
+            BuilderAction::FindTile
+        );
+        assert_eq!(
+            reject(&mut builder, &mut pool.bad_tiles, tp(30, 0)),
+            BuilderAction::Release
+        );
+        pool.put_back(hatch, builder);
+        assert_eq!(
+            pool.dispatch(hatch, &counts, Some((3, 0))),
+            None,
+            "given up for now"
+        );
+
+        pool.forget(FORGET_FRAMES);
+        assert!(
+            pool.reserved(UnitType::Zerg_Hatchery).is_empty(),
+            "worth another look"
+        );
+        assert_eq!(pool.dispatch(hatch, &counts, Some((3, 0))), Some(3));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{
+    build_order::TechChecker,
+    seen::HaveSeen,
+    sim::{army_value, Matchup, SimUnit},
+};
+use rsbwapi::UnitType;
+
+const REPLAN_INTERVAL: i32 = 24 * 5; // frames
+
+// compare compositions with at least this much to spend on them
+const MIN_BUDGET: i32 = 1_000;
+// fights the enemy can't win are scored as if they took this long
+const FRAMES_TO_LOSE_CAP: f32 = 24.0 * 60.0;
+// pretend the enemy has a couple of whatever their tech buildings make
+const HINTED_UNITS: usize = 2;
+// expect one in this many of the units the enemy could have made unseen
+const UNSEEN_SHARE: i32 = 2;
+
+/// enemy buildings that tell us what units to expect before we see them
+/// TODO: carriers and reavers do their damage through interceptors/scarabs
+const TECH_HINTS: [(UnitType, UnitType); 8] = [
+    (UnitType::Terran_Factory, UnitType::Terran_Vulture),
+    (
+        UnitType::Terran_Machine_Shop,
+        UnitType::Terran_Siege_Tank_Tank_Mode,
+    ),
+    (UnitType::Terran_Starport, UnitType::Terran_Wraith),
+    (
+        UnitType::Protoss_Cybernetics_Core,
+        UnitType::Protoss_Dragoon,
+    ),
+    (UnitType::Protoss_Stargate, UnitType::Protoss_Corsair),
+    (UnitType::Protoss_Fleet_Beacon, UnitType::Protoss_Carrier),
+    (UnitType::Zerg_Hydralisk_Den, UnitType::Zerg_Hydralisk),
+    (UnitType::Zerg_Spire, UnitType::Zerg_Mutalisk),
+];
+
+/// Target ratios of army units, e.g. 2 hydras for every lurker
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Composition {
+    ratios: Vec<(UnitType, i32)>,
+}
+
+impl Composition {
+    pub fn new(ratios: Vec<(UnitType, i32)>) -> Self {
+        Composition { ratios }
+    }
+
+    pub fn ratio_of(&self, unit_type: UnitType) -> i32 {
+        self.ratios
+            .iter()
+            .filter(|(ut, _)| *ut == unit_type)
+            .map(|(_, r)| *r)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ratios.is_empty()
+    }
+
+    /// Larva units in the order they're most needed to reach our ratios.
+    /// Units that morph from other units count towards what they morph from
+    pub fn larva_units_wanted(&self, count_of: impl Fn(UnitType) -> i32) -> Vec<UnitType> {
+        let mut wanted: Vec<(UnitType, i32, i32)> = vec![];
+        for (ut, ratio) in self.ratios.iter() {
+            let larva_type = morphs_from(*ut).unwrap_or(*ut);
+            match wanted.iter_mut().find(|(t, _, _)| *t == larva_type) {
+                Some((_, r, _)) => *r += ratio,
+                None => wanted.push((larva_type, *ratio, 0)),
+            }
+        }
+        for (larva_type, _, count) in wanted.iter_mut() {
+            *count = count_of(*larva_type)
+                + self
+                    .ratios
+                    .iter()
+                    .filter(|(ut, _)| morphs_from(*ut) == Some(*larva_type))
+                    .map(|(ut, _)| count_of(*ut))
+                    .sum::<i32>();
+        }
+        // least built relative to its ratio first
+        wanted.sort_by(|(_, r1, c1), (_, r2, c2)| {
+            (*c1 as f32 / *r1 as f32)
+                .partial_cmp(&(*c2 as f32 / *r2 as f32))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        wanted.into_iter().map(|(ut, _, _)| ut).collect()
+    }
+
+    /// Morphed units we have fewer of than our ratio wants, paired with the
+    /// unit type they morph from
+    pub fn morphs_wanted(&self, count_of: impl Fn(UnitType) -> i32) -> Vec<(UnitType, UnitType)> {
+        self.ratios
+            .iter()
+            .filter_map(|(ut, ratio)| {
+                let from = morphs_from(*ut)?;
+                let from_ratio = self.ratio_of(from);
+                let family = count_of(from) + count_of(*ut);
+                let wanted = family * ratio / (ratio + from_ratio);
+                if count_of(*ut) < wanted {
+                    Some((from, *ut))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// gas for one set of units in our ratios
+    pub fn gas_per_set(&self) -> i32 {
+        self.ratios
+            .iter()
+            .map(|(ut, r)| {
+                let from_gas = morphs_from(*ut).map_or(0, |from| from.gas_price());
+                r * (ut.gas_price() + from_gas)
+            })
+            .sum()
+    }
+
+    /// An army of roughly `budget` resources built in our ratios
+    fn build_army(&self, budget: i32) -> Vec<SimUnit> {
+        let set_cost: i32 = self.ratios.iter().map(|(ut, r)| r * full_cost(*ut)).sum();
+        if set_cost == 0 {
+            return vec![];
+        }
+        let sets = budget as f32 / set_cost as f32;
+        self.ratios
+            .iter()
+            .flat_map(|(ut, r)| {
+                let count = ((*r as f32 * sets).round() as usize).max(1);
+                (0..count).map(move |_| SimUnit::from_type(*ut, 0, 0))
+            })
+            .collect()
+    }
+}
+
+/// what a unit type has to be before it can morph into this one
+fn morphs_from(unit_type: UnitType) -> Option<UnitType> {
+    match unit_type {
+        UnitType::Zerg_Lurker => Some(UnitType::Zerg_Hydralisk),
+        UnitType::Zerg_Guardian | UnitType::Zerg_Devourer => Some(UnitType::Zerg_Mutalisk),
+        _ => None,
+    }
+}
+
+/// per-unit cost including whatever it morphed from
+fn full_cost(unit_type: UnitType) -> i32 {
+    let cost = unit_type.mineral_price() + unit_type.gas_price();
+    let cost = if unit_type.is_two_units_in_one_egg() {
+        cost / 2
+    } else {
+        cost
+    };
+    cost + morphs_from(unit_type).map(full_cost).unwrap_or(0)
+}
+
+/// Candidate compositions, roughly in tech order
+fn candidates() -> Vec<Composition> {
+    use UnitType::*;
+    vec![
+        Composition::new(vec![(Zerg_Zergling, 1)]),
+        Composition::new(vec![(Zerg_Zergling, 2), (Zerg_Hydralisk, 1)]),
+        Composition::new(vec![(Zerg_Hydralisk, 1)]),
+        Composition::new(vec![(Zerg_Mutalisk, 1), (Zerg_Zergling, 2)]),
+        Composition::new(vec![(Zerg_Hydralisk, 2), (Zerg_Lurker, 1)]),
+        Composition::new(vec![(Zerg_Ultralisk, 1), (Zerg_Zergling, 4)]),
+        Composition::new(vec![(Zerg_Mutalisk, 1), (Zerg_Guardian, 1)]),
+        Composition::new(vec![
+            (Zerg_Ultralisk, 1),
+            (Zerg_Zergling, 4),
+            (Zerg_Defiler, 1),
+        ]),
+    ]
+}
+
+/// Picks the army composition that fares best against what we've seen of the
+/// enemy, using the damage model in sim
+pub(crate) struct CompPlanner {
+    planned_frame: Option<i32>,
+    current: Composition,
+}
+
+impl CompPlanner {
+    pub fn new() -> Self {
+        CompPlanner {
+            planned_frame: None,
+            current: Composition::default(),
+        }
+    }
+
+    pub fn on_frame(&mut self, frame: i32, seen: &HaveSeen, tech: impl TechChecker) {
+        let due = self
+            .planned_frame
+            .map_or(true, |f| frame >= f + REPLAN_INTERVAL);
+        if due {
+            let plan = CompPlanner::plan(seen, tech);
+            if plan != self.current {
+                println!("frame {} :: new army composition {:?}", frame, plan);
+            }
+            self.current = plan;
+            self.planned_frame = Some(frame);
+        }
+    }
+
+    pub fn current(&self) -> &Composition {
+        &self.current
+    }
+
+    /// Score the compositions we can make against the enemy's seen army and
+    /// tech, ties go to the higher tech composition
+    fn plan(seen: &HaveSeen, tech: impl TechChecker) -> Composition {
+        let makeable: Vec<Composition> = candidates()
+            .into_iter()
+            .filter(|c| c.ratios.iter().all(|(ut, _)| tech.has_prereqs(ut)))
+            .collect();
+        let enemy = CompPlanner::expected_enemy_army(seen);
+        if enemy.is_empty() {
+            return makeable.into_iter().last().unwrap_or_default();
+        }
+
+        let budget = army_value(&enemy).max(MIN_BUDGET);
+        let mut best: Option<(f32, Composition)> = None;
+        for comp in makeable {
+            let score = CompPlanner::score(&comp.build_army(budget), &enemy);
+            if best.as_ref().map_or(true, |(s, _)| score >= *s) {
+                best = Some((score, comp));
+            }
+        }
+        best.map(|(_, c)| c).unwrap_or_default()
+    }
+
+    /// higher is better: how much longer it takes them to kill us than us to kill them
+    fn score(ours: &[SimUnit], theirs: &[SimUnit]) -> f32 {
+        let m = Matchup::evaluate(ours, theirs);
+        match m.frames_to_win {
+            None => 0.0,
+            Some(win) => {
+                let lose = m
+                    .frames_to_lose
+                    .unwrap_or(FRAMES_TO_LOSE_CAP)
+                    .min(FRAMES_TO_LOSE_CAP);
+                lose / win.max(1.0)
+            }
+        }
+    }
+
+    /// the enemy units we've seen plus a few from each tech building that
+    /// hasn't shown us its units yet
+    fn expected_enemy_army(seen: &HaveSeen) -> Vec<SimUnit> {
+        let army = seen.get_enemy_army();
+        let mut expected: Vec<SimUnit> = army.iter().map(|su| SimUnit::from_seen(su)).collect();
+        let estimates = seen.estimate_army();
+        for (from, hinted) in TECH_HINTS.iter() {
+            // the estimate has a better idea how many there could be
+            let estimated = estimates.iter().any(|e| e.unit_type == *hinted);
+            if !estimated && !army.iter().any(|su| su.unit_type == *hinted) {
+                let hints = HINTED_UNITS * seen.building_count(*from);
+                expected.extend((0..hints).map(|_| SimUnit::from_type(*hinted, 0, 0)));
+            }
+        }
+        // they've probably made more than we've seen, but not everything they could have
+        for estimate in estimates {
+            let unseen = (estimate.most - estimate.seen) / UNSEEN_SHARE;
+            expected.extend((0..unseen).map(|_| SimUnit::from_type(estimate.unit_type, 0, 0)));
+        }
+        expected
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::seen::SeenUnit;
+    use crate::test_util::AllTech;
+
+    struct NoSpire;
+    impl TechChecker for &NoSpire {
+        fn has_prereqs(&self, unit_type: &UnitType) -> bool {
+            matches!(
+                unit_type,
+                UnitType::Zerg_Zergling | UnitType::Zerg_Hydralisk | UnitType::Zerg_Lurker
+            )
+        }
+    }
+
+    fn seen_with(units: Vec<(UnitType, usize)>) -> HaveSeen {
+        let mut seen = HaveSeen::new();
+        let mut id = 0;
+        for (ut, count) in units {
+            for _ in 0..count {
+                id += 1;
+                seen.add_fake(id, SeenUnit::fake(ut, 0));
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn test_hydras_vs_carriers() {
+        let seen = seen_with(vec![(UnitType::Protoss_Carrier, 6)]);
+        let plan = CompPlanner::plan(&seen, &AllTech {});
+        assert!(
+            plan.ratio_of(UnitType::Zerg_Hydralisk) > plan.ratio_of(UnitType::Zerg_Mutalisk),
+            "more hydras vs carriers: {:?}",
+            plan
+        );
+        assert_eq!(
+            plan.ratio_of(UnitType::Zerg_Zergling),
+            0,
+            "lings are useless vs carriers"
+        );
+    }
+
+    #[test]
+    fn test_hinted_carriers() {
+        let seen = seen_with(vec![(UnitType::Protoss_Fleet_Beacon, 1)]);
+        let plan = CompPlanner::plan(&seen, &AllTech {});
+        assert!(
+            plan.ratio_of(UnitType::Zerg_Hydralisk) > 0,
+            "fleet beacon means hydras: {:?}",
+            plan
+        );
+    }
+
+    #[test]
+    fn test_mutas_vs_no_anti_air() {
+        let seen = seen_with(vec![(UnitType::Protoss_Zealot, 8)]);
+        let plan = CompPlanner::plan(&seen, &AllTech {});
+        assert!(
+            plan.ratio_of(UnitType::Zerg_Mutalisk) > 0,
+            "zealots can't shoot up: {:?}",
+            plan
+        );
+        let plan = CompPlanner::plan(&seen, &NoSpire {});
+        assert_eq!(
+            plan.ratio_of(UnitType::Zerg_Mutalisk),
+            0,
+            "can't make mutas without a spire"
+        );
+        assert!(!plan.is_empty(), "still makes something");
+    }
+
+    #[test]
+    fn test_nothing_seen() {
+        let plan = CompPlanner::plan(&HaveSeen::new(), &NoSpire {});
+        assert_eq!(
+            plan,
+            Composition::new(vec![
+                (UnitType::Zerg_Hydralisk, 2),
+                (UnitType::Zerg_Lurker, 1)
+            ]),
+            "highest tech we have"
+        );
+    }
+
+    #[test]
+    fn test_larva_units_wanted() {
+        let comp = Composition::new(vec![
+            (UnitType::Zerg_Hydralisk, 2),
+            (UnitType::Zerg_Lurker, 1),
+            (UnitType::Zerg_Zergling, 3),
+        ]);
+        let counts = |ut: UnitType| match ut {
+            UnitType::Zerg_Hydralisk => 2,
+            UnitType::Zerg_Lurker => 1,
+            UnitType::Zerg_Zergling => 6,
+            _ => 0,
+        };
+        assert_eq!(
+            comp.larva_units_wanted(counts),
+            vec![UnitType::Zerg_Hydralisk, UnitType::Zerg_Zergling],
+            "lurkers count as hydras"
+        );
+        assert_eq!(
+            comp.morphs_wanted(counts),
+            vec![],
+            "one lurker per two hydras"
+        );
+        let no_lurkers = |ut: UnitType| match ut {
+            UnitType::Zerg_Hydralisk => 3,
+            _ => 0,
+        };
+        assert_eq!(
+            comp.morphs_wanted(no_lurkers),
+            vec![(UnitType::Zerg_Hydralisk, UnitType::Zerg_Lurker)],
+            "morph a hydra"
+        );
+    }
+}
@@ -1,5 +1,100 @@
-use crate::build_order::BuildOrder;
+use crate::{
+    build_order::BuildOrder,
+    drones::{DroneManager, DroneRole},
+};
 use rsbwapi::{Game, UnitType};
+use std::collections::VecDeque;
+
+// rough per-frame income of a single worker, used until we've seen some income
+const MINERALS_PER_DRONE_FRAME: f32 = 0.045;
+const GAS_PER_DRONE_FRAME: f32 = 0.07;
+// how far back we look at what we've gathered
+const INCOME_WINDOW: i32 = 24 * 10; // frames
+
+/**
+ * Rolling income rate from what we've gathered recently and how many drones
+ * are mining right now
+ */
+pub(crate) struct Income {
+    // (frame, total minerals gathered, total gas gathered)
+    samples: VecDeque<(i32, i32, i32)>,
+    mineral_drones: usize,
+    gas_drones: usize,
+}
+
+impl Income {
+    pub fn new() -> Self {
+        Income {
+            samples: VecDeque::new(),
+            mineral_drones: 0,
+            gas_drones: 0,
+        }
+    }
+
+    pub fn on_frame(&mut self, game: &Game, drones: &DroneManager) {
+        if let Some(self_) = game.self_() {
+            self.record(
+                game.get_frame_count(),
+                self_.gathered_minerals(),
+                self_.gathered_gas(),
+                drones.count_role(&DroneRole::Minerals),
+                drones.count_role(&DroneRole::Gas),
+            );
+        }
+    }
+
+    fn record(
+        &mut self,
+        frame: i32,
+        minerals_gathered: i32,
+        gas_gathered: i32,
+        mineral_drones: usize,
+        gas_drones: usize,
+    ) {
+        self.samples
+            .push_back((frame, minerals_gathered, gas_gathered));
+        while let Some(&(oldest, _, _)) = self.samples.front() {
+            if frame - oldest <= INCOME_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+        self.mineral_drones = mineral_drones;
+        self.gas_drones = gas_drones;
+    }
+
+    /// what we've gathered per frame over the window, once the window is full
+    fn observed(&self) -> Option<(f32, f32)> {
+        let (first_frame, first_m, first_g) = self.samples.front()?;
+        let (last_frame, last_m, last_g) = self.samples.back()?;
+        let frames = last_frame - first_frame;
+        if frames < INCOME_WINDOW {
+            return None;
+        }
+        Some((
+            (last_m - first_m) as f32 / frames as f32,
+            (last_g - first_g) as f32 / frames as f32,
+        ))
+    }
+
+    /// what our current drones should be gathering per frame
+    fn estimated(&self) -> (f32, f32) {
+        (
+            self.mineral_drones as f32 * MINERALS_PER_DRONE_FRAME,
+            self.gas_drones as f32 * GAS_PER_DRONE_FRAME,
+        )
+    }
+
+    /// minerals and gas per frame. The observed rate lags behind changes in
+    /// drone counts so it's averaged with the estimate
+    pub fn rates(&self) -> (f32, f32) {
+        let (est_m, est_g) = self.estimated();
+        match self.observed() {
+            Some((obs_m, obs_g)) => ((obs_m + est_m) / 2.0, (obs_g + est_g) / 2.0),
+            None => (est_m, est_g),
+        }
+    }
+}
 
 /**
  * Keep track of quantities more reliably than the game engine
@@ -8,32 +103,37 @@ use rsbwapi::{Game, UnitType};
 pub(crate) struct Counts {
     minerals: i32,
     gas: i32,
+    // what the game says we're using, eggs included
     supply_used: i32,
+    // used by production we've queued this frame
+    supply_reserved: i32,
+    // provided by finished overlords and hatcheries
     supply_max: i32,
+    // provided once overlord eggs and new hatcheries finish
+    supply_pending: i32,
     frame: i32,
+    mineral_rate: f32,
+    gas_rate: f32,
 }
 
 impl Counts {
-    pub fn new(game: &Game, build_order: &BuildOrder) -> Self {
-        use std::cmp::max;
+    pub fn new(game: &Game, build_order: &BuildOrder, income: &Income) -> Self {
+        let (mineral_rate, gas_rate) = income.rates();
         let mut c = Counts {
             frame: game.get_frame_count(),
+            mineral_rate,
+            gas_rate,
             ..Default::default()
         };
         if let Some(self_) = game.self_() {
             c.minerals = self_.minerals() - build_order.spent_minerals();
             c.gas = self_.gas() - build_order.spent_gas();
-            c.supply_max = max(
-                self_.supply_total(),
+            c.supply_max = self_.supply_total();
+            c.supply_pending = pending_supply(
                 self_
                     .get_units()
                     .iter()
-                    .map(|u| u.get_type())
-                    .filter(|t| {
-                        *t == UnitType::Zerg_Overlord || t.is_successor_of(UnitType::Zerg_Hatchery)
-                    })
-                    .map(|ut| ut.supply_provided())
-                    .sum(),
+                    .map(|u| (u.get_type(), u.is_completed(), u.get_build_type())),
             );
             c.supply_used = self_.supply_used();
         }
@@ -47,17 +147,59 @@ impl Counts {
         }
     }
 
+    #[cfg(test)]
+    pub fn new_fake_bank(minerals: i32, gas: i32, supply_used: i32, supply_max: i32) -> Self {
+        Counts {
+            minerals,
+            gas,
+            supply_used,
+            supply_max,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_pending(mut self, supply_pending: i32) -> Self {
+        self.supply_pending = supply_pending;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_income(mut self, mineral_rate: f32, gas_rate: f32) -> Self {
+        self.mineral_rate = mineral_rate;
+        self.gas_rate = gas_rate;
+        self
+    }
+
     pub fn supply_string(&self) -> String {
-        format!("supply {} / {}", self.supply_used / 2, self.supply_max / 2)
+        format!(
+            "supply {} / {} (+{})",
+            self.supply_used() / 2,
+            self.supply_max / 2,
+            self.supply_pending / 2
+        )
     }
 
     pub fn bought(&mut self, unit: UnitType) {
+        self.reserve(unit);
+        self.supply_reserved += unit.supply_required();
+        self.supply_pending += unit.supply_provided();
+    }
+
+    /// hold money back for something we haven't made yet, it doesn't use or
+    /// give any supply until it's actually bought
+    pub fn reserve(&mut self, unit: UnitType) {
         self.minerals -= unit.mineral_price();
         self.gas -= unit.gas_price();
-        self.supply_used += unit.supply_required();
     }
 
-    #[allow(unused)]
+    /// a unit turning into another costs the price of what it turns into and
+    /// any supply it needs over what it used before
+    pub fn morphed(&mut self, from: UnitType, to: UnitType) {
+        self.reserve(to);
+        self.supply_reserved += (to.supply_required() - from.supply_required()).max(0);
+    }
+
     pub fn spent(&mut self, minerals: i32, gas: i32) {
         self.minerals -= minerals;
         self.gas -= gas;
@@ -67,6 +209,30 @@ impl Counts {
         return self.minerals() >= unit.mineral_price() && self.gas() >= unit.gas_price();
     }
 
+    /// minerals and gas we expect to have `frames` from now
+    pub fn projected_bank(&self, frames: i32) -> (i32, i32) {
+        (
+            self.minerals + (self.mineral_rate * frames as f32) as i32,
+            self.gas + (self.gas_rate * frames as f32) as i32,
+        )
+    }
+
+    /// frames until our income pays for a unit, None if it never will
+    pub fn frames_until_affordable(&self, unit: UnitType) -> Option<i32> {
+        let wait = |have: i32, price: i32, rate: f32| {
+            if have >= price {
+                Some(0)
+            } else if rate <= 0.0 {
+                None
+            } else {
+                Some(((price - have) as f32 / rate).ceil() as i32)
+            }
+        };
+        let minerals = wait(self.minerals, unit.mineral_price(), self.mineral_rate)?;
+        let gas = wait(self.gas, unit.gas_price(), self.gas_rate)?;
+        Some(minerals.max(gas))
+    }
+
     pub fn minerals(&self) -> i32 {
         self.minerals
     }
@@ -76,10 +242,139 @@ impl Counts {
     pub fn supply_max(&self) -> i32 {
         self.supply_max
     }
+    /// supply in use including what we've queued this frame
     pub fn supply_used(&self) -> i32 {
-        self.supply_used
+        self.supply_used + self.supply_reserved
+    }
+    pub fn supply_pending(&self) -> i32 {
+        self.supply_pending
+    }
+    pub fn income(&self) -> (f32, f32) {
+        (self.mineral_rate, self.gas_rate)
     }
     pub fn frame(&self) -> i32 {
         self.frame
     }
 }
+
+/// supply from overlords still in their eggs and hatcheries still being built,
+/// given (unit type, is completed, build type) for each of our units
+fn pending_supply(units: impl Iterator<Item = (UnitType, bool, UnitType)>) -> i32 {
+    units
+        .map(|(unit_type, completed, build_type)| match unit_type {
+            UnitType::Zerg_Egg => build_type.supply_provided(),
+            _ if !completed => unit_type.supply_provided(),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_income_estimate() {
+        let mut income = Income::new();
+        income.record(0, 50, 0, 8, 0);
+        let (minerals, gas) = income.rates();
+        assert_eq!(
+            minerals,
+            8.0 * MINERALS_PER_DRONE_FRAME,
+            "estimated from drones"
+        );
+        assert_eq!(gas, 0.0, "no gas drones");
+    }
+
+    #[test]
+    fn test_income_observed() {
+        let mut income = Income::new();
+        for frame in 0..=INCOME_WINDOW * 2 {
+            // exactly one mineral per frame with no drones
+            income.record(frame, frame, frame / 2, 0, 0);
+        }
+        assert_eq!(
+            income.samples.len(),
+            INCOME_WINDOW as usize + 1,
+            "old samples dropped"
+        );
+        let (minerals, gas) = income.rates();
+        assert_eq!(minerals, 0.5, "averaged with a zero estimate");
+        assert_eq!(gas, 0.25);
+    }
+
+    #[test]
+    fn test_projected_bank() {
+        let c = Counts::new_fake_bank(100, 0, 18, 18).with_income(0.5, 0.25);
+        assert_eq!(c.projected_bank(0), (100, 0));
+        assert_eq!(c.projected_bank(100), (150, 25));
+    }
+
+    #[test]
+    fn test_frames_until_affordable() {
+        let c = Counts::new_fake_bank(100, 0, 18, 18).with_income(0.5, 0.0);
+        assert_eq!(c.frames_until_affordable(UnitType::Zerg_Drone), Some(0));
+        assert_eq!(
+            c.frames_until_affordable(UnitType::Zerg_Spawning_Pool),
+            Some(200),
+            "100 more minerals at 0.5 per frame"
+        );
+        assert_eq!(
+            c.frames_until_affordable(UnitType::Zerg_Spire),
+            None,
+            "no gas income"
+        );
+
+        let c = c.with_income(0.5, 0.125);
+        assert_eq!(
+            c.frames_until_affordable(UnitType::Zerg_Spire),
+            Some(1_600),
+            "gas is the bottleneck"
+        );
+    }
+
+    #[test]
+    fn test_pending_supply() {
+        let none = UnitType::None;
+        let units = vec![
+            // finished units provide supply through the game's total
+            (UnitType::Zerg_Overlord, true, none),
+            (UnitType::Zerg_Hatchery, true, none),
+            (UnitType::Zerg_Egg, false, UnitType::Zerg_Overlord),
+            (UnitType::Zerg_Egg, false, UnitType::Zerg_Drone),
+            (UnitType::Zerg_Hatchery, false, none),
+            // a lair in progress is still a finished hatchery
+            (UnitType::Zerg_Hatchery, true, UnitType::Zerg_Lair),
+        ];
+        assert_eq!(
+            pending_supply(units.into_iter()),
+            UnitType::Zerg_Overlord.supply_provided() + UnitType::Zerg_Hatchery.supply_provided()
+        );
+    }
+
+    #[test]
+    fn test_bought_supply() {
+        let mut c = Counts::new_fake_bank(500, 0, 18, 18);
+        c.bought(UnitType::Zerg_Drone);
+        assert_eq!(c.supply_used(), 20, "reserved for the drone");
+        assert_eq!(c.supply_max(), 18, "no more supply until it's finished");
+        c.bought(UnitType::Zerg_Overlord);
+        assert_eq!(c.supply_pending(), 16, "overlord on the way");
+        assert_eq!(c.minerals(), 350);
+
+        c.reserve(UnitType::Zerg_Hatchery);
+        c.reserve(UnitType::Zerg_Lair);
+        assert_eq!(c.supply_pending(), 16, "planned, not coming yet");
+        assert_eq!(c.minerals(), 350 - 300 - 150);
+    }
+
+    #[test]
+    fn test_morphed_supply() {
+        let mut c = Counts::new_fake_bank(500, 500, 40, 60);
+        c.morphed(UnitType::Zerg_Hydralisk, UnitType::Zerg_Lurker);
+        assert_eq!(c.supply_used(), 42, "a lurker needs one more than a hydra");
+        assert_eq!(c.minerals(), 450);
+        c.morphed(UnitType::Zerg_Mutalisk, UnitType::Zerg_Guardian);
+        assert_eq!(c.supply_used(), 42, "same supply as the muta");
+    }
+}
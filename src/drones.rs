@@ -9,6 +9,8 @@ pub(crate) enum DroneRole {
     Gas,
     Scout,
     Builder,
+    // mining out minerals that are in the way
+    Clearing,
     Idle,
     Defending,
     Fleeing,
@@ -33,11 +35,9 @@ impl DroneManager {
 
     pub fn on_frame(&mut self, game: &Game) {
         // check for idle drones in temporary states and reassign them to
-        // idle so they can get back to work
+        // idle so they can get back to work, fleeing drones are released
+        // once they're safe
         let mut to_check: Vec<&usize> = vec![];
-        if let Some(fleeing) = self.by_roles.get(&DroneRole::Fleeing) {
-            to_check.extend(fleeing);
-        }
         if let Some(defending) = self.by_roles.get(&DroneRole::Defending) {
             to_check.extend(defending);
         }
@@ -134,6 +134,11 @@ impl DroneManager {
         self.add_to_role_map(id, saved);
     }
 
+    #[cfg(test)]
+    pub fn add_fake(&mut self, id: UnitId) {
+        self.insert(id);
+    }
+
     fn insert(&mut self, id: UnitId) {
         self.by_id.insert(id, DroneRole::Idle);
         self.add_to_role_map(id, DroneRole::Idle);
@@ -0,0 +1,160 @@
+use crate::sim::{army_value, SimUnit};
+
+// drones worth putting on each patch and geyser
+const DRONES_PER_PATCH: i32 = 2;
+const DRONES_PER_GEYSER: i32 = 3;
+// extra drones to carry over to the next base
+const EXPANSION_DRONES: i32 = 4;
+// any more than this and the army runs out of supply
+const MAX_DRONES: i32 = 75;
+// stop making drones this close to max supply
+const MAXED_SUPPLY: i32 = 380;
+
+// before this an army showing up is a rush
+const RUSH_FRAMES: i32 = 24 * 60 * 5;
+
+/// How dangerous the enemy army we've seen is compared to ours
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Threat {
+    Safe,
+    Contested,
+    Danger,
+}
+
+impl Threat {
+    pub fn assess(theirs: &[SimUnit], ours: &[SimUnit]) -> Self {
+        Threat::from_values(army_value(theirs), army_value(ours))
+    }
+
+    fn from_values(theirs: i32, ours: i32) -> Self {
+        if theirs == 0 || theirs * 2 <= ours {
+            Threat::Safe
+        } else if theirs <= ours {
+            Threat::Contested
+        } else {
+            Threat::Danger
+        }
+    }
+}
+
+/// What we've got to work with
+#[derive(Debug, Default)]
+pub(crate) struct Economy {
+    /// mineral patches at our bases
+    pub patches: i32,
+    /// extractors we're mining from
+    pub geysers: i32,
+    pub drones: i32,
+    pub supply_used: i32,
+    pub frame: i32,
+}
+
+/// How many drones we want, from how many we can mine with, how scary the
+/// enemy is and how far into the game we are
+pub(crate) fn drone_target(eco: &Economy, threat: Threat) -> i32 {
+    let saturated = eco.patches * DRONES_PER_PATCH + eco.geysers * DRONES_PER_GEYSER;
+    let wanted = (saturated + EXPANSION_DRONES).min(MAX_DRONES);
+    let early = eco.frame < RUSH_FRAMES;
+    let target = match threat {
+        Threat::Safe => wanted,
+        Threat::Contested if !early => wanted * 3 / 4,
+        // an even fight early is a rush, hold on to what we have until it's held
+        Threat::Contested | Threat::Danger => eco.drones.min(wanted),
+    };
+    if eco.supply_used >= MAXED_SUPPLY {
+        // supply goes to the army from here
+        target.min(eco.drones)
+    } else {
+        target
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_threat() {
+        assert_eq!(Threat::from_values(0, 0), Threat::Safe, "nothing seen");
+        assert_eq!(Threat::from_values(200, 400), Threat::Safe);
+        assert_eq!(Threat::from_values(300, 400), Threat::Contested);
+        assert_eq!(Threat::from_values(500, 400), Threat::Danger);
+    }
+
+    #[test]
+    fn test_safe_expansion() {
+        let one_base = Economy {
+            patches: 8,
+            geysers: 1,
+            drones: 12,
+            supply_used: 40,
+            frame: 24 * 60 * 3,
+        };
+        assert_eq!(drone_target(&one_base, Threat::Safe), 23);
+
+        let natural = Economy {
+            patches: 16,
+            drones: 23,
+            ..one_base
+        };
+        assert_eq!(
+            drone_target(&natural, Threat::Safe),
+            39,
+            "drones for the new base"
+        );
+
+        let five_bases = Economy {
+            patches: 40,
+            geysers: 4,
+            ..natural
+        };
+        assert_eq!(drone_target(&five_bases, Threat::Safe), MAX_DRONES);
+    }
+
+    #[test]
+    fn test_incoming_rush() {
+        let eco = Economy {
+            patches: 8,
+            geysers: 0,
+            drones: 10,
+            supply_used: 30,
+            frame: 24 * 60 * 3,
+        };
+        assert_eq!(drone_target(&eco, Threat::Danger), 10, "no more drones");
+        assert_eq!(
+            drone_target(&eco, Threat::Contested),
+            10,
+            "even an even fight early on"
+        );
+
+        let later = Economy {
+            patches: 24,
+            geysers: 2,
+            drones: 40,
+            frame: 24 * 60 * 12,
+            ..eco
+        };
+        assert_eq!(
+            drone_target(&later, Threat::Contested),
+            (24 * 2 + 2 * 3 + EXPANSION_DRONES) * 3 / 4,
+            "keep droning, but not all the way"
+        );
+        assert_eq!(drone_target(&later, Threat::Danger), 40);
+    }
+
+    #[test]
+    fn test_maxed_out() {
+        let eco = Economy {
+            patches: 32,
+            geysers: 4,
+            drones: 60,
+            supply_used: 392,
+            frame: 24 * 60 * 20,
+        };
+        assert_eq!(
+            drone_target(&eco, Threat::Safe),
+            60,
+            "remaining supply is for the army"
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use rsbwapi::UnitType;
+use std::collections::HashMap;
+
+// everyone starts with this and four workers
+const START_MINERALS: i32 = 50;
+const START_WORKERS: i32 = 4;
+// how often they can make another worker and how many a base can use
+const WORKER_FRAMES: i32 = 300;
+const WORKERS_PER_BASE: i32 = 20;
+// what a mining worker and a saturated geyser bring in each frame
+const MINERALS_PER_WORKER_FRAME: f32 = 0.045;
+const GAS_PER_REFINERY_FRAME: f32 = 0.16;
+// each hatchery makes a larva this often
+const LARVA_FRAMES: i32 = 342;
+
+// the unit a production building makes if we haven't seen what it's making
+const BASIC_UNITS: [(UnitType, UnitType); 4] = [
+    (UnitType::Protoss_Gateway, UnitType::Protoss_Zealot),
+    (UnitType::Terran_Barracks, UnitType::Terran_Marine),
+    (UnitType::Terran_Factory, UnitType::Terran_Vulture),
+    (UnitType::Zerg_Spawning_Pool, UnitType::Zerg_Zergling),
+];
+
+/// How many of a unit type the enemy could have
+#[derive(Debug, PartialEq)]
+pub(crate) struct ArmyEstimate {
+    pub unit_type: UnitType,
+    /// what we've seen, they have at least this many
+    pub seen: i32,
+    /// all their production and money going into this type
+    pub most: i32,
+}
+
+/// minerals and gas they could have mined by `frame` if they kept making
+/// workers, given their refineries and when we first saw them
+fn mined(frame: i32, bases: i32, refineries: &[i32]) -> (i32, i32) {
+    let cap = WORKERS_PER_BASE * bases.max(1);
+    let mut worker_frames = 0;
+    let mut t = 0;
+    while t < frame {
+        let workers = (START_WORKERS + t / WORKER_FRAMES).min(cap);
+        worker_frames += workers * (frame - t).min(WORKER_FRAMES);
+        t += WORKER_FRAMES;
+    }
+    let workers_made = (START_WORKERS + frame / WORKER_FRAMES).min(cap) - START_WORKERS;
+    let minerals = START_MINERALS + (worker_frames as f32 * MINERALS_PER_WORKER_FRAME) as i32
+        - workers_made * UnitType::Protoss_Probe.mineral_price();
+    let gas_frames: i32 = refineries.iter().map(|since| (frame - since).max(0)).sum();
+    (
+        minerals,
+        (gas_frames as f32 * GAS_PER_REFINERY_FRAME) as i32,
+    )
+}
+
+/// How many `unit_type` its producers could have made since they were
+/// finished, None if it isn't made in a building
+fn capacity(frame: i32, unit_type: UnitType, buildings: &[(UnitType, i32)]) -> Option<i32> {
+    let producer = unit_type.what_builds().0;
+    let per_batch = if unit_type.is_two_units_in_one_egg() {
+        2
+    } else {
+        1
+    };
+    let (producers, frames): (Vec<i32>, i32) = if producer == UnitType::Zerg_Larva {
+        let hatcheries = buildings
+            .iter()
+            .filter(|(t, _)| t.is_successor_of(UnitType::Zerg_Hatchery))
+            .map(|(_, since)| *since)
+            .collect();
+        (hatcheries, LARVA_FRAMES)
+    } else if producer.is_building() {
+        let producers = buildings
+            .iter()
+            .filter(|(t, _)| *t == producer)
+            .map(|(_, since)| *since)
+            .collect();
+        (producers, unit_type.build_time())
+    } else {
+        return None;
+    };
+    Some(
+        producers
+            .iter()
+            .map(|since| (frame - since).max(0) / frames.max(1) * per_batch)
+            .sum(),
+    )
+}
+
+/// A range for each unit type in the enemy army. `army` is what we've seen,
+/// `buildings` what they have now and about when each was finished,
+/// `timeline` every building they've made
+pub(crate) fn estimate_army(
+    frame: i32,
+    army: &HashMap<UnitType, i32>,
+    buildings: &[(UnitType, i32)],
+    timeline: &[(UnitType, i32)],
+) -> Vec<ArmyEstimate> {
+    let bases = buildings
+        .iter()
+        .filter(|(t, _)| t.is_resource_depot())
+        .count() as i32;
+    let refineries: Vec<i32> = buildings
+        .iter()
+        .filter(|(t, _)| t.is_refinery())
+        .map(|(_, since)| *since)
+        .collect();
+    let (mut minerals, mut gas) = mined(frame, bases, &refineries);
+    // their main came for free
+    if let Some((main, _)) = timeline.iter().find(|(t, _)| t.is_resource_depot()) {
+        minerals += main.mineral_price();
+    }
+    for (unit_type, _) in timeline {
+        minerals -= unit_type.mineral_price();
+        gas -= unit_type.gas_price();
+    }
+    for (unit_type, count) in army {
+        minerals -= unit_type.mineral_price() * count;
+        gas -= unit_type.gas_price() * count;
+    }
+
+    let mut types: Vec<UnitType> = army.keys().copied().collect();
+    for (building, unit_type) in BASIC_UNITS {
+        let producing = buildings.iter().any(|(t, _)| *t == building);
+        let made_here = |t: &UnitType| t.what_builds().0 == unit_type.what_builds().0;
+        if producing && !types.iter().any(made_here) {
+            types.push(unit_type);
+        }
+    }
+    types.sort_by_key(|t| army.get(t).copied().unwrap_or(0));
+    types.reverse();
+    // the bank pays for all of them, not each
+    let share = types.len().max(1) as i32;
+    // and each producer makes one thing at a time
+    let producers: Vec<UnitType> = types.iter().map(|t| t.what_builds().0).collect();
+
+    types
+        .into_iter()
+        .map(|unit_type| {
+            let sharing = producers
+                .iter()
+                .filter(|p| **p == unit_type.what_builds().0)
+                .count() as i32;
+            let seen = army.get(&unit_type).copied().unwrap_or(0);
+            let per_batch = if unit_type.is_two_units_in_one_egg() {
+                2
+            } else {
+                1
+            };
+            let affordable = |have: i32, price: i32| match price {
+                0 => i32::MAX,
+                _ => have.max(0) / price * per_batch,
+            };
+            let affordable = affordable(minerals / share, unit_type.mineral_price())
+                .min(affordable(gas / share, unit_type.gas_price()));
+            let most = match capacity(frame, unit_type, buildings) {
+                Some(capacity) => (capacity / sharing.max(1)).min(seen.saturating_add(affordable)),
+                None => seen,
+            };
+            ArmyEstimate {
+                unit_type,
+                seen,
+                most: most.max(seen),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MINUTE: i32 = 24 * 60;
+
+    fn gateways(count: usize, since: i32) -> Vec<(UnitType, i32)> {
+        let mut buildings = vec![(UnitType::Protoss_Nexus, 0), (UnitType::Protoss_Pylon, 0)];
+        buildings.extend((0..count).map(|_| (UnitType::Protoss_Gateway, since)));
+        buildings
+    }
+
+    #[test]
+    fn test_mined() {
+        assert_eq!(mined(0, 1, &[]), (START_MINERALS, 0));
+        let (early, _) = mined(MINUTE, 1, &[]);
+        let (late, _) = mined(MINUTE * 6, 1, &[]);
+        assert!(late > early * 6, "more workers mine faster");
+        let (_, gas) = mined(MINUTE * 2, 1, &[MINUTE]);
+        assert_eq!(gas, (MINUTE as f32 * GAS_PER_REFINERY_FRAME) as i32);
+    }
+
+    #[test]
+    fn test_gateway_capacity() {
+        let buildings = gateways(3, MINUTE * 2);
+        let army = HashMap::from([(UnitType::Protoss_Zealot, 2)]);
+        let estimate = estimate_army(MINUTE * 6, &army, &buildings, &buildings);
+        assert_eq!(
+            estimate,
+            vec![ArmyEstimate {
+                unit_type: UnitType::Protoss_Zealot,
+                seen: 2,
+                most: 27,
+            }],
+            "three gates making zealots for four minutes"
+        );
+
+        let now = estimate_army(MINUTE * 2, &army, &buildings, &buildings);
+        assert_eq!(now[0].most, 2, "no time to make more");
+    }
+
+    #[test]
+    fn test_money_runs_out() {
+        let buildings = gateways(12, MINUTE * 2);
+        let army = HashMap::from([(UnitType::Protoss_Zealot, 2)]);
+        let estimate = estimate_army(MINUTE * 6, &army, &buildings, &buildings);
+        assert!(estimate[0].most > 27, "more gates make more");
+        assert!(
+            estimate[0].most < 12 * 9,
+            "can't pay for a zealot from every gate"
+        );
+    }
+
+    #[test]
+    fn test_shared_bank() {
+        let buildings = gateways(12, MINUTE * 2);
+        let zealots = HashMap::from([(UnitType::Protoss_Zealot, 2)]);
+        let alone = estimate_army(MINUTE * 6, &zealots, &buildings, &buildings);
+        // no dragoons yet, but the gates could be making them too
+        let both = HashMap::from([
+            (UnitType::Protoss_Zealot, 2),
+            (UnitType::Protoss_Dragoon, 0),
+        ]);
+        let estimate = estimate_army(MINUTE * 6, &both, &buildings, &buildings);
+        let zealot = estimate
+            .iter()
+            .find(|e| e.unit_type == UnitType::Protoss_Zealot)
+            .unwrap();
+        assert!(
+            zealot.most < alone[0].most,
+            "the money goes to one or the other"
+        );
+    }
+
+    #[test]
+    fn test_unseen_units() {
+        let buildings = vec![
+            (UnitType::Terran_Command_Center, 0),
+            (UnitType::Terran_Barracks, MINUTE * 2),
+        ];
+        let estimate = estimate_army(MINUTE * 3, &HashMap::new(), &buildings, &buildings);
+        assert_eq!(estimate.len(), 1);
+        assert_eq!(estimate[0].unit_type, UnitType::Terran_Marine);
+        assert_eq!(estimate[0].seen, 0);
+        assert!(estimate[0].most > 0, "the barracks has been busy");
+
+        let army = HashMap::from([(UnitType::Terran_Firebat, 1)]);
+        let estimate = estimate_army(MINUTE * 3, &army, &buildings, &buildings);
+        assert_eq!(estimate.len(), 1, "it's making firebats, not marines");
+    }
+
+    #[test]
+    fn test_larva() {
+        let buildings = vec![
+            (UnitType::Zerg_Hatchery, 0),
+            (UnitType::Zerg_Hatchery, 0),
+            (UnitType::Zerg_Spawning_Pool, MINUTE),
+        ];
+        let frame = LARVA_FRAMES * 3;
+        assert_eq!(
+            capacity(frame, UnitType::Zerg_Zergling, &buildings),
+            Some(12),
+            "two hatcheries, three larva each"
+        );
+        assert_eq!(
+            capacity(frame, UnitType::Zerg_Hydralisk, &buildings),
+            Some(6)
+        );
+        assert_eq!(capacity(frame, UnitType::Zerg_Lurker, &buildings), None);
+
+        let army = HashMap::from([(UnitType::Zerg_Lurker, 1)]);
+        let estimate = estimate_army(frame, &army, &buildings, &buildings);
+        assert_eq!(estimate[0].most, 1, "no idea how many hydras they have");
+
+        let frame = MINUTE * 8;
+        let army = HashMap::from([(UnitType::Zerg_Zergling, 2), (UnitType::Zerg_Hydralisk, 1)]);
+        let estimate = estimate_army(frame, &army, &buildings, &buildings);
+        let lings = capacity(frame, UnitType::Zerg_Zergling, &buildings).unwrap();
+        assert_eq!(
+            estimate[0],
+            ArmyEstimate {
+                unit_type: UnitType::Zerg_Zergling,
+                seen: 2,
+                most: lings / 2,
+            },
+            "the larva make hydras too"
+        );
+    }
+}
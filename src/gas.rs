@@ -1,12 +1,11 @@
 use rsbwapi::*;
 use std::collections::HashMap;
 
-use crate::{
-    counts::Counts,
-    drones::{DroneManager, DroneRole},
-};
+use crate::drones::{DroneManager, DroneRole};
 
 const MAX_DRONES_PER_GAS: i8 = 3;
+// once off gas, stay off until we're this far short, a couple of trips
+const GAS_MARGIN: i32 = 16;
 
 struct MiningGas {
     drones: Vec<Unit>,
@@ -75,29 +74,24 @@ impl MiningGas {
 
 pub(crate) struct GasManager {
     gasses: HashMap<usize, MiningGas>,
+    drones_per_gas: i8,
 }
 
 impl GasManager {
     pub fn new() -> Self {
         GasManager {
             gasses: HashMap::new(),
+            drones_per_gas: MAX_DRONES_PER_GAS,
         }
     }
 
-    fn get_drones_per_gas(&self, counts: &Counts, drones: &DroneManager) -> i8 {
-        let on_mins = drones.count_role(&DroneRole::Minerals);
-        if counts.gas() > 1_000 && counts.minerals() < 500 {
-            if on_mins < 12 {
-                return 1;
-            }
-            if on_mins < 24 {
-                return 2;
-            }
-        }
-        MAX_DRONES_PER_GAS
+    /// extractors we're mining from
+    pub fn extractors(&self) -> usize {
+        self.gasses.len()
     }
 
-    pub fn on_frame(&mut self, game: &Game, counts: &Counts, drones: &mut DroneManager) {
+    /// keep extractors mined while we have a use for the gas
+    pub fn on_frame(&mut self, game: &Game, gas: i32, gas_needed: i32, drones: &mut DroneManager) {
         if let Some(self_) = game.self_() {
             let mining_gasses: Vec<_> = self_
                 .get_units()
@@ -111,11 +105,63 @@ impl GasManager {
                 .collect();
             self.gasses.clear();
 
-            let dpg = self.get_drones_per_gas(counts, drones);
+            self.drones_per_gas = drones_per_gas(self.drones_per_gas, gas, gas_needed);
             for mut mg in mining_gasses {
-                mg.ensure_mining(game, drones, dpg);
+                mg.ensure_mining(game, drones, self.drones_per_gas);
                 self.gasses.insert(mg.gas.get_id(), mg);
             }
         }
     }
 }
+
+/// Drones on each extractor, none once the bank covers what we need gas for
+/// and back on only once we're clearly short so they don't flip every trip
+fn drones_per_gas(current: i8, gas: i32, gas_needed: i32) -> i8 {
+    if gas >= gas_needed {
+        0
+    } else if current > 0 || gas < gas_needed - GAS_MARGIN {
+        MAX_DRONES_PER_GAS
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::research::Research;
+
+    #[test]
+    fn test_speed_opening() {
+        let on = MAX_DRONES_PER_GAS;
+        let speed = Research::new().upcoming_gas();
+        assert_eq!(drones_per_gas(on, 0, speed), on);
+        assert_eq!(drones_per_gas(on, 92, speed), on);
+        assert_eq!(
+            drones_per_gas(on, 100, speed),
+            0,
+            "off gas once speed is paid for"
+        );
+
+        // speed is researching and the lair is a ways off
+        assert_eq!(drones_per_gas(0, 4, 0), 0, "stay off gas");
+        let lair = UnitType::Zerg_Lair.gas_price();
+        assert_eq!(drones_per_gas(0, 4, lair), on, "back on gas");
+    }
+
+    #[test]
+    fn test_steady_near_threshold() {
+        // trips coming in and gas going out around what we need
+        let mut dpg = MAX_DRONES_PER_GAS;
+        let mut changes = 0;
+        for gas in [92, 100, 96, 104, 92, 88, 100, 94, 102, 90] {
+            let next = drones_per_gas(dpg, gas, 100);
+            if next != dpg {
+                changes += 1;
+            }
+            dpg = next;
+        }
+        assert_eq!(changes, 1, "off once and stays off");
+        assert_eq!(drones_per_gas(dpg, 80, 100), MAX_DRONES_PER_GAS);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::build_position::get_hatches;
+use rsbwapi::{Game, TilePosition, Unit, UnitId, UnitType};
+use std::collections::HashMap;
+
+// a hatchery stops making larva once it has this many
+const LARVA_CAP: usize = 3;
+// frames between larva spawns at a hatchery
+const LARVA_FRAMES: i32 = 342;
+// a hatch this many tiles further away is still preferred if its larva are capped
+const CAPPED_BONUS: i32 = 16;
+// how often we decide whether we're larva starved
+const STARVED_WINDOW: i32 = 24 * 20; // frames
+
+// how much money sitting in the bank with no larva counts as starved
+const STARVED_MINERALS: i32 = 300;
+
+#[derive(Debug)]
+struct HatchLarva {
+    tile: TilePosition,
+    larva: Vec<UnitId>,
+    last_spawn: i32,
+}
+
+impl HatchLarva {
+    fn is_capped(&self) -> bool {
+        self.larva.len() >= LARVA_CAP
+    }
+
+    /// when we expect this hatch to make its next larva
+    fn next_larva_frame(&self) -> Option<i32> {
+        if self.is_capped() {
+            None
+        } else {
+            Some(self.last_spawn + LARVA_FRAMES)
+        }
+    }
+}
+
+/// Keeps track of which larva belong to which hatchery so units can be
+/// spawned where they're needed, and notices when we need more hatcheries
+pub(crate) struct LarvaPlanner {
+    frame: i32,
+    hatches: HashMap<UnitId, HatchLarva>,
+    drone_rally: Option<TilePosition>,
+    army_rally: Option<TilePosition>,
+    window_start: i32,
+    starved_frames: i32,
+}
+
+impl LarvaPlanner {
+    pub fn new() -> Self {
+        LarvaPlanner {
+            frame: 0,
+            hatches: HashMap::new(),
+            drone_rally: None,
+            army_rally: None,
+            window_start: 0,
+            starved_frames: 0,
+        }
+    }
+
+    pub fn on_frame(&mut self, game: &Game) {
+        let hatches = get_hatches(game)
+            .into_iter()
+            .map(|h| (h.get_id(), h.get_tile_position()))
+            .collect();
+        let larva = game
+            .self_()
+            .map(|self_| self_.get_units())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|u: &Unit| {
+                u.get_type() == UnitType::Zerg_Larva
+                    && !u.is_morphing()
+                    && u.can_command().unwrap_or(false)
+            })
+            .map(|u| (u.get_id(), u.get_tile_position()))
+            .collect();
+        self.update(game.get_frame_count(), hatches, larva);
+    }
+
+    /// assign each larva to its closest hatchery and track spawn timers
+    fn update(
+        &mut self,
+        frame: i32,
+        hatches: Vec<(UnitId, TilePosition)>,
+        larva: Vec<(UnitId, TilePosition)>,
+    ) {
+        self.frame = frame;
+        let previous = std::mem::take(&mut self.hatches);
+        for (id, tile) in hatches {
+            // the timer only runs while there's room for more larva
+            let last_spawn = match previous.get(&id) {
+                Some(h) if !h.is_capped() => h.last_spawn,
+                _ => frame,
+            };
+            self.hatches.insert(
+                id,
+                HatchLarva {
+                    tile,
+                    larva: vec![],
+                    last_spawn,
+                },
+            );
+        }
+        for (larva_id, larva_tile) in larva {
+            let closest = self
+                .hatches
+                .values_mut()
+                .min_by_key(|h| h.tile.chebyshev_distance(larva_tile));
+            if let Some(hatch) = closest {
+                hatch.larva.push(larva_id);
+            }
+        }
+        for (id, hatch) in self.hatches.iter_mut() {
+            let prev_count = previous.get(id).map_or(0, |h| h.larva.len());
+            if hatch.larva.len() > prev_count {
+                hatch.last_spawn = frame;
+            }
+        }
+    }
+
+    pub fn set_rallies(&mut self, drones: Option<TilePosition>, army: Option<TilePosition>) {
+        self.drone_rally = drones;
+        self.army_rally = army;
+    }
+
+    pub fn available(&self) -> usize {
+        self.hatches.values().map(|h| h.larva.len()).sum()
+    }
+
+    /// larva we expect per frame from all of our hatcheries
+    pub fn larva_rate(&self) -> f32 {
+        self.hatches.len() as f32 / LARVA_FRAMES as f32
+    }
+
+    /// frames until the next larva pops at any hatchery
+    pub fn frames_until_larva(&self) -> Option<i32> {
+        self.hatches
+            .values()
+            .filter_map(HatchLarva::next_larva_frame)
+            .min()
+            .map(|f| (f - self.frame).max(0))
+    }
+
+    /// Take a larva from the hatch closest to where this type of unit is
+    /// needed, preferring hatcheries that have stopped making larva
+    pub fn take_for(&mut self, unit_type: UnitType) -> Option<UnitId> {
+        let rally = if unit_type.is_worker() {
+            self.drone_rally
+        } else if unit_type == UnitType::Zerg_Overlord {
+            None
+        } else {
+            self.army_rally
+        };
+        let hatch = self
+            .hatches
+            .values_mut()
+            .filter(|h| !h.larva.is_empty())
+            .min_by_key(|h| {
+                let distance = rally.map_or(0, |r| h.tile.chebyshev_distance(r) as i32);
+                let bonus = if h.is_capped() { CAPPED_BONUS } else { 0 };
+                (distance - bonus, -(h.larva.len() as i32))
+            })?;
+        hatch.larva.pop()
+    }
+
+    /// Called every frame, returns true once per window if we've spent most
+    /// of it with money in the bank and nothing to spend it on
+    pub fn is_starved(&mut self, minerals: i32) -> bool {
+        if self.available() == 0 && minerals >= STARVED_MINERALS {
+            self.starved_frames += 1;
+        }
+        if self.frame < self.window_start + STARVED_WINDOW {
+            return false;
+        }
+        let starved = self.starved_frames * 2 > self.frame - self.window_start;
+        if starved {
+            println!(
+                "frame {} :: larva starved for {} of the last {} frames",
+                self.frame,
+                self.starved_frames,
+                self.frame - self.window_start
+            );
+        }
+        self.window_start = self.frame;
+        self.starved_frames = 0;
+        starved
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+
+    #[test]
+    fn test_larva_per_hatch() {
+        let mut lp = LarvaPlanner::new();
+        let hatches = vec![(1, tp(10, 10)), (2, tp(50, 50))];
+        lp.update(
+            0,
+            hatches.clone(),
+            vec![(10, tp(11, 12)), (11, tp(9, 12)), (12, tp(51, 52))],
+        );
+        assert_eq!(lp.available(), 3);
+        assert_eq!(lp.hatches[&1].larva.len(), 2, "two at the main");
+        assert_eq!(lp.hatches[&2].larva.len(), 1, "one at the natural");
+
+        lp.set_rallies(Some(tp(48, 48)), None);
+        assert_eq!(
+            lp.take_for(UnitType::Zerg_Drone),
+            Some(12),
+            "drones spawn near the rally"
+        );
+        assert_eq!(
+            lp.take_for(UnitType::Zerg_Drone),
+            Some(11),
+            "then wherever there's larva"
+        );
+    }
+
+    #[test]
+    fn test_prefer_capped() {
+        let mut lp = LarvaPlanner::new();
+        lp.update(
+            0,
+            vec![(1, tp(10, 10)), (2, tp(20, 10))],
+            vec![
+                (10, tp(10, 11)),
+                (11, tp(10, 11)),
+                (12, tp(10, 11)),
+                (13, tp(20, 11)),
+            ],
+        );
+        lp.set_rallies(None, Some(tp(22, 10)));
+        assert_eq!(
+            lp.hatches[&1].larva.len(),
+            LARVA_CAP,
+            "main is capped on larva"
+        );
+        let taken = lp.take_for(UnitType::Zerg_Zergling);
+        assert!(
+            lp.hatches[&1].larva.len() < LARVA_CAP,
+            "used a capped larva first, took {:?}",
+            taken
+        );
+    }
+
+    #[test]
+    fn test_larva_timer() {
+        let mut lp = LarvaPlanner::new();
+        let hatches = vec![(1, tp(10, 10))];
+        lp.update(0, hatches.clone(), vec![(10, tp(10, 11))]);
+        assert_eq!(lp.frames_until_larva(), Some(LARVA_FRAMES));
+
+        lp.update(100, hatches.clone(), vec![(10, tp(10, 11))]);
+        assert_eq!(lp.frames_until_larva(), Some(LARVA_FRAMES - 100));
+
+        lp.update(
+            200,
+            hatches.clone(),
+            vec![(10, tp(10, 11)), (11, tp(10, 11))],
+        );
+        assert_eq!(
+            lp.frames_until_larva(),
+            Some(LARVA_FRAMES),
+            "timer restarts on a new larva"
+        );
+
+        let capped = vec![(10, tp(10, 11)), (11, tp(10, 11)), (12, tp(10, 11))];
+        lp.update(500, hatches.clone(), capped);
+        assert_eq!(lp.frames_until_larva(), None, "no more larva when capped");
+
+        lp.update(600, hatches.clone(), vec![(10, tp(10, 11))]);
+        assert_eq!(
+            lp.frames_until_larva(),
+            Some(LARVA_FRAMES),
+            "timer starts again after larva are used"
+        );
+    }
+
+    #[test]
+    fn test_starved() {
+        let mut lp = LarvaPlanner::new();
+        let hatches = vec![(1, tp(10, 10))];
+        for frame in 0..STARVED_WINDOW {
+            lp.update(frame, hatches.clone(), vec![]);
+            assert!(!lp.is_starved(500), "not decided yet");
+        }
+        lp.update(STARVED_WINDOW, hatches.clone(), vec![]);
+        assert!(lp.is_starved(500), "no larva and lots of money");
+
+        for frame in STARVED_WINDOW + 1..STARVED_WINDOW * 2 + 1 {
+            lp.update(frame, hatches.clone(), vec![]);
+            assert!(!lp.is_starved(50), "broke, not starved");
+        }
+    }
+}
@@ -1,11 +1,28 @@
+mod bases;
 pub mod bot;
 mod build_order;
 mod build_position;
+mod builder;
+mod composition;
 mod counts;
 mod drones;
+mod economy;
+mod estimate;
 mod gas;
+mod larva;
 mod minerals;
+mod neutrals;
+mod overlords;
+mod production;
+mod research;
+mod scout_micro;
 mod scouting;
 mod seen;
 mod sim;
+mod strategy;
+mod supply;
+#[cfg(test)]
+mod test_util;
+mod threat;
 mod unit_comp;
+mod worker_defense;
@@ -1,111 +1,800 @@
 use crate::{
     build_position::get_hatches,
-    counts::Counts,
     drones::{DroneManager, DroneRole},
+    neutrals::is_blocking_minerals,
 };
-use rsbwapi::{Game, Unit, UnitId, UnitType};
-use std::collections::{HashMap, HashSet};
+use rsbwapi::{Game, Order, Position, TilePosition, UnitId, UnitType};
+use std::collections::HashMap;
+
+// patches this close to a hatchery are mined from it
+const BASE_RADIUS: i32 = 8; // tiles
+const HATCH_MIDDLE: TilePosition = TilePosition { x: 2, y: 1 };
+// drones per patch before adding more stops paying off
+const OPTIMAL_PER_PATCH: usize = 2;
+const MAX_PER_PATCH: usize = 3;
+// patches this close to the long distance base's geyser are part of it
+const LONG_DISTANCE_RADIUS: u32 = 12;
+
+// don't pull the whole mineral line across the map in one frame
+const TRANSFERS_PER_FRAME: usize = 4;
+// wait this long between gather commands to a drone
+const REGATHER_FRAMES: i32 = 8;
+// longer than a drone waits for and mines a patch
+const TRAPPED_FRAMES: i32 = 24 * 8;
+
+// give a mineral walk this long to get clear before going back to work
+const ESCAPE_FRAMES: i32 = 24 * 3;
+
+/// What the mineral manager needs from the game
+trait MiningGame {
+    fn frame(&self) -> i32;
+    /// tiles of our finished hatcheries
+    fn bases(&self) -> Vec<TilePosition>;
+    /// mineral patches we know about
+    fn patches(&self) -> Vec<(UnitId, TilePosition)>;
+    /// the drone is still around and working
+    fn is_mining(&self, drone: UnitId) -> bool;
+    fn gather(&self, drone: UnitId, patch: UnitId) -> bool;
+    /// the patch a drone is heading to or mining
+    fn gather_target(&self, drone: UnitId) -> Option<UnitId>;
+    fn is_carrying(&self, drone: UnitId) -> bool;
+    fn position(&self, drone: UnitId) -> Option<Position>;
+    /// heading somewhere rather than mining or waiting its turn
+    fn is_walking(&self, drone: UnitId) -> bool;
+    /// the one base we can safely mine from without a hatch there
+    fn long_distance_base(&self) -> Option<TilePosition>;
+}
+
+struct GameMining<'a> {
+    game: &'a Game,
+    long_distance: Option<TilePosition>,
+}
+
+impl<'a> MiningGame for GameMining<'a> {
+    fn frame(&self) -> i32 {
+        self.game.get_frame_count()
+    }
+
+    fn bases(&self) -> Vec<TilePosition> {
+        get_hatches(self.game)
+            .into_iter()
+            .map(|h| h.get_tile_position())
+            .collect()
+    }
+
+    fn patches(&self) -> Vec<(UnitId, TilePosition)> {
+        self.game
+            .get_minerals()
+            .into_iter()
+            .filter(|m| {
+                m.exists() && !is_blocking_minerals(m.get_type(), m.get_initial_resources())
+            })
+            .map(|m| (m.get_id(), m.get_tile_position()))
+            .collect()
+    }
+
+    fn is_mining(&self, drone: UnitId) -> bool {
+        self.game.get_unit(drone).map_or(false, |d| {
+            d.exists() && d.get_type() == UnitType::Zerg_Drone && !d.is_morphing() && !d.is_idle()
+        })
+    }
+
+    fn gather(&self, drone: UnitId, patch: UnitId) -> bool {
+        match (self.game.get_unit(drone), self.game.get_unit(patch)) {
+            (Some(d), Some(p)) => {
+                let r = d.gather(&p);
+                if r.is_err() {
+                    println!(
+                        "frame {} :: worker {} mining failed {:?}",
+                        self.frame(),
+                        drone,
+                        r
+                    );
+                }
+                r.is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    fn gather_target(&self, drone: UnitId) -> Option<UnitId> {
+        self.game
+            .get_unit(drone)
+            .and_then(|d| d.get_order_target())
+            .map(|t| t.get_id())
+    }
+
+    fn is_carrying(&self, drone: UnitId) -> bool {
+        self.game
+            .get_unit(drone)
+            .map_or(false, |d| d.is_carrying_minerals())
+    }
+
+    fn position(&self, drone: UnitId) -> Option<Position> {
+        self.game.get_unit(drone).map(|d| d.get_position())
+    }
+
+    fn is_walking(&self, drone: UnitId) -> bool {
+        self.game.get_unit(drone).map_or(false, |d| {
+            matches!(
+                d.get_order(),
+                Order::MoveToMinerals | Order::ReturnMinerals | Order::Move
+            )
+        })
+    }
+
+    fn long_distance_base(&self) -> Option<TilePosition> {
+        self.long_distance
+    }
+}
+
+struct Patch {
+    tile: TilePosition,
+    // the hatch we mine this from, None for long distance mining
+    base: Option<TilePosition>,
+    // squared tiles from the closest hatch
+    distance: i32,
+    drones: Vec<UnitId>,
+}
+
+impl Patch {
+    /// lower is a better place for another drone, None if it's full
+    fn priority(&self) -> Option<i32> {
+        let mining = self.drones.len();
+        match self.base {
+            Some(_) if mining < OPTIMAL_PER_PATCH => Some(0),
+            Some(_) if mining < MAX_PER_PATCH => Some(1),
+            None if mining < OPTIMAL_PER_PATCH => Some(2),
+            _ => None,
+        }
+    }
+
+    /// drones here would be better off at an unsaturated base
+    fn has_spare_drones(&self) -> bool {
+        match self.base {
+            Some(_) => self.drones.len() > OPTIMAL_PER_PATCH,
+            None => !self.drones.is_empty(),
+        }
+    }
+}
+
+/// The closest patch in the same mineral line as `stuck` that's past it
+/// going the way `from` was heading, somewhere to mineral walk to
+fn escape_patch(patches: &HashMap<UnitId, Patch>, stuck: UnitId, from: Position) -> Option<UnitId> {
+    let own = patches.get(&stuck)?;
+    let target = own.tile.to_position();
+    let (dx, dy) = (target.x - from.x, target.y - from.y);
+    patches
+        .iter()
+        .filter(|(id, p)| **id != stuck && p.base == own.base)
+        .map(|(id, p)| (id, p.tile.to_position()))
+        .filter(|(_, to)| {
+            (to.x - from.x) * dx + (to.y - from.y) * dy > 0
+                && to.distance_squared(from) > target.distance_squared(from)
+        })
+        .min_by_key(|(id, to)| (to.distance_squared(from), **id))
+        .map(|(id, _)| *id)
+}
+
+/// Keeps track of when to next check on a drone and whether it's stuck
+struct Miner {
+    next_command: i32,
+    last_position: Option<Position>,
+    still_since: i32,
+}
 
 pub(crate) struct MineralManager {
-    patch_to_drones: HashMap<UnitId, Vec<UnitId>>,
+    patches: HashMap<UnitId, Patch>,
+    miners: HashMap<UnitId, Miner>,
 }
 
 impl MineralManager {
     pub fn new() -> Self {
         MineralManager {
-            patch_to_drones: HashMap::new(),
+            patches: HashMap::new(),
+            miners: HashMap::new(),
         }
     }
 
-    fn get_visible_minerals(&self, game: &Game) -> HashSet<usize> {
-        let mut patches = HashSet::new();
-        for base in get_hatches(game) {
-            let mid_of_base = base.get_tile_position() + base.get_type().tile_size() / 2;
-            let mins = game
-                .get_units_in_radius(mid_of_base.to_position(), 8 * 32, |u: &Unit| {
-                    u.exists() && u.get_type().is_mineral_field()
-                })
-                .into_iter()
-                .map(|m| m.get_id());
-            patches.extend(mins);
+    /// patches at our hatcheries, not counting long distance mining
+    pub fn owned_patches(&self) -> usize {
+        self.patches.values().filter(|p| p.base.is_some()).count()
+    }
+
+    /// The hatch whose patches have the fewest drones on them
+    pub fn least_saturated_base(&self) -> Option<TilePosition> {
+        let mut bases: HashMap<TilePosition, (usize, usize)> = HashMap::new();
+        for patch in self.patches.values() {
+            if let Some(base) = patch.base {
+                let (patches, mining) = bases.entry(base).or_insert((0, 0));
+                *patches += 1;
+                *mining += patch.drones.len();
+            }
         }
-        patches
+        bases
+            .into_iter()
+            .min_by(|(_, (p1, m1)), (_, (p2, m2))| {
+                (*m1 as f32 / *p1 as f32)
+                    .partial_cmp(&(*m2 as f32 / *p2 as f32))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(base, _)| base)
     }
 
-    pub fn on_frame(&mut self, game: &Game, counts: &Counts, drones: &mut DroneManager) {
-        let patches = self.get_visible_minerals(game);
-        // prune non-current patches (mined out, base destroyed, etc)
-        self.patch_to_drones.retain(|id, patch_drones| {
-            if !patches.contains(&id) {
-                patch_drones.iter().for_each(|drone| drones.idle(*drone));
-                false
-            } else {
-                true
+    /// The closest patch to each of our hatcheries
+    pub fn mineral_lines(&self) -> Vec<(UnitId, TilePosition)> {
+        let mut lines: HashMap<TilePosition, (i32, UnitId, TilePosition)> = HashMap::new();
+        for (id, patch) in self.patches.iter() {
+            if let Some(base) = patch.base {
+                let closest = lines
+                    .entry(base)
+                    .or_insert((patch.distance, *id, patch.tile));
+                if (patch.distance, *id) < (closest.0, closest.1) {
+                    *closest = (patch.distance, *id, patch.tile);
+                }
             }
-        });
-        let least_mined_patch = self
-            .patch_to_drones
-            .values()
-            .map(|d| d.len())
-            .min()
-            .unwrap_or(0);
-
-        for id in patches {
-            if let Some(patch) = game.get_unit(id) {
-                let mining = self.patch_to_drones.entry(id).or_insert_with(Vec::new);
-                // prune drones that aren't mining anymore
-                mining.retain(|drone_id| {
-                    if let Some(drone) = game.get_unit(*drone_id) {
-                        if drones.check_role(drone_id) != DroneRole::Minerals {
-                            return false;
-                        }
-                        let is_mining = drone.exists()
-                            && drone.get_type() == UnitType::Zerg_Drone
-                            && !drone.is_morphing()
-                            && !drone.is_idle();
-                        if !is_mining {
-                            drones.idle(*drone_id);
-                        }
-                        is_mining
-                    } else {
-                        false
+        }
+        lines
+            .into_values()
+            .map(|(_, id, tile)| (id, tile))
+            .collect()
+    }
+
+    /// `long_distance` is where drones can go once our bases are full
+    pub fn on_frame(
+        &mut self,
+        game: &Game,
+        drones: &mut DroneManager,
+        long_distance: Option<TilePosition>,
+    ) {
+        self.update(
+            &GameMining {
+                game,
+                long_distance,
+            },
+            drones,
+        );
+    }
+
+    fn update(&mut self, game: &impl MiningGame, drones: &mut DroneManager) {
+        self.refresh_patches(game, drones);
+        self.transfer(game, drones);
+        self.assign_idle(game, drones);
+        self.keep_mining(game);
+    }
+
+    /// match patches up with our current hatcheries, releasing drones from
+    /// patches that are gone (mined out, base destroyed, etc)
+    fn refresh_patches(&mut self, game: &impl MiningGame, drones: &mut DroneManager) {
+        let bases = game.bases();
+        let long_distance = game.long_distance_base();
+        let mut previous = std::mem::take(&mut self.patches);
+        if !bases.is_empty() {
+            for (id, tile) in game.patches() {
+                let closest = bases
+                    .iter()
+                    .map(|b| (*b + HATCH_MIDDLE, *b))
+                    .min_by_key(|(middle, _)| middle.distance_squared(tile));
+                if let Some((middle, base)) = closest {
+                    let in_range = middle.chebyshev_distance(tile) as i32 <= BASE_RADIUS;
+                    let safe = long_distance.map_or(false, |b| {
+                        b.chebyshev_distance(tile) <= LONG_DISTANCE_RADIUS
+                    });
+                    if !in_range && !safe {
+                        continue;
                     }
+                    let mining = previous.remove(&id).map_or(vec![], |p| p.drones);
+                    let patch = Patch {
+                        tile,
+                        base: Some(base).filter(|_| in_range),
+                        distance: middle.distance_squared(tile) as i32,
+                        drones: mining,
+                    };
+                    self.patches.insert(id, patch);
+                }
+            }
+        }
+        for gone in previous.into_values() {
+            gone.drones.iter().for_each(|drone| drones.idle(*drone));
+        }
+
+        // prune drones that aren't mining anymore
+        for patch in self.patches.values_mut() {
+            patch.drones.retain(|drone| {
+                if drones.check_role(drone) != DroneRole::Minerals {
+                    return false;
+                }
+                let is_mining = game.is_mining(*drone);
+                if !is_mining {
+                    drones.idle(*drone);
+                }
+                is_mining
+            });
+        }
+    }
+
+    /// Send drones back to their own patch when they wander off to another
+    /// one, and mineral walk them out of wherever they're stuck
+    fn keep_mining(&mut self, game: &impl MiningGame) {
+        let frame = game.frame();
+        let miners = &mut self.miners;
+        miners.retain(|drone, _| self.patches.values().any(|p| p.drones.contains(drone)));
+        for (patch_id, patch) in self.patches.iter() {
+            for drone in patch.drones.iter() {
+                let miner = miners.entry(*drone).or_insert(Miner {
+                    next_command: frame,
+                    last_position: None,
+                    still_since: frame,
                 });
-                // saturate patches evenly
-                if mining.len() > least_mined_patch {
-                    // worker transfer
-                    if mining.len() > 2 && least_mined_patch == 0 {
-                        let transfered = mining.swap_remove(0);
-                        drones.idle(transfered);
-                    }
+                let position = game.position(*drone);
+                // standing still to mine or wait for the patch isn't stuck
+                if position != miner.last_position || !game.is_walking(*drone) {
+                    miner.last_position = position;
+                    miner.still_since = frame;
+                }
+                if frame < miner.next_command || game.is_carrying(*drone) {
                     continue;
                 }
-                // assign an idle drone to the patch
-                if let Some(drone) = drones
-                    .grab_and_assign(DroneRole::Minerals)
-                    .map(|id| game.get_unit(id))
-                    .flatten()
-                {
-                    let r = drone.gather(&patch);
-                    if r.is_ok() {
-                        mining.push(drone.get_id());
-                        println!(
-                            "frame {} :: worker {} gathering {:?}",
-                            counts.frame(),
-                            drone.get_id(),
-                            &patch
-                        );
-                    } else {
+                if frame - miner.still_since >= TRAPPED_FRAMES {
+                    // gathering ignores collisions, so head for a patch on
+                    // the other side of whatever's in the way
+                    let escape = position.and_then(|at| escape_patch(&self.patches, *patch_id, at));
+                    if let Some(escape) = escape {
                         println!(
-                            "frame {} :: worker {} mining failed {:?}, ",
-                            counts.frame(),
-                            drone.get_id(),
-                            r
+                            "frame {} :: worker {} is stuck, mineral walking to {}",
+                            frame, drone, escape
                         );
-                        drones.idle(drone.get_id());
+                        game.gather(*drone, escape);
+                        miner.next_command = frame + ESCAPE_FRAMES;
+                        miner.still_since = frame;
+                        continue;
                     }
                 }
+                // the engine sends drones to another patch when theirs is busy
+                if game.gather_target(*drone) != Some(*patch_id) {
+                    game.gather(*drone, *patch_id);
+                    miner.next_command = frame + REGATHER_FRAMES;
+                }
+            }
+        }
+    }
+
+    /// where the next drone should mine, None if everywhere's full
+    fn best_patch(&self) -> Option<(i32, UnitId)> {
+        self.patches
+            .iter()
+            .filter_map(|(id, p)| {
+                p.priority()
+                    .map(|priority| (priority, p.drones.len(), p.distance, *id))
+            })
+            .min()
+            .map(|(priority, _, _, id)| (priority, id))
+    }
+
+    /// Move drones off oversaturated and long distance patches to bases that
+    /// need them, e.g. once a new hatchery finishes
+    fn transfer(&mut self, game: &impl MiningGame, drones: &mut DroneManager) {
+        for _ in 0..TRANSFERS_PER_FRAME {
+            let to = match self.best_patch() {
+                Some((0, id)) => id,
+                _ => return,
+            };
+            // long distance miners come home first
+            let from = self
+                .patches
+                .iter()
+                .filter(|(_, p)| p.has_spare_drones())
+                .max_by_key(|(id, p)| (p.base.is_none(), p.drones.len(), **id))
+                .map(|(id, _)| *id);
+            let drone = match from.and_then(|id| self.patches.get_mut(&id)?.drones.pop()) {
+                Some(drone) => drone,
+                None => return,
+            };
+            if game.gather(drone, to) {
+                println!(
+                    "frame {} :: transferring worker {} from {:?} to {:?}",
+                    game.frame(),
+                    drone,
+                    from,
+                    to
+                );
+                self.patches.entry(to).and_modify(|p| p.drones.push(drone));
+            } else {
+                drones.idle(drone);
             }
         }
     }
+
+    /// put idle drones to work wherever they're most useful
+    fn assign_idle(&mut self, game: &impl MiningGame, drones: &mut DroneManager) {
+        while let Some((_, patch)) = self.best_patch() {
+            let drone = match drones.grab_and_assign(DroneRole::Minerals) {
+                Some(drone) => drone,
+                None => return,
+            };
+            if game.gather(drone, patch) {
+                println!(
+                    "frame {} :: worker {} gathering {:?}",
+                    game.frame(),
+                    drone,
+                    patch
+                );
+                self.patches
+                    .entry(patch)
+                    .and_modify(|p| p.drones.push(drone));
+            } else {
+                drones.idle(drone);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+    use std::{
+        cell::RefCell,
+        collections::{BTreeMap, HashSet},
+    };
+
+    #[derive(Default)]
+    struct FakeMining {
+        frame: i32,
+        bases: Vec<TilePosition>,
+        long_distance: Option<TilePosition>,
+        patches: Vec<(UnitId, TilePosition)>,
+        dead: HashSet<UnitId>,
+        // where every drone is and whether it's trying to move
+        position: Position,
+        walking: bool,
+        gathers: RefCell<Vec<(UnitId, UnitId)>>,
+        targets: RefCell<HashMap<UnitId, UnitId>>,
+    }
+
+    impl MiningGame for FakeMining {
+        fn frame(&self) -> i32 {
+            self.frame
+        }
+        fn bases(&self) -> Vec<TilePosition> {
+            self.bases.clone()
+        }
+        fn patches(&self) -> Vec<(UnitId, TilePosition)> {
+            self.patches.clone()
+        }
+        fn is_mining(&self, drone: UnitId) -> bool {
+            !self.dead.contains(&drone)
+        }
+        fn gather(&self, drone: UnitId, patch: UnitId) -> bool {
+            self.gathers.borrow_mut().push((drone, patch));
+            self.targets.borrow_mut().insert(drone, patch);
+            true
+        }
+        fn gather_target(&self, drone: UnitId) -> Option<UnitId> {
+            self.targets.borrow().get(&drone).copied()
+        }
+        fn is_carrying(&self, _drone: UnitId) -> bool {
+            false
+        }
+        fn position(&self, _drone: UnitId) -> Option<Position> {
+            // nobody ever moves
+            Some(self.position)
+        }
+        fn is_walking(&self, _drone: UnitId) -> bool {
+            self.walking
+        }
+        fn long_distance_base(&self) -> Option<TilePosition> {
+            self.long_distance
+        }
+    }
+
+    /// a mineral line of `count` patches above a hatch
+    fn mineral_line(
+        first_id: UnitId,
+        base: TilePosition,
+        count: usize,
+    ) -> Vec<(UnitId, TilePosition)> {
+        (0..count)
+            .map(|i| (first_id + i, base + tp(i as i32, -5)))
+            .collect()
+    }
+
+    fn drones(ids: std::ops::Range<UnitId>) -> DroneManager {
+        let mut dm = DroneManager::new();
+        ids.for_each(|id| dm.add_fake(id));
+        dm
+    }
+
+    /// drones on each patch of a base, smallest first
+    fn saturation(mm: &MineralManager, base: Option<TilePosition>) -> Vec<usize> {
+        let mut mining: Vec<_> = mm
+            .patches
+            .values()
+            .filter(|p| p.base == base)
+            .map(|p| p.drones.len())
+            .collect();
+        mining.sort();
+        mining
+    }
+
+    #[test]
+    fn test_saturate_base() {
+        let main = tp(10, 10);
+        let game = FakeMining {
+            bases: vec![main],
+            patches: mineral_line(100, main, 4),
+            ..Default::default()
+        };
+        let mut dm = drones(0..14);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            saturation(&mm, Some(main)),
+            vec![3, 3, 3, 3],
+            "two per patch then up to three"
+        );
+        assert_eq!(dm.count_role(&DroneRole::Idle), 2, "nowhere left to mine");
+        assert_eq!(mm.mineral_lines(), vec![(102, tp(12, 5))]);
+    }
+
+    #[test]
+    fn test_maynard() {
+        let main = tp(10, 10);
+        let natural = tp(40, 40);
+        let mut game = FakeMining {
+            bases: vec![main],
+            long_distance: Some(natural),
+            patches: mineral_line(100, main, 4),
+            ..Default::default()
+        };
+        let mut dm = drones(0..12);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        assert_eq!(saturation(&mm, Some(main)), vec![3, 3, 3, 3]);
+
+        // natural patches are too far to mine until the hatch finishes
+        game.patches.extend(mineral_line(200, natural, 4));
+        mm.update(&game, &mut dm);
+        assert_eq!(saturation(&mm, Some(main)), vec![3, 3, 3, 3]);
+        assert_eq!(saturation(&mm, None), vec![0, 0, 0, 0]);
+
+        game.bases.push(natural);
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            saturation(&mm, Some(main)),
+            vec![2, 2, 2, 2],
+            "extra drones leave the main"
+        );
+        assert_eq!(saturation(&mm, Some(natural)), vec![1, 1, 1, 1]);
+        assert_eq!(dm.count_role(&DroneRole::Minerals), 12);
+    }
+
+    #[test]
+    fn test_long_distance() {
+        let main = tp(10, 10);
+        let far_away = tp(60, 60);
+        let their_natural = tp(100, 100);
+        let mut game = FakeMining {
+            bases: vec![main],
+            long_distance: Some(far_away),
+            patches: mineral_line(100, main, 2),
+            ..Default::default()
+        };
+        game.patches.extend(mineral_line(200, far_away, 2));
+        game.patches.extend(mineral_line(300, their_natural, 2));
+        let mut dm = drones(0..8);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        assert_eq!(saturation(&mm, Some(main)), vec![3, 3]);
+        assert_eq!(
+            saturation(&mm, None),
+            vec![1, 1],
+            "long distance once the main is full, only at the safe base"
+        );
+        assert!(!mm.patches.contains_key(&300));
+
+        // lose some drones at home
+        for dead in mm.patches[&100].drones.clone() {
+            game.dead.insert(dead);
+            dm.on_unit_destroy(dead);
+        }
+        mm.update(&game, &mut dm);
+        assert_eq!(saturation(&mm, Some(main)), vec![2, 3], "miners came home");
+        assert_eq!(saturation(&mm, None), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_regather() {
+        let main = tp(10, 10);
+        let mut game = FakeMining {
+            bases: vec![main],
+            patches: mineral_line(100, main, 4),
+            ..Default::default()
+        };
+        let mut dm = drones(0..2);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        let gathers = game.gathers.borrow().len();
+        let (patch, drone) = mm
+            .patches
+            .iter()
+            .find_map(|(id, p)| p.drones.first().map(|d| (*id, *d)))
+            .expect("a drone is mining");
+
+        // the engine sends it somewhere else
+        let elsewhere = if patch == 100 { 101 } else { 100 };
+        game.targets.borrow_mut().insert(drone, elsewhere);
+        game.frame = 1;
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            game.gathers.borrow()[gathers..],
+            [(drone, patch)],
+            "sent back to its own patch"
+        );
+
+        game.targets.borrow_mut().insert(drone, elsewhere);
+        game.frame = 2;
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            game.gathers.borrow().len(),
+            gathers + 1,
+            "don't spam commands"
+        );
+    }
+
+    #[test]
+    fn test_mineral_walk() {
+        let main = tp(10, 10);
+        let natural = tp(60, 60);
+        let mut game = FakeMining {
+            bases: vec![main, natural],
+            patches: mineral_line(100, main, 4),
+            // left of the mineral line
+            position: tp(8, 5).to_position(),
+            ..Default::default()
+        };
+        game.patches.extend(mineral_line(200, natural, 4));
+        let mut dm = drones(0..1);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        let (drone, patch) = game.gathers.borrow()[0];
+        assert_eq!(patch, 102);
+
+        game.frame = TRAPPED_FRAMES;
+        mm.update(&game, &mut dm);
+        assert_eq!(game.gathers.borrow().len(), 1, "just mining");
+
+        game.walking = true;
+        game.frame = TRAPPED_FRAMES * 2;
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            *game.gathers.borrow().last().unwrap(),
+            (drone, 103),
+            "the next patch along in its own line"
+        );
+
+        game.frame = TRAPPED_FRAMES * 2 + ESCAPE_FRAMES;
+        mm.update(&game, &mut dm);
+        assert_eq!(
+            *game.gathers.borrow().last().unwrap(),
+            (drone, patch),
+            "back to work after the escape"
+        );
+    }
+
+    // rough drone timings for the mining model
+    const FRAMES_PER_TILE: i32 = 6;
+    const MINE_FRAMES: i32 = 40;
+    const BOUNCE_FRAMES: i32 = 16;
+    const MINERALS_PER_TRIP: i32 = 8;
+
+    #[derive(Clone, Copy)]
+    enum SimMiner {
+        ToPatch(i32),
+        Mining(i32),
+        Returning(i32),
+    }
+
+    /// Minerals gathered over `frames` by `drone_count` drones the mineral
+    /// manager puts to work at the one base in `game`. Only one drone can
+    /// mine a patch at a time, the engine bounces the others to the next
+    /// patch and with `regather` we send them back
+    fn simulate_mining(
+        mut game: FakeMining,
+        drone_count: usize,
+        regather: bool,
+        frames: i32,
+    ) -> i32 {
+        let middle = game.bases[0] + HATCH_MIDDLE;
+        let order: Vec<UnitId> = game.patches.iter().map(|(id, _)| *id).collect();
+        let walk: HashMap<UnitId, i32> = game
+            .patches
+            .iter()
+            .map(|(id, tile)| {
+                (
+                    *id,
+                    middle.chebyshev_distance(*tile) as i32 * FRAMES_PER_TILE,
+                )
+            })
+            .collect();
+        let mut dm = drones(0..drone_count);
+        let mut mm = MineralManager::new();
+        mm.update(&game, &mut dm);
+        let mut miners: BTreeMap<UnitId, (UnitId, SimMiner)> = game
+            .targets
+            .borrow()
+            .iter()
+            .map(|(drone, patch)| (*drone, (*patch, SimMiner::ToPatch(walk[patch]))))
+            .collect();
+        let mut busy_until: HashMap<UnitId, i32> = HashMap::new();
+        let mut minerals = 0;
+        for frame in 0..frames {
+            for (patch, state) in miners.values_mut() {
+                *state = match *state {
+                    SimMiner::ToPatch(arrive) if arrive <= frame => {
+                        let busy = busy_until.entry(*patch).or_insert(0);
+                        if *busy <= frame {
+                            *busy = frame + MINE_FRAMES;
+                            SimMiner::Mining(frame + MINE_FRAMES)
+                        } else {
+                            let next = order.iter().position(|id| *id == *patch).unwrap_or(0) + 1;
+                            *patch = order[next % order.len()];
+                            SimMiner::ToPatch(frame + BOUNCE_FRAMES)
+                        }
+                    }
+                    SimMiner::Mining(done) if done <= frame => {
+                        SimMiner::Returning(frame + walk[&*patch])
+                    }
+                    SimMiner::Returning(home) if home <= frame => {
+                        minerals += MINERALS_PER_TRIP;
+                        SimMiner::ToPatch(frame + walk[&*patch])
+                    }
+                    unchanged => unchanged,
+                };
+            }
+            if regather {
+                game.frame = frame;
+                game.targets
+                    .borrow_mut()
+                    .extend(miners.iter().map(|(drone, (patch, _))| (*drone, *patch)));
+                let sent = game.gathers.borrow().len();
+                mm.update(&game, &mut dm);
+                for (drone, patch) in game.gathers.borrow()[sent..].iter() {
+                    miners.insert(*drone, (*patch, SimMiner::ToPatch(frame + BOUNCE_FRAMES)));
+                }
+            }
+        }
+        minerals
+    }
+
+    #[test]
+    fn test_mining_income() {
+        let main = tp(10, 10);
+        // far patches get the low ids so arbitrary ordering favours them
+        let game = || {
+            let mut patches = mineral_line(100, main + tp(0, -2), 4);
+            patches.extend(mineral_line(104, main, 4));
+            FakeMining {
+                bases: vec![main],
+                patches,
+                ..Default::default()
+            }
+        };
+        let mut mm = MineralManager::new();
+        mm.update(&game(), &mut drones(0..12));
+        assert_eq!(
+            (104..108)
+                .map(|id| mm.patches[&id].drones.len())
+                .collect::<Vec<_>>(),
+            vec![2, 2, 2, 2],
+            "the close patches get the second drone"
+        );
+
+        let frames = 24 * 60 * 5;
+        assert!(
+            simulate_mining(game(), 12, true, frames) > simulate_mining(game(), 12, false, frames),
+            "drones wandering off cost income"
+        );
+    }
 }
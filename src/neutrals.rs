@@ -0,0 +1,164 @@
+use crate::bases::BASE_RADIUS;
+use rsbwapi::{TilePosition, UnitId, UnitType};
+
+// mineral fields with this little in them are there to block the way
+const BLOCKING_RESOURCES: i32 = 16;
+// a blocker this many tiles from the straight line to somewhere we walk to is in the way
+const PATH_WIDTH: f32 = 4.0;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum NeutralKind {
+    /// wanders around, never in the way for long
+    Critter,
+    /// mineral walls, mined out to get through
+    BlockingMinerals,
+    Building,
+    /// anything else sitting on the ground
+    Obstacle,
+}
+
+/// Something on the map that isn't anyone's
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Neutral {
+    pub kind: NeutralKind,
+    pub unit_type: UnitType,
+    /// top left of where it sits
+    pub tile: TilePosition,
+}
+
+pub(crate) fn is_blocking_minerals(unit_type: UnitType, resources: i32) -> bool {
+    unit_type.is_mineral_field() && resources <= BLOCKING_RESOURCES
+}
+
+impl Neutral {
+    /// None for the resources we mine and anything that doesn't sit on the
+    /// ground
+    pub fn new(unit_type: UnitType, tile: TilePosition, resources: i32) -> Option<Self> {
+        let kind = match unit_type {
+            _ if is_blocking_minerals(unit_type, resources) => NeutralKind::BlockingMinerals,
+            _ if unit_type.is_mineral_field() || unit_type.is_powerup() => return None,
+            UnitType::Resource_Vespene_Geyser => return None,
+            _ if unit_type.is_critter() => NeutralKind::Critter,
+            _ if unit_type.is_building() => NeutralKind::Building,
+            _ if unit_type.is_flyer() => return None,
+            _ => NeutralKind::Obstacle,
+        };
+        Some(Neutral {
+            kind,
+            unit_type,
+            tile,
+        })
+    }
+
+    /// the tiles it sits on, critters move on so they don't block any
+    pub fn tiles(&self) -> Vec<TilePosition> {
+        if self.kind == NeutralKind::Critter {
+            return vec![];
+        }
+        let size = self.unit_type.tile_size();
+        (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| TilePosition { x, y }))
+            .map(|offset| self.tile + offset)
+            .collect()
+    }
+}
+
+/// tiles from `tile` to the nearest point on the line from `from` to `to`
+fn distance_to_line(tile: TilePosition, from: TilePosition, to: TilePosition) -> f32 {
+    let (dx, dy) = ((to.x - from.x) as f32, (to.y - from.y) as f32);
+    let (px, py) = ((tile.x - from.x) as f32, (tile.y - from.y) as f32);
+    let length = dx * dx + dy * dy;
+    let along = if length > 0.0 {
+        ((px * dx + py * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((px - dx * along).powi(2) + (py - dy * along).powi(2)).sqrt()
+}
+
+/// Blocking minerals worth mining out, closest to `home` first: those at
+/// `bases` we want to take and those on the way from `home` to `targets`
+pub(crate) fn blockers_in_the_way(
+    blockers: &[(UnitId, TilePosition)],
+    home: TilePosition,
+    bases: &[TilePosition],
+    targets: &[TilePosition],
+) -> Vec<UnitId> {
+    let mut in_the_way: Vec<(UnitId, TilePosition)> = blockers
+        .iter()
+        .filter(|(_, tile)| {
+            bases
+                .iter()
+                .any(|b| b.chebyshev_distance(*tile) <= BASE_RADIUS)
+                || targets
+                    .iter()
+                    .any(|t| distance_to_line(*tile, home, *t) <= PATH_WIDTH)
+        })
+        .copied()
+        .collect();
+    in_the_way.sort_by_key(|(id, tile)| (home.distance_squared(*tile), *id));
+    in_the_way.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+
+    #[test]
+    fn test_classify() {
+        let kind =
+            |unit_type, resources| Neutral::new(unit_type, tp(0, 0), resources).map(|n| n.kind);
+        assert_eq!(kind(UnitType::Resource_Mineral_Field, 1500), None);
+        assert_eq!(
+            kind(UnitType::Resource_Mineral_Field, 8),
+            Some(NeutralKind::BlockingMinerals)
+        );
+        assert_eq!(kind(UnitType::Resource_Vespene_Geyser, 5000), None);
+        assert_eq!(
+            kind(UnitType::Critter_Bengalaas, 0),
+            Some(NeutralKind::Critter)
+        );
+        assert_eq!(
+            kind(UnitType::Special_Psi_Disrupter, 0),
+            Some(NeutralKind::Building)
+        );
+    }
+
+    #[test]
+    fn test_tiles() {
+        let wall = Neutral::new(UnitType::Resource_Mineral_Field, tp(10, 20), 0).unwrap();
+        assert_eq!(wall.tiles(), vec![tp(10, 20), tp(11, 20)]);
+        let critter = Neutral::new(UnitType::Critter_Rhynadon, tp(10, 20), 0).unwrap();
+        assert_eq!(critter.tiles(), vec![], "it'll wander off");
+    }
+
+    #[test]
+    fn test_in_the_way() {
+        let home = tp(10, 10);
+        let blockers = [
+            // at the natural
+            (1, tp(30, 12)),
+            // across the middle of the map
+            (2, tp(60, 62)),
+            (3, tp(64, 64)),
+            // off in a corner nobody goes to
+            (4, tp(10, 120)),
+        ];
+        assert_eq!(blockers_in_the_way(&blockers, home, &[], &[]), vec![]);
+        assert_eq!(
+            blockers_in_the_way(&blockers, home, &[tp(32, 10)], &[]),
+            vec![1]
+        );
+        assert_eq!(
+            blockers_in_the_way(&blockers, home, &[tp(32, 10)], &[tp(120, 120)]),
+            vec![1, 2, 3],
+            "the natural's closer than the path"
+        );
+        assert_eq!(
+            blockers_in_the_way(&blockers, home, &[], &[tp(40, 40)]),
+            vec![],
+            "we stop short of the wall"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{build_position::tile_position_towards, scouting::natural_base, threat::ThreatMap};
+use rsbwapi::{TilePosition, UnitId};
+use std::collections::{HashMap, HashSet};
+
+// where proxies usually go, this many tiles out from our main towards theirs
+const PROXY_DISTANCE: i32 = 16;
+// tiles between overlords watching the way in
+const WATCH_SPACING: i32 = 12;
+// stay away from a post anti-air was seen at for this long
+const UNSAFE_FRAMES: i32 = 24 * 60;
+// a retreating overlord this many tiles from home is free again
+const HOME_RADIUS: u32 = 4;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum OverlordOrder {
+    Move(UnitId, TilePosition),
+    Retreat(UnitId, TilePosition),
+}
+
+/// Where overlords should sit, most important first: their natural, likely
+/// proxy spots and then along the way from our main to theirs
+pub(crate) fn overlord_posts(
+    home: TilePosition,
+    enemy_main: Option<TilePosition>,
+    bases: &[TilePosition],
+) -> Vec<TilePosition> {
+    let enemy = match enemy_main {
+        Some(enemy) => enemy,
+        None => return vec![],
+    };
+    let mut posts: Vec<TilePosition> = natural_base(enemy, bases).into_iter().collect();
+    posts.push(tile_position_towards(&home, PROXY_DISTANCE, &enemy));
+    let length = home.chebyshev_distance(enemy) as i32;
+    let mut along = WATCH_SPACING;
+    while along < length - WATCH_SPACING {
+        posts.push(TilePosition {
+            x: home.x + (enemy.x - home.x) * along / length,
+            y: home.y + (enemy.y - home.y) * along / length,
+        });
+        along += WATCH_SPACING;
+    }
+    posts
+}
+
+/// Spreads overlords over the map for vision and pulls them back when
+/// anti-air shows up
+pub(crate) struct OverlordManager {
+    posts: HashMap<UnitId, TilePosition>,
+    retreating: HashSet<UnitId>,
+    // posts we saw anti-air at and when
+    unsafe_posts: HashMap<TilePosition, i32>,
+}
+
+impl OverlordManager {
+    pub fn new() -> Self {
+        OverlordManager {
+            posts: HashMap::new(),
+            retreating: HashSet::new(),
+            unsafe_posts: HashMap::new(),
+        }
+    }
+
+    /// on its way home from anti-air, not free to send anywhere yet
+    pub fn is_retreating(&self, id: UnitId) -> bool {
+        self.retreating.contains(&id)
+    }
+
+    /// Orders for `overlords` given where they are. Overlords are only told
+    /// where to go when that changes
+    pub fn on_frame(
+        &mut self,
+        frame: i32,
+        home: TilePosition,
+        overlords: &[(UnitId, TilePosition)],
+        posts: &[TilePosition],
+        threats: &ThreatMap,
+    ) -> Vec<OverlordOrder> {
+        let alive: HashSet<UnitId> = overlords.iter().map(|(id, _)| *id).collect();
+        self.posts.retain(|id, _| alive.contains(id));
+        self.retreating.retain(|id| alive.contains(id));
+        self.unsafe_posts
+            .retain(|_, seen| frame - *seen < UNSAFE_FRAMES);
+
+        let mut orders = vec![];
+        let mut free = vec![];
+        let mut sorted = overlords.to_vec();
+        sorted.sort_by_key(|(id, _)| *id);
+        for (id, tile) in sorted {
+            if self.retreating.contains(&id) {
+                if tile.chebyshev_distance(home) <= HOME_RADIUS {
+                    self.retreating.remove(&id);
+                    free.push(id);
+                }
+                continue;
+            }
+            match self.posts.get(&id).copied() {
+                Some(post) if threats.air(tile) > 0.0 || threats.air(post) > 0.0 => {
+                    println!(
+                        "frame {} :: overlord {} saw anti-air near {:?}",
+                        frame, id, post
+                    );
+                    self.unsafe_posts.insert(post, frame);
+                    self.posts.remove(&id);
+                    self.retreating.insert(id);
+                    orders.push(OverlordOrder::Retreat(id, home));
+                }
+                // we were wrong about where they are, go somewhere still wanted
+                Some(post) if !posts.contains(&post) => {
+                    self.posts.remove(&id);
+                    free.push(id);
+                }
+                Some(_) => {}
+                // a scout that ran into anti-air
+                None if threats.air(tile) > 0.0 => {
+                    println!(
+                        "frame {} :: overlord {} saw anti-air at {:?}",
+                        frame, id, tile
+                    );
+                    self.retreating.insert(id);
+                    orders.push(OverlordOrder::Retreat(id, home));
+                }
+                None => free.push(id),
+            }
+        }
+
+        let taken: HashSet<TilePosition> = self.posts.values().copied().collect();
+        let mut open = posts.iter().filter(|p| {
+            !taken.contains(*p) && !self.unsafe_posts.contains_key(*p) && threats.air(**p) <= 0.0
+        });
+        let mut assigned = vec![];
+        for id in free {
+            match open.next() {
+                Some(post) => {
+                    assigned.push((id, *post));
+                    orders.push(OverlordOrder::Move(id, *post));
+                }
+                None => break,
+            }
+        }
+        self.posts.extend(assigned);
+        orders
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+    use rsbwapi::{Position, UnitType};
+
+    const HOME: TilePosition = TilePosition { x: 8, y: 8 };
+
+    fn posts() -> Vec<TilePosition> {
+        let bases = [tp(10, 10), tp(30, 10), tp(120, 120), tp(100, 118)];
+        overlord_posts(HOME, Some(tp(120, 120)), &bases)
+    }
+
+    #[test]
+    fn test_posts() {
+        assert_eq!(overlord_posts(HOME, None, &[]), vec![], "nowhere to go yet");
+        assert_eq!(
+            posts(),
+            vec![
+                tp(100, 118),
+                tp(24, 24),
+                tp(20, 20),
+                tp(32, 32),
+                tp(44, 44),
+                tp(56, 56),
+                tp(68, 68),
+                tp(80, 80),
+                tp(92, 92),
+                tp(104, 104),
+            ],
+            "their natural, a proxy spot, then the way in"
+        );
+    }
+
+    #[test]
+    fn test_spread_out() {
+        let mut om = OverlordManager::new();
+        let threats = ThreatMap::new(128, 128);
+        let orders = om.on_frame(0, HOME, &[(1, HOME)], &posts(), &threats);
+        assert_eq!(orders, vec![OverlordOrder::Move(1, tp(100, 118))]);
+
+        let orders = om.on_frame(1, HOME, &[(1, tp(50, 50)), (2, HOME)], &posts(), &threats);
+        assert_eq!(
+            orders,
+            vec![OverlordOrder::Move(2, tp(24, 24))],
+            "the first one keeps going"
+        );
+    }
+
+    #[test]
+    fn test_enemy_main_moved() {
+        let mut om = OverlordManager::new();
+        let threats = ThreatMap::new(128, 128);
+        om.on_frame(0, HOME, &[(1, HOME)], &posts(), &threats);
+
+        // they were in the other corner
+        let bases = [tp(10, 10), tp(120, 8), tp(100, 10)];
+        let moved = overlord_posts(HOME, Some(tp(120, 8)), &bases);
+        let orders = om.on_frame(1, HOME, &[(1, tp(50, 50))], &moved, &threats);
+        assert_eq!(orders, vec![OverlordOrder::Move(1, tp(100, 10))]);
+    }
+
+    #[test]
+    fn test_anti_air() {
+        let mut om = OverlordManager::new();
+        let mut threats = ThreatMap::new(128, 128);
+        om.on_frame(0, HOME, &[(1, HOME), (2, HOME)], &posts(), &threats);
+
+        // a marine shows up at their natural
+        threats.add(UnitType::Terran_Marine, tp(100, 118).to_position());
+        let overlords = [(1, tp(98, 116)), (2, tp(24, 24))];
+        let orders = om.on_frame(10, HOME, &overlords, &posts(), &threats);
+        assert_eq!(orders, vec![OverlordOrder::Retreat(1, HOME)]);
+
+        let overlords = [(1, HOME), (2, tp(24, 24)), (3, HOME)];
+        let orders = om.on_frame(500, HOME, &overlords, &posts(), &ThreatMap::new(128, 128));
+        assert_eq!(
+            orders,
+            vec![
+                OverlordOrder::Move(1, tp(20, 20)),
+                OverlordOrder::Move(3, tp(32, 32)),
+            ],
+            "stay away from the natural for a while"
+        );
+
+        let overlords = [(1, tp(20, 20)), (2, tp(24, 24)), (3, tp(32, 32)), (4, HOME)];
+        let orders = om.on_frame(
+            UNSAFE_FRAMES + 10,
+            HOME,
+            &overlords,
+            &posts(),
+            &ThreatMap::new(128, 128),
+        );
+        assert_eq!(orders, vec![OverlordOrder::Move(4, tp(100, 118))]);
+
+        let mut turret = ThreatMap::new(128, 128);
+        turret.add(
+            UnitType::Terran_Missile_Turret,
+            Position {
+                x: 44 * 32,
+                y: 44 * 32,
+            },
+        );
+        let overlords = [
+            (1, tp(20, 20)),
+            (2, tp(24, 24)),
+            (3, tp(32, 32)),
+            (4, tp(100, 118)),
+            (5, HOME),
+        ];
+        let orders = om.on_frame(UNSAFE_FRAMES + 11, HOME, &overlords, &posts(), &turret);
+        assert_eq!(
+            orders,
+            vec![OverlordOrder::Move(5, tp(56, 56))],
+            "skip posts we know are covered"
+        );
+
+        // a scouting overlord we've just been handed
+        let orders = om.on_frame(UNSAFE_FRAMES + 12, HOME, &[(6, tp(44, 44))], &[], &turret);
+        assert_eq!(orders, vec![OverlordOrder::Retreat(6, HOME)]);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::{
+    build_order::TechChecker, composition::Composition, counts::Counts, supply::SupplyPlanner,
+};
+use rsbwapi::UnitType;
+use std::str::FromStr;
+
+/// where we look for a production config before falling back to the defaults
+pub(crate) const GOALS_PATH: &str = "bwapi-data/AI/barcode_goals.txt";
+
+// NB: these counts are of units, not eggs
+const DEFAULT_GOALS: &str = "
+# no more than 4 overlords morphing at once
+overlords 4
+count zergling 8
+drones
+army
+";
+
+/// Something we'd like to spend larva on, in priority order
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Goal {
+    /// overlords when the supply planner says we'll be blocked, with at most
+    /// this many morphing at once
+    Overlords(i32),
+    /// build up to this many of a unit
+    Count(UnitType, i32),
+    /// drones up to what the economy controller wants
+    Drones,
+    /// `amount` of a unit for every `per` of another unit
+    Ratio {
+        unit: UnitType,
+        amount: i32,
+        other: UnitType,
+        per: i32,
+    },
+    /// whatever the composition planner wants
+    Army,
+}
+
+/// What the production solver needs to know about our units
+pub(crate) trait UnitCounts {
+    /// units we have or are morphing
+    fn count_of(&self, unit_type: UnitType) -> i32;
+    /// units still in their eggs
+    fn morphing(&self, unit_type: UnitType) -> i32;
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Production {
+    goals: Vec<Goal>,
+    drone_target: i32,
+}
+
+impl Default for Production {
+    fn default() -> Self {
+        DEFAULT_GOALS.parse().expect("default goals parse")
+    }
+}
+
+impl Production {
+    pub fn new(goals: Vec<Goal>) -> Self {
+        Production {
+            goals,
+            drone_target: 0,
+        }
+    }
+
+    pub fn set_drone_target(&mut self, target: i32) {
+        self.drone_target = target;
+    }
+
+    /// read goals from the config file, or use the defaults if there isn't one
+    pub fn load(path: &str) -> Self {
+        match std::fs::read_to_string(path).map(|config| config.parse::<Production>()) {
+            Ok(Ok(production)) => {
+                println!("loaded production goals from {}", path);
+                production
+            }
+            Ok(Err(e)) => {
+                println!("bad production goals in {}: {}, using defaults", path, e);
+                Production::default()
+            }
+            Err(_) => Production::default(),
+        }
+    }
+
+    /// The next unit to spend a larva on, if any
+    pub fn next_unit(
+        &self,
+        counts: &Counts,
+        units: &impl UnitCounts,
+        tech: impl TechChecker,
+        comp: &Composition,
+        supply: &SupplyPlanner,
+        larva: usize,
+    ) -> Option<UnitType> {
+        if larva == 0 {
+            return None;
+        }
+        let used = counts.supply_used();
+        let max = counts.supply_max();
+        // no more supply, whatever order the goals are in, only an overlord fits
+        let fits = |ut: &UnitType| used < max || ut.supply_required() == 0;
+        let makeable = |ut: &UnitType| fits(ut) && counts.can_afford(*ut) && tech.has_prereqs(ut);
+
+        for goal in self.goals.iter() {
+            match goal {
+                Goal::Overlords(most) => {
+                    let overlord = UnitType::Zerg_Overlord;
+                    if supply.overlords_wanted(counts, larva) > 0
+                        && units.morphing(overlord) < *most
+                        && makeable(&overlord)
+                    {
+                        return Some(overlord);
+                    }
+                }
+                Goal::Count(unit, amount) => {
+                    if units.count_of(*unit) < *amount && makeable(unit) {
+                        return Some(*unit);
+                    }
+                }
+                Goal::Drones => {
+                    let drone = UnitType::Zerg_Drone;
+                    if units.count_of(drone) < self.drone_target && makeable(&drone) {
+                        return Some(drone);
+                    }
+                }
+                Goal::Ratio {
+                    unit,
+                    amount,
+                    other,
+                    per,
+                } => {
+                    let behind = units.count_of(*unit) * per < units.count_of(*other) * amount;
+                    if behind && makeable(unit) {
+                        return Some(*unit);
+                    }
+                }
+                Goal::Army => {
+                    let army_unit = comp
+                        .larva_units_wanted(|ut| units.count_of(ut))
+                        .into_iter()
+                        .find(|ut| makeable(ut));
+                    if army_unit.is_some() {
+                        return army_unit;
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// config names for the units we're able to make from larva
+fn parse_unit(name: &str) -> Result<UnitType, String> {
+    match name.to_lowercase().as_str() {
+        "drone" => Ok(UnitType::Zerg_Drone),
+        "overlord" => Ok(UnitType::Zerg_Overlord),
+        "zergling" | "ling" => Ok(UnitType::Zerg_Zergling),
+        "hydralisk" | "hydra" => Ok(UnitType::Zerg_Hydralisk),
+        "mutalisk" | "muta" => Ok(UnitType::Zerg_Mutalisk),
+        "scourge" => Ok(UnitType::Zerg_Scourge),
+        "queen" => Ok(UnitType::Zerg_Queen),
+        "ultralisk" | "ultra" => Ok(UnitType::Zerg_Ultralisk),
+        "defiler" => Ok(UnitType::Zerg_Defiler),
+        _ => Err(format!("unknown unit {}", name)),
+    }
+}
+
+fn parse_amount(amount: &str) -> Result<i32, String> {
+    amount.parse().map_err(|_| format!("bad amount {}", amount))
+}
+
+impl FromStr for Goal {
+    type Err = String;
+
+    /// one goal per line, e.g.
+    /// overlords 1
+    /// count drone 18
+    /// drones
+    /// ratio mutalisk 1 hydralisk 2
+    /// army
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["overlords", most] => Ok(Goal::Overlords(parse_amount(most)?)),
+            ["count", unit, amount] => Ok(Goal::Count(parse_unit(unit)?, parse_amount(amount)?)),
+            ["ratio", unit, amount, other, per] => Ok(Goal::Ratio {
+                unit: parse_unit(unit)?,
+                amount: parse_amount(amount)?,
+                other: parse_unit(other)?,
+                per: parse_amount(per)?,
+            }),
+            ["drones"] => Ok(Goal::Drones),
+            ["army"] => Ok(Goal::Army),
+            _ => Err(format!("can't parse goal '{}'", line)),
+        }
+    }
+}
+
+impl FromStr for Production {
+    type Err = String;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        let goals = config
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.parse::<Goal>())
+            .collect::<Result<Vec<Goal>, String>>()?;
+        Ok(Production::new(goals))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::AllTech;
+    use std::collections::HashMap;
+
+    fn supply(c: &Counts) -> SupplyPlanner {
+        SupplyPlanner::new(c, 0.0)
+    }
+
+    struct NoPool;
+    impl TechChecker for &NoPool {
+        fn has_prereqs(&self, unit_type: &UnitType) -> bool {
+            *unit_type == UnitType::Zerg_Drone || *unit_type == UnitType::Zerg_Overlord
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeUnits {
+        counts: HashMap<UnitType, i32>,
+        morphing: HashMap<UnitType, i32>,
+    }
+
+    impl FakeUnits {
+        fn with(mut self, unit_type: UnitType, count: i32) -> Self {
+            self.counts.insert(unit_type, count);
+            self
+        }
+    }
+
+    impl UnitCounts for FakeUnits {
+        fn count_of(&self, unit_type: UnitType) -> i32 {
+            *self.counts.get(&unit_type).unwrap_or(&0)
+        }
+        fn morphing(&self, unit_type: UnitType) -> i32 {
+            *self.morphing.get(&unit_type).unwrap_or(&0)
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let p: Production = "
+            # a comment
+            overlords 2
+            count drone 12
+            drones
+            ratio muta 1 hydra 2
+            army
+        "
+        .parse()
+        .expect("parses");
+        assert_eq!(
+            p,
+            Production::new(vec![
+                Goal::Overlords(2),
+                Goal::Count(UnitType::Zerg_Drone, 12),
+                Goal::Drones,
+                Goal::Ratio {
+                    unit: UnitType::Zerg_Mutalisk,
+                    amount: 1,
+                    other: UnitType::Zerg_Hydralisk,
+                    per: 2
+                },
+                Goal::Army,
+            ])
+        );
+        assert!("count marine 4".parse::<Production>().is_err(), "not zerg");
+        assert!(
+            "count drone lots".parse::<Production>().is_err(),
+            "not a number"
+        );
+        assert!(
+            "make me a sandwich".parse::<Production>().is_err(),
+            "not a goal"
+        );
+        assert_eq!(Production::default().goals.len(), 4, "defaults parse");
+    }
+
+    #[test]
+    fn test_opening() {
+        let mut p = Production::default();
+        p.set_drone_target(18);
+        let comp = Composition::default();
+        let drones = FakeUnits::default().with(UnitType::Zerg_Drone, 8);
+
+        let c = Counts::new_fake_bank(100, 0, 12, 18);
+        assert_eq!(
+            p.next_unit(&c, &drones, &NoPool {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Drone),
+            "drones first"
+        );
+        assert_eq!(
+            p.next_unit(&c, &drones, &NoPool {}, &comp, &supply(&c), 0),
+            None,
+            "nothing without larva"
+        );
+
+        let c = Counts::new_fake_bank(100, 0, 18, 18);
+        assert_eq!(
+            p.next_unit(&c, &drones, &NoPool {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Overlord),
+            "9 overlord"
+        );
+        let mut morphing = FakeUnits::default().with(UnitType::Zerg_Drone, 9);
+        morphing.morphing.insert(UnitType::Zerg_Overlord, 1);
+        let c = c.with_pending(16);
+        assert_eq!(
+            p.next_unit(&c, &morphing, &NoPool {}, &comp, &supply(&c), 3),
+            None,
+            "supply blocked until the overlord pops"
+        );
+
+        let c = Counts::new_fake_bank(100, 0, 20, 34);
+        assert_eq!(
+            p.next_unit(&c, &drones, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Zergling),
+            "lings once the pool is done"
+        );
+        let lings = FakeUnits::default()
+            .with(UnitType::Zerg_Drone, 17)
+            .with(UnitType::Zerg_Zergling, 8);
+        assert_eq!(
+            p.next_unit(&c, &lings, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Drone),
+            "back to drones after 8 lings"
+        );
+    }
+
+    #[test]
+    fn test_army() {
+        let mut p = Production::default();
+        p.set_drone_target(20);
+        let comp = Composition::new(vec![(UnitType::Zerg_Mutalisk, 1)]);
+        let units = FakeUnits::default()
+            .with(UnitType::Zerg_Drone, 20)
+            .with(UnitType::Zerg_Zergling, 8);
+        let c = Counts::new_fake_bank(100, 100, 60, 100);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Mutalisk),
+            "army before more drones"
+        );
+        let c = Counts::new_fake_bank(100, 0, 60, 100);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            None,
+            "save up for the army once we have enough drones"
+        );
+        p.set_drone_target(30);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Drone),
+            "drones when the controller wants more"
+        );
+    }
+
+    #[test]
+    fn test_supply_capped() {
+        let p: Production = "count zergling 10\noverlords 2".parse().unwrap();
+        let comp = Composition::default();
+        let units = FakeUnits::default().with(UnitType::Zerg_Drone, 9);
+        let c = Counts::new_fake_bank(500, 0, 18, 18);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Overlord),
+            "lings come first but don't fit"
+        );
+        let mut morphing = FakeUnits::default().with(UnitType::Zerg_Drone, 9);
+        morphing.morphing.insert(UnitType::Zerg_Overlord, 2);
+        assert_eq!(
+            p.next_unit(&c, &morphing, &AllTech {}, &comp, &supply(&c), 3),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ratio() {
+        let p: Production = "ratio mutalisk 1 hydralisk 2\ncount hydralisk 99"
+            .parse()
+            .unwrap();
+        let comp = Composition::default();
+        let c = Counts::new_fake_bank(500, 500, 60, 100);
+        let units = FakeUnits::default()
+            .with(UnitType::Zerg_Hydralisk, 4)
+            .with(UnitType::Zerg_Mutalisk, 1);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Mutalisk),
+            "1 muta per 2 hydras"
+        );
+        let units = units.with(UnitType::Zerg_Mutalisk, 2);
+        assert_eq!(
+            p.next_unit(&c, &units, &AllTech {}, &comp, &supply(&c), 3),
+            Some(UnitType::Zerg_Hydralisk),
+            "ratio satisfied"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::counts::Counts;
+use rsbwapi::{Game, UnitType, UpgradeType};
+
+/// An upgrade to start once the building that researches it is done
+struct ResearchStep {
+    upgrade: UpgradeType,
+    building: UnitType,
+    // level one prices
+    minerals: i32,
+    gas: i32,
+    started: bool,
+}
+
+impl ResearchStep {
+    fn new(upgrade: UpgradeType, building: UnitType) -> Self {
+        ResearchStep {
+            upgrade,
+            building,
+            minerals: upgrade.mineral_price(),
+            gas: upgrade.gas_price(),
+            started: false,
+        }
+    }
+}
+
+pub(crate) struct Research {
+    steps: Vec<ResearchStep>,
+}
+
+impl Research {
+    pub fn new() -> Self {
+        Research {
+            steps: vec![ResearchStep::new(
+                UpgradeType::Metabolic_Boost,
+                UnitType::Zerg_Spawning_Pool,
+            )],
+        }
+    }
+
+    pub fn on_frame(&mut self, game: &Game, counts: &mut Counts) {
+        let self_ = match game.self_() {
+            Some(self_) => self_,
+            None => return,
+        };
+        for step in self.steps.iter_mut().filter(|s| !s.started) {
+            if self_.get_upgrade_level(step.upgrade) > 0 || self_.is_upgrading(step.upgrade) {
+                step.started = true;
+                continue;
+            }
+            let building = self_
+                .get_units()
+                .into_iter()
+                .find(|u| u.get_type() == step.building && u.is_completed() && u.is_idle());
+            if let Some(building) = building {
+                if counts.gas() < step.gas {
+                    continue;
+                }
+                if counts.minerals() >= step.minerals {
+                    if let Ok(true) = building.upgrade(step.upgrade) {
+                        println!("frame {} :: researching {:?}", counts.frame(), step.upgrade);
+                        step.started = true;
+                    }
+                }
+                // set aside money for the research whether it started or not
+                counts.spent(step.minerals, step.gas);
+            }
+        }
+    }
+
+    /// gas for research we haven't started yet
+    pub fn upcoming_gas(&self) -> i32 {
+        self.steps
+            .iter()
+            .filter(|s| !s.started)
+            .map(|s| s.gas)
+            .sum()
+    }
+}
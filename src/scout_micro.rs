@@ -0,0 +1,447 @@
+use crate::{seen::ScoutReport, worker_defense::CombatUnit};
+use rsbwapi::{Position, TilePosition, UnitId, UnitType, WeaponType};
+use std::collections::HashMap;
+
+// how far out from the middle of their main to circle
+const CIRCLE_RADIUS: i32 = 32 * 9;
+const WAYPOINTS: i32 = 8;
+// close enough to a waypoint to move on to the next
+const ARRIVED_DISTANCE: u32 = 48;
+// give up on a waypoint we can't get to after this long
+const WAYPOINT_FRAMES: i32 = 24 * 8;
+// buildings this far from the middle of their main are part of it
+const MAIN_RADIUS: u32 = 32 * 12;
+// units that can shoot us this close mean we're being chased
+const CHASE_DISTANCE: u32 = 32 * 4;
+// run home before it's too late
+const FLEE_HP: i32 = 20;
+// how long to sit on their natural
+const DELAY_FRAMES: i32 = 24 * 60;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Phase {
+    Circle { waypoint: usize, since: Option<i32> },
+    BlockGas(TilePosition),
+    DelayExpansion { since: Option<i32> },
+    Home,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum ScoutAction {
+    Move(Position),
+    /// turn the drone into an extractor on their geyser
+    BlockGas(TilePosition),
+    /// what we saw on the way around their main
+    Report(ScoutReport),
+    /// back home, the drone can go mining
+    Done,
+}
+
+/// The worker scout once it has found their main: it circles the main to
+/// see what they've got, blocks a gas or sits on their natural if it can
+/// and heads home when it's chased
+pub(crate) struct DroneScout {
+    pub drone: UnitId,
+    main: Position,
+    /// the middle of where their natural's resource depot goes
+    natural: Option<Position>,
+    home: Position,
+    waypoints: Vec<Position>,
+    phase: Phase,
+    // buildings in their main we've seen on the way around
+    buildings: HashMap<UnitId, UnitType>,
+}
+
+impl DroneScout {
+    /// `main` and `natural` are the top left of where their resource depots
+    /// go, `map` is the map size in tiles
+    pub fn new(
+        drone: UnitId,
+        from: Position,
+        main: TilePosition,
+        natural: Option<TilePosition>,
+        home: TilePosition,
+        map: TilePosition,
+    ) -> Self {
+        let size = UnitType::Zerg_Hatchery.tile_size().to_position();
+        let middle = |tile: TilePosition| {
+            tile.to_position()
+                + Position {
+                    x: size.x / 2,
+                    y: size.y / 2,
+                }
+        };
+        let main = middle(main);
+        let edge = map.to_position() - Position { x: 1, y: 1 };
+        let mut waypoints: Vec<Position> = (0..WAYPOINTS)
+            .map(|i| {
+                // clockwise from straight up
+                let angle = i as f64 * std::f64::consts::TAU / WAYPOINTS as f64;
+                Position {
+                    x: (main.x + (angle.sin() * CIRCLE_RADIUS as f64) as i32).clamp(0, edge.x),
+                    y: (main.y - (angle.cos() * CIRCLE_RADIUS as f64) as i32).clamp(0, edge.y),
+                }
+            })
+            .collect();
+        // start from wherever is closest
+        let closest = (0..waypoints.len())
+            .min_by_key(|i| waypoints[*i].chebyshev_distance(from))
+            .unwrap_or(0);
+        waypoints.rotate_left(closest);
+        DroneScout {
+            drone,
+            main,
+            natural: natural.map(middle),
+            home: home.to_position(),
+            waypoints,
+            phase: Phase::Circle {
+                waypoint: 0,
+                since: None,
+            },
+            buildings: HashMap::new(),
+        }
+    }
+
+    fn chased<U: CombatUnit>(drone: &U, enemies: &[U]) -> bool {
+        drone.hit_points() < FLEE_HP
+            || enemies.iter().any(|e| {
+                let type_ = e.unit_type();
+                !type_.is_worker()
+                    && type_.ground_weapon() != WeaponType::None
+                    && e.position().chebyshev_distance(drone.position()) <= CHASE_DISTANCE
+            })
+    }
+
+    /// What the scout should do this frame. `free_geyser` is one of their
+    /// geysers we could block, if we can spare the drone
+    pub fn on_frame<U: CombatUnit>(
+        &mut self,
+        frame: i32,
+        drone: &U,
+        enemies: &[U],
+        free_geyser: Option<TilePosition>,
+    ) -> ScoutAction {
+        if self.phase != Phase::Home && Self::chased(drone, enemies) {
+            println!(
+                "frame {} :: scout {} is chased, going home",
+                frame, self.drone
+            );
+            self.phase = Phase::Home;
+        }
+        let position = drone.position();
+        match self.phase {
+            Phase::Circle { waypoint: i, since } => {
+                self.look(enemies);
+                let since = since.unwrap_or(frame);
+                let waypoint = self.waypoints[i];
+                let arrived = position.chebyshev_distance(waypoint) <= ARRIVED_DISTANCE;
+                if !arrived && frame - since < WAYPOINT_FRAMES {
+                    self.phase = Phase::Circle {
+                        waypoint: i,
+                        since: Some(since),
+                    };
+                    return ScoutAction::Move(waypoint);
+                }
+                if !arrived {
+                    // off the map's walkable area or behind a wall
+                    println!(
+                        "frame {} :: scout {} can't get to {:?}, skipping it",
+                        frame, self.drone, waypoint
+                    );
+                }
+                if i + 1 < self.waypoints.len() {
+                    self.phase = Phase::Circle {
+                        waypoint: i + 1,
+                        since: Some(frame),
+                    };
+                    return ScoutAction::Move(self.waypoints[i + 1]);
+                }
+                self.phase = match free_geyser {
+                    Some(geyser) => Phase::BlockGas(geyser),
+                    None => Phase::DelayExpansion { since: None },
+                };
+                ScoutAction::Report(self.report(frame))
+            }
+            Phase::BlockGas(geyser) if free_geyser == Some(geyser) => ScoutAction::BlockGas(geyser),
+            Phase::BlockGas(_) => {
+                // they took it first
+                self.phase = Phase::DelayExpansion { since: None };
+                self.on_frame(frame, drone, enemies, free_geyser)
+            }
+            Phase::DelayExpansion { since } => {
+                let spot = match self.natural {
+                    Some(natural) => natural,
+                    None => {
+                        self.phase = Phase::Home;
+                        return ScoutAction::Move(self.home);
+                    }
+                };
+                match since {
+                    Some(since) if frame - since >= DELAY_FRAMES => {
+                        self.phase = Phase::Home;
+                        ScoutAction::Move(self.home)
+                    }
+                    Some(_) => ScoutAction::Move(spot),
+                    None => {
+                        if position.chebyshev_distance(spot) <= ARRIVED_DISTANCE {
+                            self.phase = Phase::DelayExpansion { since: Some(frame) };
+                        }
+                        ScoutAction::Move(spot)
+                    }
+                }
+            }
+            Phase::Home => {
+                if position.chebyshev_distance(self.home) <= ARRIVED_DISTANCE {
+                    ScoutAction::Done
+                } else {
+                    ScoutAction::Move(self.home)
+                }
+            }
+        }
+    }
+
+    /// remember the buildings in their main that are in sight
+    fn look<U: CombatUnit>(&mut self, enemies: &[U]) {
+        for e in enemies {
+            let in_main = e.position().chebyshev_distance(self.main) <= MAIN_RADIUS;
+            if in_main && e.unit_type().is_building() {
+                self.buildings.insert(e.id(), e.unit_type());
+            }
+        }
+    }
+
+    fn report(&self, frame: i32) -> ScoutReport {
+        ScoutReport {
+            frame,
+            buildings: self.buildings.len(),
+            gases: self.buildings.values().filter(|t| t.is_refinery()).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{tp, FakeUnit};
+
+    fn unit(id: UnitId, unit_type: UnitType, position: Position) -> FakeUnit {
+        FakeUnit::new(id, unit_type, position)
+    }
+
+    const MAP: TilePosition = TilePosition { x: 128, y: 128 };
+    const MAIN: TilePosition = TilePosition { x: 60, y: 60 };
+    const NATURAL: TilePosition = TilePosition { x: 80, y: 60 };
+    const HOME: TilePosition = TilePosition { x: 8, y: 8 };
+
+    fn middle() -> Position {
+        MAIN.to_position() + Position { x: 64, y: 48 }
+    }
+
+    fn natural() -> Position {
+        NATURAL.to_position() + Position { x: 64, y: 48 }
+    }
+
+    /// walk the drone wherever it's told until it wants something else
+    fn follow(
+        scout: &mut DroneScout,
+        frame: &mut i32,
+        drone: &mut FakeUnit,
+        enemies: &[FakeUnit],
+        geyser: Option<TilePosition>,
+    ) -> (Vec<Position>, ScoutAction) {
+        let mut visited = vec![];
+        loop {
+            *frame += 1;
+            match scout.on_frame(*frame, drone, enemies, geyser) {
+                ScoutAction::Move(to) => {
+                    if visited.last() != Some(&to) {
+                        visited.push(to);
+                    }
+                    drone.position = to;
+                }
+                action => return (visited, action),
+            }
+            assert!(*frame < 10_000, "scout never finished");
+        }
+    }
+
+    #[test]
+    fn test_circle() {
+        let mut drone = unit(
+            1,
+            UnitType::Zerg_Drone,
+            middle() + Position { x: 400, y: 0 },
+        );
+        let mut scout = DroneScout::new(1, drone.position, MAIN, None, HOME, MAP);
+        let enemies = vec![
+            unit(10, UnitType::Protoss_Nexus, middle()),
+            unit(
+                11,
+                UnitType::Protoss_Assimilator,
+                middle() + Position { x: 200, y: 0 },
+            ),
+            unit(
+                12,
+                UnitType::Protoss_Gateway,
+                middle() - Position { x: 200, y: 100 },
+            ),
+            unit(13, UnitType::Protoss_Probe, middle()),
+            // their natural isn't their main
+            unit(14, UnitType::Protoss_Nexus, NATURAL.to_position()),
+        ];
+        let mut frame = 0;
+        let (visited, action) = follow(&mut scout, &mut frame, &mut drone, &enemies, None);
+        assert_eq!(visited.len(), 8, "all the way around");
+        assert_eq!(
+            visited[0],
+            middle()
+                + Position {
+                    x: CIRCLE_RADIUS,
+                    y: 0
+                },
+            "closest first"
+        );
+        assert_eq!(
+            visited[1],
+            middle() + Position { x: 203, y: 203 },
+            "clockwise"
+        );
+        assert_eq!(
+            action,
+            ScoutAction::Report(ScoutReport {
+                frame,
+                buildings: 3,
+                gases: 1,
+            })
+        );
+
+        let (visited, action) = follow(&mut scout, &mut frame, &mut drone, &enemies, None);
+        assert_eq!(visited, vec![HOME.to_position()], "no natural to sit on");
+        assert_eq!(action, ScoutAction::Done);
+    }
+
+    #[test]
+    fn test_counted_on_the_way() {
+        let mut drone = unit(1, UnitType::Zerg_Drone, middle());
+        let mut scout = DroneScout::new(1, drone.position, MAIN, None, HOME, MAP);
+        let gateway = vec![unit(
+            10,
+            UnitType::Protoss_Gateway,
+            middle() - Position { x: 200, y: 100 },
+        )];
+        let mut frame = 1;
+        scout.on_frame(frame, &drone, &gateway, None);
+        // out of sight for the rest of the way
+        let (_, action) = follow(&mut scout, &mut frame, &mut drone, &[], None);
+        assert_eq!(
+            action,
+            ScoutAction::Report(ScoutReport {
+                frame,
+                buildings: 1,
+                gases: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unreachable_waypoints() {
+        // stuck where it is, it never gets to a waypoint
+        let drone = unit(1, UnitType::Zerg_Drone, middle());
+        let mut scout = DroneScout::new(1, drone.position, MAIN, None, HOME, MAP);
+        let mut frame = 0;
+        let action = loop {
+            frame += 1;
+            match scout.on_frame(frame, &drone, &[], None) {
+                ScoutAction::Move(_) => {}
+                action => break action,
+            }
+            assert!(frame < 10_000, "stuck circling forever");
+        };
+        assert!(matches!(action, ScoutAction::Report(_)), "{:?}", action);
+        assert_eq!(frame, WAYPOINT_FRAMES * WAYPOINTS + 1, "a go at each one");
+    }
+
+    #[test]
+    fn test_block_gas_and_delay() {
+        let mut drone = unit(1, UnitType::Zerg_Drone, middle());
+        let mut scout = DroneScout::new(1, drone.position, MAIN, Some(NATURAL), HOME, MAP);
+        let mut frame = 0;
+        let geyser = Some(tp(66, 60));
+        follow(&mut scout, &mut frame, &mut drone, &[], geyser);
+        assert_eq!(
+            scout.on_frame(frame, &drone, &[], geyser),
+            ScoutAction::BlockGas(tp(66, 60))
+        );
+
+        // they got there first, sit on their natural instead
+        frame += 1;
+        assert_eq!(
+            scout.on_frame(frame, &drone, &[], None),
+            ScoutAction::Move(natural())
+        );
+        drone.position = natural();
+        let (_, action) = follow(&mut scout, &mut frame, &mut drone, &[], None);
+        assert_eq!(action, ScoutAction::Done);
+
+        let mut drone = unit(1, UnitType::Zerg_Drone, natural());
+        let mut delay = DroneScout::new(1, drone.position, MAIN, Some(NATURAL), HOME, MAP);
+        delay.phase = Phase::DelayExpansion { since: None };
+        let mut frame = 100;
+        assert_eq!(
+            delay.on_frame(frame, &drone, &[], None),
+            ScoutAction::Move(natural())
+        );
+        frame += DELAY_FRAMES - 1;
+        assert_eq!(
+            delay.on_frame(frame, &drone, &[], None),
+            ScoutAction::Move(natural()),
+            "still delaying"
+        );
+        frame += 1;
+        assert_eq!(
+            delay.on_frame(frame, &drone, &[], None),
+            ScoutAction::Move(HOME.to_position())
+        );
+        drone.position = HOME.to_position();
+        assert_eq!(
+            delay.on_frame(frame + 1, &drone, &[], None),
+            ScoutAction::Done
+        );
+    }
+
+    #[test]
+    fn test_chased() {
+        let mut drone = unit(1, UnitType::Zerg_Drone, middle());
+        let mut scout = DroneScout::new(1, drone.position, MAIN, Some(NATURAL), HOME, MAP);
+        let probe = vec![unit(10, UnitType::Protoss_Probe, middle())];
+        assert!(
+            matches!(scout.on_frame(1, &drone, &probe, None), ScoutAction::Move(to) if to != HOME.to_position()),
+            "probes don't scare us"
+        );
+
+        drone.hit_points = 15;
+        assert_eq!(
+            scout.on_frame(2, &drone, &probe, None),
+            ScoutAction::Move(HOME.to_position()),
+            "too hurt to stay"
+        );
+
+        let mut drone = unit(1, UnitType::Zerg_Drone, middle());
+        let mut scout = DroneScout::new(1, drone.position, MAIN, Some(NATURAL), HOME, MAP);
+        let zealot = vec![unit(
+            10,
+            UnitType::Protoss_Zealot,
+            middle() + Position { x: 64, y: 0 },
+        )];
+        assert_eq!(
+            scout.on_frame(1, &drone, &zealot, None),
+            ScoutAction::Move(HOME.to_position())
+        );
+        drone.hit_points = 40;
+        assert_eq!(
+            scout.on_frame(2, &drone, &[], None),
+            ScoutAction::Move(HOME.to_position()),
+            "doesn't come back"
+        );
+    }
+}
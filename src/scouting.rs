@@ -1,65 +1,483 @@
-use rsbwapi::{Game, TilePosition, Unit, UnitId, UnitType};
+use crate::{bases::BASE_RADIUS, build_position::get_hatches, seen::HaveSeen};
+use rsbwapi::{Game, Position, TilePosition, UnitId, UnitType};
 use std::collections::VecDeque;
 
-pub(crate) struct Scout {
-    unit: Unit,
-    current_destination: Option<TilePosition>,
+// a scout this close to its destination has seen it
+const ARRIVED_DISTANCE: u32 = 32 * 2;
+
+// when to go back and look again
+const TECH_RESCOUT_FRAMES: i32 = 24 * 60 * 2;
+const NATURAL_FRAME: i32 = 24 * 60 * 3;
+const NATURAL_RESCOUT_FRAMES: i32 = 24 * 60 * 2;
+const EXPANSION_FRAME: i32 = 24 * 60 * 5;
+const EXPANSION_RESCOUT_FRAMES: i32 = 24 * 60 * 3;
+
+/// What scouting needs to know about the map
+trait ScoutMap {
+    fn frame(&self) -> i32;
+    /// start locations the enemy could be at
+    fn start_locations(&self) -> Vec<TilePosition>;
+    /// where we think they are until we know
+    fn likely_enemy_main(&self) -> Option<TilePosition>;
+    /// possible bases, by their geysers
+    fn bases(&self) -> Vec<TilePosition>;
+    fn our_bases(&self) -> Vec<TilePosition>;
+    fn enemy_main(&self) -> Option<TilePosition>;
+    fn is_visible(&self, tile: TilePosition) -> bool;
+}
+
+struct GameScoutMap<'a> {
+    game: &'a Game,
+    seen: &'a HaveSeen,
+}
+
+impl<'a> ScoutMap for GameScoutMap<'a> {
+    fn frame(&self) -> i32 {
+        self.game.get_frame_count()
+    }
+
+    fn start_locations(&self) -> Vec<TilePosition> {
+        self.seen.possible_starts()
+    }
+
+    fn likely_enemy_main(&self) -> Option<TilePosition> {
+        self.seen.likely_enemy_main()
+    }
+
+    fn bases(&self) -> Vec<TilePosition> {
+        self.seen.get_gas_locs().into_iter().copied().collect()
+    }
+
+    fn our_bases(&self) -> Vec<TilePosition> {
+        get_hatches(self.game)
+            .into_iter()
+            .map(|h| h.get_tile_position())
+            .collect()
+    }
+
+    fn enemy_main(&self) -> Option<TilePosition> {
+        self.seen.enemy_main()
+    }
+
+    fn is_visible(&self, tile: TilePosition) -> bool {
+        self.game.is_visible(tile)
+    }
+}
+
+/// Things we want to find out
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub(crate) enum ScoutGoal {
+    FindMain,
+    /// their gas and tech buildings
+    TechCheck,
+    /// whether and when they took their natural
+    NaturalTiming,
+    Expansions,
+}
+
+impl ScoutGoal {
+    /// who to send, best first. Drones are for the first look, going back
+    /// shouldn't cost us mining
+    pub fn scout_types(&self, rescout: bool) -> &'static [UnitType] {
+        match self {
+            ScoutGoal::FindMain => &[UnitType::Zerg_Drone],
+            ScoutGoal::TechCheck | ScoutGoal::NaturalTiming if rescout => {
+                &[UnitType::Zerg_Zergling, UnitType::Zerg_Overlord]
+            }
+            ScoutGoal::TechCheck | ScoutGoal::NaturalTiming => {
+                &[UnitType::Zerg_Zergling, UnitType::Zerg_Drone]
+            }
+            ScoutGoal::Expansions => &[UnitType::Zerg_Zergling, UnitType::Zerg_Overlord],
+        }
+    }
+
+    fn rescout_frames(&self) -> Option<i32> {
+        match self {
+            ScoutGoal::FindMain => None,
+            ScoutGoal::TechCheck => Some(TECH_RESCOUT_FRAMES),
+            ScoutGoal::NaturalTiming => Some(NATURAL_RESCOUT_FRAMES),
+            ScoutGoal::Expansions => Some(EXPANSION_RESCOUT_FRAMES),
+        }
+    }
+}
+
+struct Goal {
+    kind: ScoutGoal,
     destinations: VecDeque<TilePosition>,
+    scout: Option<UnitId>,
+    // when we last finished it
+    done: Option<i32>,
+}
+
+/// Keeps track of what we want to scout and who's scouting it. Destinations
+/// belong to the goal so a replacement scout carries on where the last one
+/// died
+pub(crate) struct ScoutManager {
+    goals: Vec<Goal>,
 }
 
-impl Scout {
-    pub fn new(unit: Unit) -> Self {
-        Scout {
-            unit,
-            current_destination: None,
-            destinations: VecDeque::new(),
+impl ScoutManager {
+    pub fn new() -> Self {
+        let goals = [
+            ScoutGoal::FindMain,
+            ScoutGoal::TechCheck,
+            ScoutGoal::NaturalTiming,
+            ScoutGoal::Expansions,
+        ];
+        ScoutManager {
+            goals: goals
+                .into_iter()
+                .map(|kind| Goal {
+                    kind,
+                    destinations: VecDeque::new(),
+                    scout: None,
+                    done: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_scouting(&self, unit: UnitId) -> bool {
+        self.goals.iter().any(|g| g.scout == Some(unit))
+    }
+
+    /// what a scout is out looking for
+    pub fn goal_of(&self, unit: UnitId) -> Option<ScoutGoal> {
+        self.goals
+            .iter()
+            .find(|g| g.scout == Some(unit))
+            .map(|g| g.kind)
+    }
+
+    /// we've done this goal before and are going back for another look
+    pub fn is_rescout(&self, kind: ScoutGoal) -> bool {
+        self.goals
+            .iter()
+            .any(|g| g.kind == kind && g.done.is_some())
+    }
+
+    pub fn scouts(&self) -> Vec<UnitId> {
+        self.goals.iter().filter_map(|g| g.scout).collect()
+    }
+
+    /// goals with somewhere to go and nobody going there
+    pub fn unscouted(&self) -> Vec<ScoutGoal> {
+        self.goals
+            .iter()
+            .filter(|g| g.scout.is_none() && !g.destinations.is_empty())
+            .map(|g| g.kind)
+            .collect()
+    }
+
+    pub fn assign(&mut self, kind: ScoutGoal, unit: UnitId) {
+        if let Some(goal) = self.goals.iter_mut().find(|g| g.kind == kind) {
+            goal.scout = Some(unit);
         }
     }
 
-    pub fn is_alive(&self) -> bool {
-        self.unit.exists() && self.unit.get_type() == UnitType::Zerg_Drone
+    /// the scout died or had to run, its destinations wait for the next one
+    pub fn lost(&mut self, unit: UnitId) {
+        for goal in self.goals.iter_mut().filter(|g| g.scout == Some(unit)) {
+            goal.scout = None;
+        }
     }
 
-    pub fn is_done(&self) -> bool {
-        self.destinations.is_empty() && self.current_destination.is_none() && self.unit.is_idle()
+    pub fn on_frame(&mut self, game: &Game, seen: &HaveSeen) {
+        self.plan(&GameScoutMap { game, seen });
     }
 
-    pub fn get_id(&self) -> UnitId {
-        self.unit.get_id()
+    /// Where a scout should head next, None once its goal is done and it
+    /// can go home
+    pub fn next_destination(
+        &mut self,
+        game: &Game,
+        seen: &HaveSeen,
+        scout: UnitId,
+        position: Position,
+    ) -> Option<TilePosition> {
+        self.step(&GameScoutMap { game, seen }, scout, position)
     }
 
-    pub fn on_frame(&mut self, game: &Game) {
-        match self.current_destination {
-            Some(dest)
-                if self
-                    .unit
-                    .get_position()
-                    .chebyshev_distance(dest.to_position())
-                    < 3
-                    || (self.unit.is_stuck() && !self.unit.is_moving())
-                    || game.is_visible(dest) =>
-            {
-                println!("scout {} arrived at {}", self.unit.get_id(), dest);
-                self.current_destination = None;
+    /// start goals that are due, drop ones we've learned the answer to
+    fn plan(&mut self, map: &impl ScoutMap) {
+        let frame = map.frame();
+        let main = map.enemy_main();
+        for goal in self.goals.iter_mut() {
+            if goal.kind == ScoutGoal::FindMain && main.is_some() && goal.done.is_none() {
+                println!("frame {} :: found the enemy main at {:?}", frame, main);
+                goal.destinations.clear();
+                goal.done = Some(frame);
+                continue;
             }
-            Some(dest) => {
-                self.unit.move_(dest.to_position()).ok();
+            if !goal.destinations.is_empty() {
+                continue;
+            }
+            let due = match (goal.done, goal.kind.rescout_frames()) {
+                (None, _) => true,
+                (Some(done), Some(every)) => frame - done >= every,
+                (Some(_), None) => false,
+            };
+            if due {
+                goal.destinations = destinations(goal.kind, map, main).into();
+                if !goal.destinations.is_empty() {
+                    println!(
+                        "frame {} :: scouting for {:?} at {:?}",
+                        frame, goal.kind, goal.destinations
+                    );
+                }
             }
-            None => self.current_destination = self.destinations.pop_front(),
         }
     }
 
-    pub fn go_later(&mut self, dest: TilePosition) {
-        self.destinations.push_back(dest);
+    fn step(
+        &mut self,
+        map: &impl ScoutMap,
+        scout: UnitId,
+        position: Position,
+    ) -> Option<TilePosition> {
+        let goal = self.goals.iter_mut().find(|g| g.scout == Some(scout))?;
+        let starts = map.start_locations();
+        if goal.kind == ScoutGoal::FindMain {
+            // skip ahead to where they most likely are
+            let likely = map.likely_enemy_main();
+            let i = goal.destinations.iter().position(|d| Some(*d) == likely);
+            if let Some(dest) = i.and_then(|i| goal.destinations.remove(i)) {
+                goal.destinations.push_front(dest);
+            }
+        }
+        while let Some(dest) = goal.destinations.front() {
+            let ruled_out = goal.kind == ScoutGoal::FindMain && !starts.contains(dest);
+            let arrived = position.chebyshev_distance(dest.to_position()) <= ARRIVED_DISTANCE
+                || map.is_visible(*dest)
+                || ruled_out;
+            if !arrived {
+                return Some(*dest);
+            }
+            goal.destinations.pop_front();
+        }
+        println!("frame {} :: done scouting {:?}", map.frame(), goal.kind);
+        goal.done = Some(map.frame());
+        goal.scout = None;
+        None
     }
+}
+
+/// the closest base to a main that isn't the main
+pub(crate) fn natural_base(main: TilePosition, bases: &[TilePosition]) -> Option<TilePosition> {
+    bases
+        .iter()
+        .filter(|b| b.chebyshev_distance(main) > BASE_RADIUS)
+        .min_by_key(|b| b.distance_squared(main))
+        .copied()
+}
+
+fn destinations(
+    kind: ScoutGoal,
+    map: &impl ScoutMap,
+    main: Option<TilePosition>,
+) -> Vec<TilePosition> {
+    let frame = map.frame();
+    let ours = map.our_bases();
+    let near = |a: TilePosition, b: TilePosition| a.chebyshev_distance(b) <= BASE_RADIUS;
+    match (kind, main) {
+        (ScoutGoal::FindMain, None) => {
+            // closest first
+            let mut starts = map.start_locations();
+            if let Some(home) = ours.first() {
+                starts.sort_by_key(|s| s.distance_squared(*home));
+            }
+            starts
+        }
+        (ScoutGoal::TechCheck, Some(main)) => vec![main],
+        (ScoutGoal::NaturalTiming, Some(main)) if frame >= NATURAL_FRAME => {
+            natural_base(main, &map.bases()).into_iter().collect()
+        }
+        (ScoutGoal::Expansions, _) if frame >= EXPANSION_FRAME => map
+            .bases()
+            .into_iter()
+            .filter(|b| main.map_or(true, |m| !near(*b, m)) && !ours.iter().any(|o| near(*b, *o)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+    use std::collections::HashSet;
+
+    #[derive(Default)]
+    struct FakeMap {
+        frame: i32,
+        starts: Vec<TilePosition>,
+        bases: Vec<TilePosition>,
+        ours: Vec<TilePosition>,
+        main: Option<TilePosition>,
+        likely: Option<TilePosition>,
+        visible: HashSet<TilePosition>,
+    }
+
+    impl ScoutMap for FakeMap {
+        fn frame(&self) -> i32 {
+            self.frame
+        }
+        fn start_locations(&self) -> Vec<TilePosition> {
+            self.starts.clone()
+        }
+        fn likely_enemy_main(&self) -> Option<TilePosition> {
+            self.likely
+        }
+        fn bases(&self) -> Vec<TilePosition> {
+            self.bases.clone()
+        }
+        fn our_bases(&self) -> Vec<TilePosition> {
+            self.ours.clone()
+        }
+        fn enemy_main(&self) -> Option<TilePosition> {
+            self.main
+        }
+        fn is_visible(&self, tile: TilePosition) -> bool {
+            self.visible.contains(&tile)
+        }
+    }
+
+    const FAR_AWAY: Position = Position { x: 0, y: 0 };
+
+    /// a four player map with us in the top left
+    fn four_player() -> FakeMap {
+        FakeMap {
+            starts: vec![tp(120, 120), tp(120, 8), tp(8, 120)],
+            bases: vec![
+                tp(10, 10),
+                tp(30, 10),
+                tp(118, 122),
+                tp(100, 120),
+                tp(60, 60),
+            ],
+            ours: vec![tp(8, 8)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_main() {
+        let mut map = four_player();
+        let mut sm = ScoutManager::new();
+        sm.plan(&map);
+        assert_eq!(sm.unscouted(), vec![ScoutGoal::FindMain]);
+        sm.assign(ScoutGoal::FindMain, 1);
+        assert!(sm.is_scouting(1));
+        assert_eq!(
+            sm.step(&map, 1, FAR_AWAY),
+            Some(tp(120, 8)),
+            "closest first"
+        );
+
+        map.visible.insert(tp(120, 8));
+        assert_eq!(sm.step(&map, 1, FAR_AWAY), Some(tp(8, 120)), "nobody home");
+
+        map.main = Some(tp(8, 120));
+        map.frame = 24 * 60 * 2;
+        sm.plan(&map);
+        assert_eq!(sm.step(&map, 1, FAR_AWAY), None, "found them");
+        assert!(!sm.is_scouting(1));
+        assert_eq!(sm.unscouted(), vec![ScoutGoal::TechCheck]);
+    }
+
+    #[test]
+    fn test_likely_main() {
+        let mut map = four_player();
+        let mut sm = ScoutManager::new();
+        sm.plan(&map);
+        sm.assign(ScoutGoal::FindMain, 1);
+        map.likely = Some(tp(120, 120));
+        assert_eq!(
+            sm.step(&map, 1, FAR_AWAY),
+            Some(tp(120, 120)),
+            "their scout came from there"
+        );
+
+        // an overlord saw it was empty
+        map.starts.retain(|s| *s != tp(120, 120));
+        map.likely = Some(tp(8, 120));
+        assert_eq!(sm.step(&map, 1, FAR_AWAY), Some(tp(8, 120)));
+        map.starts.retain(|s| *s != tp(8, 120));
+        map.likely = Some(tp(120, 8));
+        assert_eq!(
+            sm.step(&map, 1, FAR_AWAY),
+            Some(tp(120, 8)),
+            "the last place left"
+        );
+    }
+
+    #[test]
+    fn test_replacement_scout() {
+        let mut map = four_player();
+        let mut sm = ScoutManager::new();
+        sm.plan(&map);
+        sm.assign(ScoutGoal::FindMain, 1);
+        map.visible.insert(tp(120, 8));
+        sm.step(&map, 1, FAR_AWAY);
+
+        sm.lost(1);
+        map.visible.clear();
+        sm.plan(&map);
+        assert_eq!(sm.unscouted(), vec![ScoutGoal::FindMain]);
+        sm.assign(ScoutGoal::FindMain, 2);
+        assert_eq!(
+            sm.step(&map, 2, FAR_AWAY),
+            Some(tp(8, 120)),
+            "carry on where the last scout was"
+        );
+    }
+
+    #[test]
+    fn test_rescout() {
+        let mut map = four_player();
+        map.main = Some(tp(120, 120));
+        let mut sm = ScoutManager::new();
+        sm.plan(&map);
+        assert_eq!(sm.unscouted(), vec![ScoutGoal::TechCheck]);
+        assert!(!sm.is_rescout(ScoutGoal::TechCheck));
+        sm.assign(ScoutGoal::TechCheck, 1);
+        assert_eq!(sm.goal_of(1), Some(ScoutGoal::TechCheck));
+        let main = tp(120, 120).to_position();
+        assert_eq!(sm.step(&map, 1, main), None, "had a look");
+
+        map.frame = TECH_RESCOUT_FRAMES - 1;
+        sm.plan(&map);
+        assert_eq!(sm.unscouted(), vec![]);
+        map.frame = NATURAL_FRAME;
+        sm.plan(&map);
+        assert_eq!(
+            sm.unscouted(),
+            vec![ScoutGoal::TechCheck, ScoutGoal::NaturalTiming],
+            "time for another look"
+        );
+        assert!(sm.is_rescout(ScoutGoal::TechCheck));
+        assert!(
+            !ScoutGoal::TechCheck
+                .scout_types(true)
+                .contains(&UnitType::Zerg_Drone),
+            "not another drone"
+        );
+        assert!(!sm.is_rescout(ScoutGoal::NaturalTiming));
+        sm.assign(ScoutGoal::NaturalTiming, 2);
+        assert_eq!(sm.step(&map, 2, FAR_AWAY), Some(tp(100, 120)));
 
-    #[allow(unused)]
-    pub fn go_now(&mut self, dest: TilePosition) {
-        println!("sending scout {} to {} NOW", self.unit.get_id(), dest);
-        if let Some(current) = self.current_destination {
-            self.destinations.push_front(current);
-            self.current_destination = None;
+        map.frame = EXPANSION_FRAME;
+        sm.plan(&map);
+        sm.assign(ScoutGoal::Expansions, 3);
+        let mut expansions = vec![];
+        while let Some(base) = sm.step(&map, 3, FAR_AWAY) {
+            expansions.push(base);
+            map.visible.insert(base);
         }
-        self.destinations.push_front(dest);
+        assert_eq!(
+            expansions,
+            vec![tp(30, 10), tp(100, 120), tp(60, 60)],
+            "bases that aren't ours or their main"
+        );
     }
 }
@@ -1,6 +1,20 @@
-use rsbwapi::{Game, ScaledPosition, TilePosition, Unit, UnitId, UnitType};
+use crate::{
+    bases::BASE_RADIUS,
+    build_position::cartesian_center,
+    estimate::{estimate_army, ArmyEstimate},
+    neutrals::{Neutral, NeutralKind},
+};
+use rsbwapi::{Game, Position, ScaledPosition, TilePosition, Unit, UnitId, UnitType};
 use std::collections::{HashMap, HashSet};
 
+// after this scouts have seen our base and are heading home
+const SCOUT_FRAMES: i32 = 24 * 60 * 4;
+// how much a start location the scout isn't coming from still counts
+const AWAY_WEIGHT: f32 = 0.1;
+// how much more likely a building near a start location makes it, it
+// could be a proxy
+const BUILDING_WEIGHT: f32 = 4.0;
+
 #[derive(Debug, PartialEq)]
 pub(crate) struct SeenUnit {
     pub unit_type: UnitType,
@@ -8,6 +22,10 @@ pub(crate) struct SeenUnit {
     pub hp: i32,
     pub shields: i32,
     pub frame: i32,
+    /// still under construction or morphing when we last saw it
+    pub completed: bool,
+    /// terran buildings floating somewhere
+    pub lifted: bool,
 }
 
 impl SeenUnit {
@@ -18,17 +36,66 @@ impl SeenUnit {
             hp: unit.get_hit_points(),
             shields: unit.get_shields(),
             frame,
+            completed: unit.is_completed() && !unit.is_morphing(),
+            lifted: unit.is_lifted(),
+        }
+    }
+
+    /// About when it started building, from how far along it was when we saw
+    /// it. A finished one started a build time ago at the latest
+    pub fn started(&self) -> i32 {
+        let build_time = self.unit_type.build_time();
+        let (from, _) = self.unit_type.what_builds();
+        if self.completed {
+            self.frame - build_time
+        } else if from.is_building() && !self.unit_type.is_addon() {
+            // a lair keeps the hatchery's hp, there's no telling
+            self.frame
+        } else {
+            // buildings start with a tenth of their hp and gain the rest
+            let max = self.unit_type.max_hit_points().max(1) as f32;
+            let progress = ((self.hp as f32 / max - 0.1) / 0.9).clamp(0.0, 1.0);
+            self.frame - (progress * build_time as f32).round() as i32
+        }
+    }
+
+    #[cfg(test)]
+    pub fn fake(unit_type: UnitType, frame: i32) -> Self {
+        SeenUnit {
+            unit_type,
+            position: ScaledPosition { x: 0, y: 0 },
+            hp: unit_type.max_hit_points(),
+            shields: unit_type.max_shields(),
+            frame,
+            completed: true,
+            lifted: false,
         }
     }
 }
 
+/// What our scout counted on its way around their main
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct ScoutReport {
+    pub frame: i32,
+    pub buildings: usize,
+    pub gases: usize,
+}
+
 // Keep track of things we see that don't belong to us
 pub(crate) struct HaveSeen {
     gas_locs: HashSet<TilePosition>,
     minerals: HashSet<TilePosition>,
     units: HashMap<UnitId, SeenUnit>,
     buildings: HashMap<UnitId, SeenUnit>,
-    building_counts: HashMap<UnitType, i8>,
+    // what each building was and about when it started, even once it's gone
+    first_seen: HashMap<UnitId, (UnitType, i32)>,
+    // mineral walls, neutral buildings and whatever else is on the map
+    neutrals: HashMap<UnitId, Neutral>,
+    // start locations the enemy could be at, with how likely each is
+    starts: HashMap<TilePosition, f32>,
+    scout_report: Option<ScoutReport>,
+    // the last frame we updated what's in sight
+    frame: i32,
 }
 
 impl HaveSeen {
@@ -38,12 +105,48 @@ impl HaveSeen {
             minerals: HashSet::new(),
             units: HashMap::new(),
             buildings: HashMap::new(),
-            building_counts: HashMap::new(),
+            first_seen: HashMap::new(),
+            neutrals: HashMap::new(),
+            starts: HashMap::new(),
+            scout_report: None,
+            frame: 0,
         }
     }
 
-    pub fn on_frame(&mut self, _game: &Game) {
-        // TODO: update frame counts
+    /// every start location but ours could be theirs
+    pub fn set_start_locations(&mut self, ours: TilePosition, starts: Vec<TilePosition>) {
+        self.starts = starts
+            .into_iter()
+            .filter(|s| *s != ours)
+            .map(|s| (s, 1.0))
+            .collect();
+    }
+
+    pub fn on_frame(&mut self, game: &Game) {
+        let frame = game.get_frame_count();
+        let visible = game
+            .enemy()
+            .map(|e| e.get_units())
+            .unwrap_or_default()
+            .iter()
+            .filter(|u| u.is_visible() && u.get_type() != UnitType::Unknown)
+            .map(|u| (u.get_id(), SeenUnit::new(u, frame)))
+            .collect();
+        self.update(frame, visible);
+
+        let looked_at: Vec<TilePosition> = self
+            .starts
+            .keys()
+            .filter(|s| game.is_visible(**s))
+            .copied()
+            .collect();
+        for start in looked_at {
+            if game.has_creep(start) {
+                self.found_start(start);
+            } else if self.buildings_at(start).is_empty() {
+                self.eliminate_start(start);
+            }
+        }
     }
 
     pub fn on_unit_discover(&mut self, game: &Game, unit: &Unit) {
@@ -57,23 +160,41 @@ impl HaveSeen {
             match type_ {
                 UnitType::Unknown => {}
                 _ if type_.is_mineral_field() => {
-                    self.minerals.insert(unit.get_tile_position().clone());
+                    let tile = unit.get_tile_position();
+                    match Neutral::new(type_, tile, unit.get_initial_resources()) {
+                        Some(blocker) => self.add_neutral(unit.get_id(), blocker),
+                        None => {
+                            self.minerals.insert(tile);
+                        }
+                    }
                 }
                 UnitType::Resource_Vespene_Geyser => {
                     self.gas_locs.insert(unit.get_tile_position().clone());
                 }
-                _ if type_.is_neutral() => {}
+                _ if type_.is_neutral() => {
+                    let tile = unit.get_tile_position();
+                    if let Some(neutral) = Neutral::new(type_, tile, unit.get_initial_resources()) {
+                        self.add_neutral(unit.get_id(), neutral);
+                    }
+                }
                 _ if type_.is_refinery() => {
                     self.gas_locs.insert(unit.get_tile_position().clone());
+                    if Some(unit.get_player()) == enemy {
+                        self.add_building(unit.get_id(), SeenUnit::new(unit, frame));
+                    }
                 }
                 _ if type_.is_building() => {
                     if Some(unit.get_player()) == enemy {
-                        self.buildings
-                            .insert(unit.get_id(), SeenUnit::new(unit, frame));
+                        self.building_near_start(unit.get_tile_position(), type_);
+                        self.add_building(unit.get_id(), SeenUnit::new(unit, frame));
                     }
                 }
                 _ => {
                     if Some(unit.get_player()) == enemy {
+                        if frame < SCOUT_FRAMES && (type_.is_worker() || type_.is_flyer()) {
+                            let velocity = (unit.get_velocity_x(), unit.get_velocity_y());
+                            self.enemy_scout(unit.get_position(), velocity);
+                        }
                         self.units.insert(unit.get_id(), SeenUnit::new(unit, frame));
                     }
                 }
@@ -81,15 +202,208 @@ impl HaveSeen {
         }
     }
 
+    /// hatcheries into lairs, creep into sunkens, drones into buildings
+    pub fn on_unit_morph(&mut self, game: &Game, unit: &Unit) {
+        if game.enemy() == Some(unit.get_player()) {
+            self.refresh(unit.get_id(), SeenUnit::new(unit, game.get_frame_count()));
+        }
+    }
+
+    /// Refresh the enemy units in sight this frame, anything else keeps
+    /// where and when we last saw it
+    pub fn update(&mut self, frame: i32, visible: Vec<(UnitId, SeenUnit)>) {
+        self.frame = frame;
+        for (id, unit) in visible {
+            self.refresh(id, unit);
+        }
+    }
+
+    fn refresh(&mut self, id: UnitId, unit: SeenUnit) {
+        // drones and probes become buildings
+        if unit.unit_type.is_building() {
+            self.units.remove(&id);
+            self.add_building(id, unit);
+        } else {
+            self.buildings.remove(&id);
+            self.units.insert(id, unit);
+        }
+    }
+
+    fn add_building(&mut self, id: UnitId, unit: SeenUnit) {
+        self.first_seen
+            .entry(id)
+            .or_insert((unit.unit_type, unit.started()));
+        self.buildings.insert(id, unit);
+    }
+
+    /// every enemy building we've seen, in the order they were started
+    pub fn building_timeline(&self) -> Vec<(UnitType, i32)> {
+        let mut timeline: Vec<(UnitType, i32)> = self.first_seen.values().copied().collect();
+        timeline.sort_by_key(|(_, frame)| *frame);
+        timeline
+    }
+
+    /// frames since we last saw this unit
+    pub fn age(&self, unit: &SeenUnit) -> i32 {
+        self.frame - unit.frame
+    }
+
+    fn add_neutral(&mut self, id: UnitId, neutral: Neutral) {
+        if neutral.kind != NeutralKind::Critter {
+            println!(
+                "{:?} {:?} at {:?}",
+                neutral.kind, neutral.unit_type, neutral.tile
+            );
+        }
+        self.neutrals.insert(id, neutral);
+    }
+
     pub fn on_unit_destroy(&mut self, id: UnitId) {
         self.units.remove(&id);
-        if let Some(b) = self.buildings.remove(&id) {
-            self.building_counts
-                .entry(b.unit_type)
-                .and_modify(|c| *c -= 1);
+        self.buildings.remove(&id);
+        self.neutrals.remove(&id);
+    }
+
+    #[cfg(test)]
+    pub fn add_fake(&mut self, id: UnitId, unit: SeenUnit) {
+        if unit.unit_type.is_building() {
+            self.add_building(id, unit);
+        } else {
+            self.units.insert(id, unit);
         }
     }
 
+    /// enemy buildings of this type, finished or not
+    pub fn building_count(&self, unit_type: UnitType) -> usize {
+        self.buildings
+            .values()
+            .filter(|b| b.unit_type == unit_type)
+            .count()
+    }
+
+    /// enemy buildings of this type that are done
+    pub fn completed_count(&self, unit_type: UnitType) -> usize {
+        self.buildings
+            .values()
+            .filter(|b| b.unit_type == unit_type && b.completed)
+            .count()
+    }
+
+    /// the enemy buildings at a base, not counting any that have flown off
+    pub fn buildings_at(&self, base: TilePosition) -> Vec<&SeenUnit> {
+        self.buildings
+            .values()
+            .filter(|b| {
+                !b.lifted && b.position.to_tile_position().chebyshev_distance(base) <= BASE_RADIUS
+            })
+            .collect()
+    }
+
+    /// tiles neutral buildings, mineral walls and other obstacles sit on
+    pub fn blocked_tiles(&self) -> HashSet<TilePosition> {
+        self.neutrals.values().flat_map(|n| n.tiles()).collect()
+    }
+
+    /// mineral walls we could mine out, and where they are
+    pub fn blocking_minerals(&self) -> Vec<(UnitId, TilePosition)> {
+        self.neutrals
+            .iter()
+            .filter(|(_, n)| n.kind == NeutralKind::BlockingMinerals)
+            .map(|(id, n)| (*id, n.tile))
+            .collect()
+    }
+
+    /// nobody home
+    pub fn eliminate_start(&mut self, start: TilePosition) {
+        if self.starts.remove(&start).is_some() {
+            println!("enemy isn't at {:?}", start);
+        }
+    }
+
+    fn found_start(&mut self, start: TilePosition) {
+        if self.starts.len() > 1 && self.starts.contains_key(&start) {
+            println!("enemy main is at {:?}", start);
+            self.starts.retain(|s, _| *s == start);
+        }
+    }
+
+    /// a resource depot gives their main away, anything else makes it more
+    /// likely
+    fn building_near_start(&mut self, tile: TilePosition, unit_type: UnitType) {
+        let start = self
+            .starts
+            .keys()
+            .find(|s| s.chebyshev_distance(tile) <= BASE_RADIUS)
+            .copied();
+        match start {
+            Some(start) if unit_type.is_resource_depot() => self.found_start(start),
+            Some(start) => {
+                if let Some(weight) = self.starts.get_mut(&start) {
+                    *weight *= BUILDING_WEIGHT;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// early scouts came from their main, start locations behind them are
+    /// more likely than the ones they're heading to
+    pub fn enemy_scout(&mut self, position: Position, velocity: (f64, f64)) {
+        let (vx, vy) = velocity;
+        let speed = (vx * vx + vy * vy).sqrt();
+        if speed <= 0.0 {
+            return;
+        }
+        for (start, weight) in self.starts.iter_mut() {
+            let to = start.to_position() - position;
+            let length = ((to.x * to.x + to.y * to.y) as f64).sqrt();
+            if length <= 0.0 {
+                continue;
+            }
+            let behind = -(to.x as f64 * vx + to.y as f64 * vy) / (length * speed);
+            *weight *= (1.0 + behind as f32).max(AWAY_WEIGHT);
+        }
+    }
+
+    /// how likely the enemy main is at this start location
+    pub fn start_probability(&self, start: TilePosition) -> f32 {
+        let total: f32 = self.starts.values().sum();
+        match self.starts.get(&start) {
+            Some(weight) if total > 0.0 => weight / total,
+            _ => 0.0,
+        }
+    }
+
+    /// start locations they could still be at
+    pub fn possible_starts(&self) -> Vec<TilePosition> {
+        self.starts.keys().copied().collect()
+    }
+
+    /// our best guess at their main
+    pub fn likely_enemy_main(&self) -> Option<TilePosition> {
+        self.starts
+            .iter()
+            .max_by(|(a, wa), (b, wb)| wa.total_cmp(wb).then((b.x, b.y).cmp(&(a.x, a.y))))
+            .map(|(s, _)| *s)
+    }
+
+    /// their main once there's only one place it can be
+    pub fn enemy_main(&self) -> Option<TilePosition> {
+        match self.starts.len() {
+            1 => self.starts.keys().next().copied(),
+            _ => None,
+        }
+    }
+
+    pub fn record_scout_report(&mut self, report: ScoutReport) {
+        println!("frame {} :: scouted {:?}", report.frame, report);
+        self.scout_report = Some(report);
+    }
+
+    pub fn scout_report(&self) -> Option<&ScoutReport> {
+        self.scout_report.as_ref()
+    }
+
     pub fn get_gas_locs(&self) -> Vec<&TilePosition> {
         self.gas_locs.iter().collect()
     }
@@ -98,8 +412,31 @@ impl HaveSeen {
         self.minerals.iter().collect()
     }
 
-    pub fn get_enemy_building(&self) -> Option<&SeenUnit> {
-        self.buildings.values().next()
+    /// One tile per base, the middle of its resources. Geysers and mineral
+    /// fields close together are the same base, whether it has gas or not
+    pub fn base_locations(&self) -> Vec<TilePosition> {
+        let mut resources: Vec<TilePosition> = self
+            .gas_locs
+            .iter()
+            .chain(self.minerals.iter())
+            .copied()
+            .collect();
+        resources.sort_by_key(|t| (t.x, t.y));
+        let mut bases: Vec<Vec<TilePosition>> = vec![];
+        for tile in resources {
+            let base = bases
+                .iter_mut()
+                .find(|b| b.iter().any(|r| r.chebyshev_distance(tile) <= BASE_RADIUS));
+            match base {
+                Some(base) => base.push(tile),
+                None => bases.push(vec![tile]),
+            }
+        }
+        bases.iter().filter_map(cartesian_center).collect()
+    }
+
+    pub fn get_enemy_buildings(&self) -> Vec<&SeenUnit> {
+        self.buildings.values().collect()
     }
 
     pub fn get_enemy_army(&self) -> Vec<&SeenUnit> {
@@ -108,11 +445,50 @@ impl HaveSeen {
             .filter(|su| !su.unit_type.is_worker() && su.unit_type != UnitType::Zerg_Overlord)
             .collect()
     }
+
+    /// the enemy army we've seen in the last `frames`, older sightings have
+    /// probably moved on
+    pub fn get_recent_enemy_army(&self, frames: i32) -> Vec<&SeenUnit> {
+        self.get_enemy_army()
+            .into_iter()
+            .filter(|su| self.age(su) <= frames)
+            .collect()
+    }
+
+    /// how many of each unit type the enemy could have by now, from what
+    /// we've seen and what their buildings could have made
+    pub fn estimate_army(&self) -> Vec<ArmyEstimate> {
+        let mut army = HashMap::new();
+        for su in self.get_enemy_army() {
+            *army.entry(su.unit_type).or_insert(0) += 1;
+        }
+        let buildings: Vec<(UnitType, i32)> = self
+            .buildings
+            .iter()
+            .map(|(id, b)| {
+                let started = self
+                    .first_seen
+                    .get(id)
+                    .map_or(b.started(), |(_, frame)| *frame);
+                (b.unit_type, started + b.unit_type.build_time())
+            })
+            .collect();
+        estimate_army(self.frame, &army, &buildings, &self.building_timeline())
+    }
+
+    /// the enemy army seen in the last `frames` within `radius` of `position`
+    pub fn enemy_army_near(&self, position: Position, radius: u32, frames: i32) -> Vec<&SeenUnit> {
+        self.get_recent_enemy_army(frames)
+            .into_iter()
+            .filter(|su| su.position.chebyshev_distance(position) <= radius)
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{HaveSeen, UnitId};
+    use super::{HaveSeen, Position, SeenUnit, UnitId, UnitType};
+    use crate::test_util::tp;
 
     #[test]
     fn test_destroy_missing_id() {
@@ -120,4 +496,223 @@ mod test {
         let id: UnitId = 3023;
         seen.on_unit_destroy(id);
     }
+
+    fn sighting(unit_type: UnitType, frame: i32, x: i32) -> SeenUnit {
+        let mut unit = SeenUnit::fake(unit_type, frame);
+        unit.position = Position { x, y: 0 };
+        unit
+    }
+
+    #[test]
+    fn test_sightings() {
+        let mut seen = HaveSeen::new();
+        seen.update(0, vec![(1, sighting(UnitType::Protoss_Zealot, 0, 100))]);
+        seen.update(
+            10,
+            vec![
+                (1, sighting(UnitType::Protoss_Zealot, 10, 200)),
+                (2, sighting(UnitType::Protoss_Dragoon, 10, 1_000)),
+            ],
+        );
+        let zealot = &seen.units[&1];
+        assert_eq!(zealot.position.x, 200, "it moved");
+        assert_eq!(seen.age(zealot), 0);
+
+        // the zealot walks out of sight
+        seen.update(
+            100,
+            vec![(2, sighting(UnitType::Protoss_Dragoon, 100, 1_100))],
+        );
+        let zealot = &seen.units[&1];
+        assert_eq!(zealot.position.x, 200, "where we last saw it");
+        assert_eq!(seen.age(zealot), 90);
+        assert_eq!(seen.get_enemy_army().len(), 2, "still remembered");
+        assert_eq!(seen.get_recent_enemy_army(50).len(), 1);
+
+        let near = seen.enemy_army_near(Position { x: 0, y: 0 }, 300, 100);
+        assert_eq!(near.len(), 1);
+        assert_eq!(near[0].unit_type, UnitType::Protoss_Zealot);
+        assert_eq!(
+            seen.enemy_army_near(Position { x: 0, y: 0 }, 300, 50),
+            Vec::<&SeenUnit>::new(),
+            "too long ago"
+        );
+    }
+
+    #[test]
+    fn test_base_locations() {
+        let mut seen = HaveSeen::new();
+        // two geysers and a mineral line
+        seen.gas_locs.extend([tp(0, 0), tp(0, 8)]);
+        seen.minerals.extend((0..8).map(|y| tp(6, y)));
+        // minerals only
+        seen.minerals.extend((0..4).map(|y| tp(50, y)));
+        assert_eq!(seen.base_locations(), vec![tp(4, 3), tp(50, 1)]);
+    }
+
+    #[test]
+    fn test_morph() {
+        let mut seen = HaveSeen::new();
+        seen.update(0, vec![(1, sighting(UnitType::Zerg_Drone, 0, 0))]);
+        seen.update(5, vec![(1, sighting(UnitType::Zerg_Spawning_Pool, 5, 0))]);
+        assert!(seen.units.is_empty());
+        assert_eq!(seen.get_enemy_buildings().len(), 1, "it's a building now");
+
+        seen.on_unit_destroy(1);
+        let mut hatch = sighting(UnitType::Zerg_Hatchery, 2000, 0);
+        hatch.completed = false;
+        // 60% of the way there
+        hatch.hp = UnitType::Zerg_Hatchery.max_hit_points() * 64 / 100;
+        seen.update(2000, vec![(2, hatch)]);
+        let hatch_time = UnitType::Zerg_Hatchery.build_time();
+        assert_eq!(
+            seen.building_timeline(),
+            vec![
+                (
+                    UnitType::Zerg_Spawning_Pool,
+                    5 - UnitType::Zerg_Spawning_Pool.build_time()
+                ),
+                (UnitType::Zerg_Hatchery, 2000 - hatch_time * 6 / 10)
+            ],
+            "remembered after it's gone, from when they started"
+        );
+    }
+
+    #[test]
+    fn test_inventory() {
+        let mut seen = HaveSeen::new();
+        let mut pool = sighting(UnitType::Zerg_Spawning_Pool, 0, 0);
+        pool.completed = false;
+        seen.update(
+            0,
+            vec![
+                (1, sighting(UnitType::Zerg_Hatchery, 0, 0)),
+                (2, sighting(UnitType::Zerg_Creep_Colony, 0, 100)),
+                (3, pool),
+            ],
+        );
+        assert_eq!(seen.building_count(UnitType::Zerg_Spawning_Pool), 1);
+        assert_eq!(
+            seen.completed_count(UnitType::Zerg_Spawning_Pool),
+            0,
+            "still building"
+        );
+
+        let mut lair = sighting(UnitType::Zerg_Lair, 100, 0);
+        lair.completed = false;
+        seen.update(
+            100,
+            vec![
+                (1, lair),
+                (2, sighting(UnitType::Zerg_Sunken_Colony, 100, 100)),
+                (3, sighting(UnitType::Zerg_Spawning_Pool, 100, 0)),
+            ],
+        );
+        assert_eq!(
+            seen.building_count(UnitType::Zerg_Hatchery),
+            0,
+            "it's a lair now"
+        );
+        assert_eq!(seen.building_count(UnitType::Zerg_Lair), 1);
+        assert_eq!(seen.completed_count(UnitType::Zerg_Lair), 0);
+        assert_eq!(seen.building_count(UnitType::Zerg_Creep_Colony), 0);
+        assert_eq!(seen.completed_count(UnitType::Zerg_Sunken_Colony), 1);
+        assert_eq!(seen.completed_count(UnitType::Zerg_Spawning_Pool), 1);
+        assert_eq!(seen.buildings_at(tp(0, 0)).len(), 3);
+
+        seen.on_unit_destroy(2);
+        seen.on_unit_destroy(2);
+        assert_eq!(seen.building_count(UnitType::Zerg_Sunken_Colony), 0);
+        assert_eq!(seen.buildings_at(tp(0, 0)).len(), 2, "destroyed once");
+    }
+
+    #[test]
+    fn test_liftoff() {
+        let mut seen = HaveSeen::new();
+        seen.update(0, vec![(1, sighting(UnitType::Terran_Barracks, 0, 0))]);
+        let mut lifted = sighting(UnitType::Terran_Barracks, 10, 32 * 20);
+        lifted.lifted = true;
+        seen.update(10, vec![(1, lifted)]);
+        assert_eq!(seen.building_count(UnitType::Terran_Barracks), 1);
+        assert!(seen.buildings_at(tp(0, 0)).is_empty(), "flew away");
+        assert!(seen.buildings_at(tp(20, 0)).is_empty(), "not landed yet");
+
+        seen.update(
+            20,
+            vec![(1, sighting(UnitType::Terran_Barracks, 20, 32 * 20))],
+        );
+        assert_eq!(
+            seen.buildings_at(tp(20, 0)).len(),
+            1,
+            "landed somewhere else"
+        );
+    }
+
+    #[test]
+    fn test_two_player() {
+        let mut seen = HaveSeen::new();
+        seen.set_start_locations(tp(8, 8), vec![tp(8, 8), tp(120, 120)]);
+        assert_eq!(seen.enemy_main(), Some(tp(120, 120)), "only one place");
+        assert_eq!(seen.likely_enemy_main(), Some(tp(120, 120)));
+    }
+
+    #[test]
+    fn test_three_player() {
+        let mut seen = HaveSeen::new();
+        let starts = vec![tp(8, 8), tp(120, 8), tp(64, 120)];
+        seen.set_start_locations(tp(8, 8), starts);
+        assert_eq!(seen.enemy_main(), None);
+        assert_eq!(seen.start_probability(tp(120, 8)), 0.5);
+
+        seen.eliminate_start(tp(120, 8));
+        assert_eq!(
+            seen.enemy_main(),
+            Some(tp(64, 120)),
+            "the other one's empty"
+        );
+
+        let mut seen = HaveSeen::new();
+        seen.set_start_locations(tp(8, 8), vec![tp(8, 8), tp(120, 8), tp(64, 120)]);
+        let mut pylon = SeenUnit::fake(UnitType::Protoss_Pylon, 0);
+        pylon.position = tp(66, 116).to_position();
+        seen.building_near_start(pylon.position.to_tile_position(), pylon.unit_type);
+        seen.add_fake(1, pylon);
+        assert_eq!(seen.enemy_main(), None, "could be a proxy");
+        assert_eq!(
+            seen.likely_enemy_main(),
+            Some(tp(64, 120)),
+            "saw a building there"
+        );
+        assert_eq!(seen.buildings_at(tp(64, 120)).len(), 1);
+
+        seen.building_near_start(tp(64, 120), UnitType::Protoss_Nexus);
+        assert_eq!(seen.enemy_main(), Some(tp(64, 120)), "that's their main");
+    }
+
+    #[test]
+    fn test_four_player() {
+        let mut seen = HaveSeen::new();
+        let starts = vec![tp(8, 8), tp(120, 8), tp(8, 120), tp(120, 120)];
+        seen.set_start_locations(tp(8, 8), starts);
+        assert_eq!(seen.possible_starts().len(), 3);
+
+        // a probe walking in from the right
+        let scout = tp(30, 10).to_position();
+        seen.enemy_scout(scout, (-4.0, 0.0));
+        assert_eq!(seen.likely_enemy_main(), Some(tp(120, 8)));
+        assert!(seen.start_probability(tp(120, 8)) > seen.start_probability(tp(120, 120)));
+        assert!(seen.start_probability(tp(120, 120)) > seen.start_probability(tp(8, 120)));
+        assert!(seen.start_probability(tp(8, 120)) > 0.0, "could be wrong");
+        assert_eq!(seen.enemy_main(), None, "not sure yet");
+
+        seen.enemy_scout(scout, (0.0, 0.0));
+        assert_eq!(seen.likely_enemy_main(), Some(tp(120, 8)), "standing still");
+
+        seen.eliminate_start(tp(120, 8));
+        assert_eq!(seen.likely_enemy_main(), Some(tp(120, 120)));
+        seen.eliminate_start(tp(120, 120));
+        assert_eq!(seen.enemy_main(), Some(tp(8, 120)));
+        assert_eq!(seen.start_probability(tp(8, 120)), 1.0);
+        assert_eq!(seen.start_probability(tp(120, 8)), 0.0);
+    }
 }
@@ -0,0 +1,222 @@
+// quick army strength estimates, a cheap alternative to running an Engagement
+// assumes every unit can reach everything it is able to target, so it ignores:
+// positioning, ranges and surface area
+// splash damage
+// shield/hp regen and attrition during the fight
+
+use super::{volleys::volleys_to_kill, SimUnit, SimWeapon};
+
+/// Damage per frame a weapon deals to a single target. Shields, armor and
+/// size ratios are accounted for by spreading the target's total durability
+/// over the number of volleys it takes to kill it
+fn weapon_dpf(weapon: &SimWeapon, target: &SimUnit) -> f32 {
+    // spell-like weapons never finish off anything in volleys_to_kill
+    if !weapon.can_target(target) || weapon.type_.damage_amount() <= 0 {
+        return 0.0;
+    }
+    let volleys = volleys_to_kill(target, weapon);
+    if volleys == 0 {
+        return 0.0;
+    }
+    let per_volley = durability(target) / volleys as f32;
+    per_volley / weapon.cooldown.max(1) as f32
+}
+
+fn durability(unit: &SimUnit) -> f32 {
+    unit.hp + unit.shields
+}
+
+/// Best damage per frame an attacker can deal to a target with any of its weapons
+pub(crate) fn unit_dpf(attacker: &SimUnit, target: &SimUnit) -> f32 {
+    attacker
+        .weapons
+        .iter()
+        .map(|w| weapon_dpf(w, target))
+        .fold(0.0, f32::max)
+}
+
+/// Damage per frame an army deals to another army's composition. Each attacker
+/// is assumed to spread its fire evenly over the targets it's able to hit
+pub(crate) fn army_dpf(attackers: &[SimUnit], targets: &[SimUnit]) -> f32 {
+    attackers
+        .iter()
+        .map(|a| {
+            let dpfs: Vec<f32> = targets
+                .iter()
+                .map(|t| unit_dpf(a, t))
+                .filter(|dpf| *dpf > 0.0)
+                .collect();
+            if dpfs.is_empty() {
+                0.0
+            } else {
+                dpfs.iter().sum::<f32>() / dpfs.len() as f32
+            }
+        })
+        .sum()
+}
+
+/// Estimated frames for the attackers to kill every target that matters, None
+/// if some of the targets can't be hit by any of the attackers but can hurt them.
+/// Shuttles, observers and the like can't win the fight so they're left out
+pub(crate) fn frames_to_kill(attackers: &[SimUnit], targets: &[SimUnit]) -> Option<f32> {
+    let mut total = 0.0;
+    for target in targets.iter().filter(|t| durability(t) > 0.0) {
+        if attackers.iter().any(|a| unit_dpf(a, target) > 0.0) {
+            total += durability(target);
+        } else if attackers.iter().any(|a| unit_dpf(target, a) > 0.0) {
+            return None;
+        }
+    }
+    if total == 0.0 {
+        return Some(0.0);
+    }
+    Some(total / army_dpf(attackers, targets))
+}
+
+/// Mineral + gas cost of an army
+pub(crate) fn army_value(units: &[SimUnit]) -> i32 {
+    units
+        .iter()
+        .map(|u| {
+            let price = u.type_.mineral_price() + u.type_.gas_price();
+            // zerglings & scourge are priced per egg
+            if u.type_.is_two_units_in_one_egg() {
+                price / 2
+            } else {
+                price
+            }
+        })
+        .sum()
+}
+
+/// Summary of how two armies would fare against each other
+#[derive(Debug)]
+pub(crate) struct Matchup {
+    pub ours_dpf: f32,
+    pub theirs_dpf: f32,
+    pub frames_to_win: Option<f32>,
+    pub frames_to_lose: Option<f32>,
+}
+
+impl Matchup {
+    pub fn evaluate(ours: &[SimUnit], theirs: &[SimUnit]) -> Self {
+        Matchup {
+            ours_dpf: army_dpf(ours, theirs),
+            theirs_dpf: army_dpf(theirs, ours),
+            frames_to_win: frames_to_kill(ours, theirs),
+            frames_to_lose: frames_to_kill(theirs, ours),
+        }
+    }
+
+    /// true if we'd kill their army before they kill ours
+    pub fn we_win(&self) -> bool {
+        match (self.frames_to_win, self.frames_to_lose) {
+            (Some(win), Some(lose)) => win < lose,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rsbwapi::UnitType;
+
+    fn army(type_: UnitType, count: usize) -> Vec<SimUnit> {
+        (0..count)
+            .map(|_| SimUnit::from_type(type_, 0, 0))
+            .collect()
+    }
+
+    #[test]
+    fn test_unit_dpf() {
+        let ling = SimUnit::from_type(UnitType::Zerg_Zergling, 0, 0);
+        let marine = SimUnit::from_type(UnitType::Terran_Marine, 0, 0);
+        // claws hit for 5 every 8 frames
+        assert_eq!(unit_dpf(&ling, &marine), 5.0 / 8.0, "ling v marine");
+
+        let upgraded_ling = SimUnit::from_type(UnitType::Zerg_Zergling, 1, 0);
+        assert!(
+            unit_dpf(&upgraded_ling, &marine) > unit_dpf(&ling, &marine),
+            "+1 melee hits harder"
+        );
+        let armored_marine = SimUnit::from_type(UnitType::Terran_Marine, 0, 3);
+        assert!(
+            unit_dpf(&ling, &armored_marine) < unit_dpf(&ling, &marine),
+            "armor soaks damage"
+        );
+    }
+
+    #[test]
+    fn test_air_ground() {
+        let lings = army(UnitType::Zerg_Zergling, 12);
+        let mutas = army(UnitType::Zerg_Mutalisk, 3);
+        assert_eq!(army_dpf(&lings, &mutas), 0.0, "lings can't hit air");
+        assert_eq!(
+            frames_to_kill(&lings, &mutas),
+            None,
+            "lings never kill mutas"
+        );
+
+        let m = Matchup::evaluate(&lings, &mutas);
+        assert!(!m.we_win(), "lings lose to mutas");
+
+        let hydras = army(UnitType::Zerg_Hydralisk, 6);
+        let m = Matchup::evaluate(&hydras, &mutas);
+        assert!(m.ours_dpf > 0.0, "hydras shoot up");
+        assert!(m.we_win(), "6 hydras beat 3 mutas");
+    }
+
+    #[test]
+    fn test_harmless_targets() {
+        let lings = army(UnitType::Zerg_Zergling, 24);
+        let mut theirs = army(UnitType::Protoss_Zealot, 2);
+        theirs.push(SimUnit::from_type(UnitType::Protoss_Shuttle, 0, 0));
+        let m = Matchup::evaluate(&lings, &theirs);
+        assert!(m.frames_to_win.is_some(), "the shuttle can't fight back");
+        assert!(m.we_win(), "lings still beat the zealots");
+
+        let mut dead = SimUnit::from_type(UnitType::Protoss_Zealot, 0, 0);
+        dead.hp = 0.0;
+        dead.shields = 0.0;
+        let m = Matchup::evaluate(&lings, &[dead]);
+        assert_eq!(m.frames_to_win, Some(0.0), "nothing left to kill");
+    }
+
+    #[test]
+    fn test_size_ratios() {
+        let hydra = SimUnit::from_type(UnitType::Zerg_Hydralisk, 0, 0);
+        let dragoon = SimUnit::from_type(UnitType::Protoss_Dragoon, 0, 0);
+        let ling = SimUnit::from_type(UnitType::Zerg_Zergling, 0, 0);
+        assert!(
+            unit_dpf(&dragoon, &hydra) > unit_dpf(&dragoon, &ling),
+            "explosive damage is worse against small units"
+        );
+    }
+
+    #[test]
+    fn test_known_matchups() {
+        let m = Matchup::evaluate(
+            &army(UnitType::Zerg_Hydralisk, 12),
+            &army(UnitType::Terran_Marine, 6),
+        );
+        assert!(m.we_win(), "double the hydras beat marines");
+
+        let m = Matchup::evaluate(
+            &army(UnitType::Zerg_Zergling, 4),
+            &army(UnitType::Protoss_Zealot, 4),
+        );
+        assert!(!m.we_win(), "zealots beat an equal number of lings");
+
+        let empty = Matchup::evaluate(&army(UnitType::Zerg_Zergling, 4), &vec![]);
+        assert_eq!(empty.frames_to_win, Some(0.0), "nothing to kill");
+        assert!(empty.we_win(), "uncontested");
+    }
+
+    #[test]
+    fn test_army_value() {
+        assert_eq!(army_value(&army(UnitType::Zerg_Zergling, 4)), 100);
+        assert_eq!(army_value(&army(UnitType::Zerg_Mutalisk, 2)), 400);
+    }
+}
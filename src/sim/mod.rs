@@ -1,13 +1,17 @@
 #![allow(unused)]
+use crate::seen::SeenUnit;
 use rsbwapi::{
     unit::UnitOrPosition, Player, PlayerId, ScaledPosition, TechType, Unit, UnitId, UnitType,
     Vector2D, WeaponType,
 };
 
 mod engine;
+mod evaluator;
 mod orders;
 mod volleys;
 
+pub(crate) use evaluator::{army_value, Matchup};
+
 #[derive(Debug, Clone)]
 pub(crate) enum TargetType {
     Ground,
@@ -27,16 +31,29 @@ pub(crate) struct SimWeapon {
 
 impl SimWeapon {
     pub(crate) fn from_weapon(player: &Player, wep: &WeaponType) -> Self {
+        SimWeapon::upgraded(wep, player.get_upgrade_level(wep.upgrade_type()))
+    }
+
+    pub(crate) fn upgraded(wep: &WeaponType, upgrade_level: i32) -> Self {
         SimWeapon {
             type_: wep.clone(),
             targets: SimWeapon::get_target_type(wep),
-            upgrade_damage: wep.damage_bonus() * player.get_upgrade_level(wep.upgrade_type()),
+            upgrade_damage: wep.damage_bonus() * upgrade_level,
             range_min: wep.min_range() as f64,
             range_max: wep.max_range() as f64, // TODO range upgrades
             cooldown: wep.damage_cooldown(),
         }
     }
 
+    /// whether this weapon is able to hit the target at all (air vs ground)
+    pub(crate) fn can_target(&self, target: &SimUnit) -> bool {
+        if target.type_.is_flyer() {
+            self.type_.targets_air()
+        } else {
+            self.type_.targets_ground()
+        }
+    }
+
     fn get_target_type(wep: &WeaponType) -> TargetType {
         if wep.targets_air() {
             TargetType::Air
@@ -94,14 +111,44 @@ impl SimUnit {
         }
     }
 
+    /// a fresh, full health unit of a type with the given upgrade levels
+    pub(crate) fn from_type(type_: UnitType, weapon_upgrades: i32, armor_upgrades: i32) -> Self {
+        SimUnit {
+            type_,
+            size: (type_.width(), type_.height()).into(),
+            weapons: SimUnit::get_weapons_upgraded(type_, weapon_upgrades),
+            hp: type_.max_hit_points() as f32,
+            shields: type_.max_shields() as f32,
+            armor: type_.armor() + armor_upgrades,
+            ..Default::default()
+        }
+    }
+
+    /// an enemy unit as we last saw it, we don't know its upgrades
+    pub(crate) fn from_seen(seen: &SeenUnit) -> Self {
+        let mut unit = SimUnit::from_type(seen.unit_type, 0, 0);
+        unit.position = seen.position;
+        unit.hp = seen.hp as f32;
+        unit.shields = seen.shields as f32;
+        unit
+    }
+
     fn get_weapons(player: &Player, type_: UnitType) -> Vec<SimWeapon> {
+        SimUnit::weapon_types(type_)
+            .map(|wt| SimWeapon::from_weapon(player, &wt))
+            .collect()
+    }
+
+    fn get_weapons_upgraded(type_: UnitType, upgrade_level: i32) -> Vec<SimWeapon> {
+        SimUnit::weapon_types(type_)
+            .map(|wt| SimWeapon::upgraded(&wt, upgrade_level))
+            .collect()
+    }
+
+    fn weapon_types(type_: UnitType) -> impl Iterator<Item = WeaponType> {
         [type_.air_weapon(), type_.ground_weapon()]
             .into_iter()
-            .filter_map(|wt| match wt {
-                WeaponType::None | WeaponType::Unknown => None,
-                _ => Some(SimWeapon::from_weapon(player, &wt)),
-            })
-            .collect()
+            .filter(|wt| !matches!(wt, WeaponType::None | WeaponType::Unknown))
     }
 
     #[cfg(test)]
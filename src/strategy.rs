@@ -0,0 +1,277 @@
+use rsbwapi::UnitType;
+
+const MINUTE: i32 = 24 * 60;
+// how sure we need to be before changing our plans for a rush
+const RUSH_CONFIDENCE: f32 = 0.75;
+// not seeing something is weaker evidence than seeing it, we might not have
+// looked in the right place
+const UNSEEN_SCORE: f32 = 0.75;
+
+/// Openings we can recognise from their buildings
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Opening {
+    Unknown,
+    TwoGate,
+    ForgeExpand,
+    Bbs,
+    OneRaxExpand,
+    NinePool,
+    TwelveHatch,
+}
+
+/// Something we expect to have seen by a frame if they're doing an opening
+enum Clue {
+    /// at least this many of a building
+    Built(UnitType, usize, i32),
+    /// rules the opening out if we've seen this many
+    NotBuilt(UnitType, usize, i32),
+}
+
+struct Rule {
+    opening: Opening,
+    clues: &'static [Clue],
+}
+
+use Clue::*;
+
+// ties go to the first rule, so riskier openings come first
+const RULES: &[Rule] = &[
+    Rule {
+        opening: Opening::Bbs,
+        clues: &[
+            Built(UnitType::Terran_Barracks, 2, MINUTE * 5 / 2),
+            NotBuilt(UnitType::Terran_Refinery, 1, MINUTE * 3),
+        ],
+    },
+    Rule {
+        opening: Opening::NinePool,
+        clues: &[
+            // an overpool or 12 pool goes down later
+            Built(UnitType::Zerg_Spawning_Pool, 1, MINUTE * 11 / 10),
+            NotBuilt(UnitType::Zerg_Hatchery, 2, MINUTE * 2),
+        ],
+    },
+    Rule {
+        opening: Opening::TwoGate,
+        clues: &[
+            Built(UnitType::Protoss_Gateway, 2, MINUTE * 3),
+            NotBuilt(UnitType::Protoss_Assimilator, 1, MINUTE * 5 / 2),
+        ],
+    },
+    Rule {
+        opening: Opening::ForgeExpand,
+        clues: &[
+            Built(UnitType::Protoss_Forge, 1, MINUTE * 3),
+            Built(UnitType::Protoss_Nexus, 2, MINUTE * 4),
+        ],
+    },
+    Rule {
+        opening: Opening::OneRaxExpand,
+        clues: &[
+            Built(UnitType::Terran_Barracks, 1, MINUTE * 3),
+            Built(UnitType::Terran_Command_Center, 2, MINUTE * 4),
+            NotBuilt(UnitType::Terran_Barracks, 2, MINUTE * 3),
+        ],
+    },
+    Rule {
+        opening: Opening::TwelveHatch,
+        clues: &[
+            Built(UnitType::Zerg_Hatchery, 2, MINUTE * 2),
+            NotBuilt(UnitType::Zerg_Spawning_Pool, 1, MINUTE * 5 / 4),
+        ],
+    },
+];
+
+/// What we think they're doing and how sure we are of it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct Strategy {
+    pub opening: Opening,
+    pub confidence: f32,
+}
+
+impl Strategy {
+    pub fn unknown() -> Self {
+        Strategy {
+            opening: Opening::Unknown,
+            confidence: 0.0,
+        }
+    }
+
+    /// an early attack we need to get ready for
+    pub fn is_rush(&self) -> bool {
+        matches!(
+            self.opening,
+            Opening::Bbs | Opening::NinePool | Opening::TwoGate
+        ) && self.confidence >= RUSH_CONFIDENCE
+    }
+
+    /// zerglings to have before anything else
+    pub fn zerglings_needed(&self) -> i32 {
+        match self.opening {
+            _ if !self.is_rush() => 0,
+            Opening::NinePool => 6,
+            _ => 8,
+        }
+    }
+}
+
+/// how well `timeline` fits a rule, 0 if it's ruled out
+fn confidence(rule: &Rule, timeline: &[(UnitType, i32)]) -> f32 {
+    let seen = |unit_type: UnitType, by: i32| {
+        timeline
+            .iter()
+            .filter(|(t, frame)| *t == unit_type && *frame <= by)
+            .count()
+    };
+    let mut scores = vec![];
+    let mut evidence = false;
+    for clue in rule.clues {
+        match *clue {
+            Built(unit_type, count, by) => {
+                // everyone has a main, it says nothing about their opening
+                let main = if unit_type.is_resource_depot() { 1 } else { 0 };
+                let wanted = (count - main).max(1);
+                let have = seen(unit_type, by).saturating_sub(main).min(wanted);
+                evidence |= have > 0;
+                scores.push(have as f32 / wanted as f32)
+            }
+            NotBuilt(unit_type, count, by) if seen(unit_type, by) >= count => return 0.0,
+            NotBuilt(..) => scores.push(UNSEEN_SCORE),
+        }
+    }
+    // nothing we haven't seen makes an opening on its own
+    if !evidence {
+        return 0.0;
+    }
+    scores.iter().sum::<f32>() / scores.len().max(1) as f32
+}
+
+/// The opening that best fits the enemy buildings we've seen, given as
+/// their type and about when they were started
+pub(crate) fn recognize(timeline: &[(UnitType, i32)]) -> Strategy {
+    let mut best = Strategy::unknown();
+    for rule in RULES {
+        let confidence = confidence(rule, timeline);
+        if confidence > best.confidence {
+            best = Strategy {
+                opening: rule.opening,
+                confidence,
+            };
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECOND: i32 = 24;
+
+    #[test]
+    fn test_nothing_seen() {
+        assert_eq!(recognize(&[]), Strategy::unknown());
+        let nexus = [(UnitType::Protoss_Nexus, 0)];
+        assert_eq!(recognize(&nexus).opening, Opening::Unknown);
+    }
+
+    #[test]
+    fn test_two_gate() {
+        let mut timeline = vec![
+            (UnitType::Protoss_Nexus, 90 * SECOND),
+            (UnitType::Protoss_Pylon, 90 * SECOND),
+            (UnitType::Protoss_Gateway, 100 * SECOND),
+        ];
+        let one_gate = recognize(&timeline);
+        assert_eq!(one_gate.opening, Opening::TwoGate);
+        assert_eq!(one_gate.confidence, (0.5 + UNSEEN_SCORE) / 2.0);
+        assert!(!one_gate.is_rush(), "could be one gate core");
+
+        timeline.push((UnitType::Protoss_Gateway, 110 * SECOND));
+        let two_gate = recognize(&timeline);
+        assert!(two_gate.confidence < 1.0, "haven't seen they've no gas");
+        assert!(two_gate.is_rush());
+        assert_eq!(two_gate.zerglings_needed(), 8);
+
+        timeline.push((UnitType::Protoss_Assimilator, 120 * SECOND));
+        assert_eq!(
+            recognize(&timeline).opening,
+            Opening::Unknown,
+            "they took gas, it's not a zealot rush"
+        );
+    }
+
+    #[test]
+    fn test_forge_expand() {
+        let timeline = [
+            (UnitType::Protoss_Nexus, 120 * SECOND),
+            (UnitType::Protoss_Forge, 125 * SECOND),
+            (UnitType::Protoss_Nexus, 130 * SECOND),
+            (UnitType::Protoss_Photon_Cannon, 150 * SECOND),
+        ];
+        let ffe = recognize(&timeline);
+        assert_eq!(ffe.opening, Opening::ForgeExpand);
+        assert_eq!(ffe.confidence, 1.0);
+        assert!(!ffe.is_rush());
+    }
+
+    #[test]
+    fn test_terran() {
+        let bbs = [
+            (UnitType::Terran_Command_Center, 80 * SECOND),
+            (UnitType::Terran_Barracks, 80 * SECOND),
+            (UnitType::Terran_Barracks, 85 * SECOND),
+            (UnitType::Terran_Supply_Depot, 90 * SECOND),
+        ];
+        assert_eq!(recognize(&bbs).opening, Opening::Bbs);
+        assert!(recognize(&bbs).is_rush());
+
+        let one_rax = [
+            (UnitType::Terran_Command_Center, 80 * SECOND),
+            (UnitType::Terran_Barracks, 100 * SECOND),
+            (UnitType::Terran_Command_Center, 200 * SECOND),
+        ];
+        assert_eq!(recognize(&one_rax).opening, Opening::OneRaxExpand);
+
+        let late = [
+            (UnitType::Terran_Barracks, 200 * SECOND),
+            (UnitType::Terran_Barracks, 210 * SECOND),
+        ];
+        assert_eq!(
+            recognize(&late).opening,
+            Opening::Unknown,
+            "too late for bbs"
+        );
+    }
+
+    #[test]
+    fn test_zerg() {
+        let nine_pool = [
+            (UnitType::Zerg_Hatchery, 0),
+            (UnitType::Zerg_Spawning_Pool, 58 * SECOND),
+        ];
+        let strategy = recognize(&nine_pool);
+        assert_eq!(strategy.opening, Opening::NinePool);
+        assert!(
+            strategy.confidence < 1.0,
+            "their natural could be out of sight"
+        );
+        assert_eq!(strategy.zerglings_needed(), 6);
+
+        let overpool = [
+            (UnitType::Zerg_Hatchery, 0),
+            (UnitType::Zerg_Spawning_Pool, 75 * SECOND),
+        ];
+        assert!(
+            !recognize(&overpool).is_rush(),
+            "pool's too late for 9 pool"
+        );
+
+        let twelve_hatch = [
+            (UnitType::Zerg_Hatchery, 70 * SECOND),
+            (UnitType::Zerg_Hatchery, 80 * SECOND),
+            (UnitType::Zerg_Spawning_Pool, 100 * SECOND),
+        ];
+        assert_eq!(recognize(&twelve_hatch).opening, Opening::TwelveHatch);
+    }
+}
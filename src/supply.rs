@@ -0,0 +1,142 @@
+use crate::counts::Counts;
+use rsbwapi::UnitType;
+
+// the most supply we can ever have, doubled like everything else
+const SUPPLY_CAP: i32 = 400;
+// the first overlord waits until we're blocked at 9 supply
+const OPENING_SUPPLY: i32 = 18;
+// most larva become a drone or a pair of zerglings
+const SUPPLY_PER_LARVA: i32 = 2;
+
+// and cost about this much
+const MINERALS_PER_LARVA: i32 = 50;
+
+/// Predicts when production will run out of supply so that overlords can be
+/// started early enough to finish before it does
+pub(crate) struct SupplyPlanner {
+    // supply we expect production to use each frame
+    rate: f32,
+}
+
+impl SupplyPlanner {
+    /// production is limited by whichever of larva and minerals runs out first
+    pub fn new(counts: &Counts, larva_per_frame: f32) -> Self {
+        let (mineral_rate, _gas_rate) = counts.income();
+        let larva_limited = larva_per_frame * SUPPLY_PER_LARVA as f32;
+        let money_limited = mineral_rate / MINERALS_PER_LARVA as f32 * SUPPLY_PER_LARVA as f32;
+        SupplyPlanner {
+            rate: larva_limited.min(money_limited),
+        }
+    }
+
+    fn capacity(counts: &Counts) -> i32 {
+        (counts.supply_max() + counts.supply_pending()).min(SUPPLY_CAP)
+    }
+
+    /// larva we have now and can pay for get used straight away
+    fn spendable_supply(counts: &Counts, larva: usize) -> i32 {
+        let affordable = (counts.minerals() / MINERALS_PER_LARVA).max(0);
+        (larva as i32).min(affordable) * SUPPLY_PER_LARVA
+    }
+
+    /// frames until production uses up the supply we have and have coming,
+    /// None if we aren't producing anything
+    pub fn frames_until_blocked(&self, counts: &Counts, larva: usize) -> Option<i32> {
+        let headroom =
+            Self::capacity(counts) - counts.supply_used() - Self::spendable_supply(counts, larva);
+        if headroom <= 0 {
+            Some(0)
+        } else if self.rate <= 0.0 {
+            None
+        } else {
+            Some((headroom as f32 / self.rate) as i32)
+        }
+    }
+
+    /// How many more overlords to start now so that they finish before
+    /// production runs out of supply
+    pub fn overlords_wanted(&self, counts: &Counts, larva: usize) -> i32 {
+        let capacity = Self::capacity(counts);
+        if capacity >= SUPPLY_CAP {
+            return 0;
+        }
+        if capacity <= OPENING_SUPPLY {
+            return if counts.supply_used() >= capacity {
+                1
+            } else {
+                0
+            };
+        }
+        let overlord = UnitType::Zerg_Overlord;
+        let while_morphing = (self.rate * overlord.build_time() as f32).ceil() as i32;
+        let needed = counts.supply_used() + Self::spendable_supply(counts, larva) + while_morphing;
+        let short = needed.min(SUPPLY_CAP) - capacity;
+        if short <= 0 {
+            0
+        } else {
+            let provided = overlord.supply_provided();
+            (short + provided - 1) / provided
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // larva from one hatchery
+    const ONE_HATCH: f32 = 1.0 / 342.0;
+
+    #[test]
+    fn test_nine_overlord() {
+        let eight = Counts::new_fake_bank(50, 0, 16, 18).with_income(0.36, 0.0);
+        let planner = SupplyPlanner::new(&eight, ONE_HATCH);
+        assert_eq!(planner.overlords_wanted(&eight, 3), 0, "9th drone first");
+
+        let nine = Counts::new_fake_bank(100, 0, 18, 18).with_income(0.4, 0.0);
+        let planner = SupplyPlanner::new(&nine, ONE_HATCH);
+        assert_eq!(planner.overlords_wanted(&nine, 2), 1, "overlord at 9");
+        assert_eq!(planner.frames_until_blocked(&nine, 2), Some(0));
+
+        let morphing = nine.with_pending(16);
+        assert_eq!(
+            planner.overlords_wanted(&morphing, 2),
+            0,
+            "one overlord is enough until the pool"
+        );
+    }
+
+    #[test]
+    fn test_mass_production() {
+        // five hatcheries, plenty of money, 9 larva waiting
+        let c = Counts::new_fake_bank(1_000, 0, 150, 150).with_income(2.0, 0.0);
+        let planner = SupplyPlanner::new(&c, 5.0 * ONE_HATCH);
+        assert_eq!(
+            planner.overlords_wanted(&c, 9),
+            3,
+            "cover the larva we have and what pops while the overlords morph"
+        );
+        let c = c.with_pending(48);
+        assert_eq!(planner.overlords_wanted(&c, 9), 0, "three already coming");
+
+        let broke = Counts::new_fake_bank(0, 0, 150, 150).with_income(0.2, 0.0);
+        let planner = SupplyPlanner::new(&broke, 5.0 * ONE_HATCH);
+        assert_eq!(
+            planner.overlords_wanted(&broke, 9),
+            1,
+            "production is limited by money"
+        );
+
+        let maxed = Counts::new_fake_bank(1_000, 0, 380, 400);
+        assert_eq!(planner.overlords_wanted(&maxed, 9), 0, "at the cap");
+    }
+
+    #[test]
+    fn test_frames_until_blocked() {
+        let c = Counts::new_fake_bank(0, 0, 100, 116).with_income(1.0, 0.0);
+        let planner = SupplyPlanner::new(&c, 1.0 / 100.0);
+        assert_eq!(planner.frames_until_blocked(&c, 0), Some(800));
+        let idle = SupplyPlanner::new(&c, 0.0);
+        assert_eq!(idle.frames_until_blocked(&c, 0), None, "no larva coming");
+    }
+}
@@ -0,0 +1,56 @@
+// fixtures shared by the tests of several modules
+
+use crate::{build_order::TechChecker, worker_defense::CombatUnit};
+use rsbwapi::{Position, TilePosition, UnitId, UnitType};
+
+pub(crate) fn tp(x: i32, y: i32) -> TilePosition {
+    TilePosition { x, y }
+}
+
+/// has the tech for anything
+pub(crate) struct AllTech;
+
+impl TechChecker for &AllTech {
+    fn has_prereqs(&self, _unit_type: &UnitType) -> bool {
+        true
+    }
+}
+
+pub(crate) struct FakeUnit {
+    pub id: UnitId,
+    pub unit_type: UnitType,
+    pub position: Position,
+    pub hit_points: i32,
+    pub completed: bool,
+}
+
+impl FakeUnit {
+    /// a finished unit at full health
+    pub fn new(id: UnitId, unit_type: UnitType, position: Position) -> Self {
+        FakeUnit {
+            id,
+            unit_type,
+            position,
+            hit_points: unit_type.max_hit_points() + unit_type.max_shields(),
+            completed: true,
+        }
+    }
+}
+
+impl CombatUnit for FakeUnit {
+    fn id(&self) -> UnitId {
+        self.id
+    }
+    fn unit_type(&self) -> UnitType {
+        self.unit_type
+    }
+    fn position(&self) -> Position {
+        self.position
+    }
+    fn hit_points(&self) -> i32 {
+        self.hit_points
+    }
+    fn is_completed(&self) -> bool {
+        self.completed
+    }
+}
@@ -0,0 +1,324 @@
+use crate::seen::HaveSeen;
+use rsbwapi::{Game, Position, TilePosition, UnitId, UnitType, WeaponType};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+// keep this far outside of weapon range
+const MARGIN: i32 = 32 * 2;
+// each point of dps on a tile costs as much as walking another tile
+const THREAT_COST: f32 = 10.0;
+// move this many tiles along a flee path at a time
+const FLEE_STEP: usize = 4;
+// units we haven't seen for this long have probably moved on
+const MEMORY_FRAMES: i32 = 24 * 10;
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// damage per second, ignoring upgrades and armor
+fn dps(weapon: WeaponType) -> f32 {
+    if matches!(weapon, WeaponType::None | WeaponType::Unknown) || weapon.damage_cooldown() <= 0 {
+        return 0.0;
+    }
+    (weapon.damage_amount() * weapon.damage_factor() * 24) as f32 / weapon.damage_cooldown() as f32
+}
+
+/// What fleeing drone should do next
+#[derive(Debug, PartialEq)]
+pub(crate) enum FleeOrder {
+    Move(TilePosition),
+    /// gather from a patch to slip through the units in the way
+    MineralWalk(UnitId),
+    /// nowhere safe to go
+    Stay,
+}
+
+/// The way to safety from every tile of the map
+pub(crate) struct FleePaths {
+    // the tile to step to next, usize::MAX once there or with no way out
+    next: Vec<usize>,
+    // the patch each tile is running to
+    patch: Vec<Option<UnitId>>,
+}
+
+/// How much enemy damage per second can reach each tile of the map, for
+/// ground and air units
+pub(crate) struct ThreatMap {
+    width: i32,
+    height: i32,
+    ground: Vec<f32>,
+    air: Vec<f32>,
+}
+
+impl ThreatMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        let tiles = (width * height) as usize;
+        ThreatMap {
+            width,
+            height,
+            ground: vec![0.0; tiles],
+            air: vec![0.0; tiles],
+        }
+    }
+
+    /// every enemy building we know about and the army we've seen lately
+    pub fn from_seen(game: &Game, seen: &HaveSeen) -> Self {
+        let mut map = ThreatMap::new(game.map_width(), game.map_height());
+        for su in seen
+            .get_recent_enemy_army(MEMORY_FRAMES)
+            .into_iter()
+            .chain(seen.get_enemy_buildings())
+        {
+            map.add(su.unit_type, su.position);
+        }
+        map
+    }
+
+    pub fn add(&mut self, unit_type: UnitType, position: Position) {
+        let size = unit_type.width().max(unit_type.height()) / 2;
+        let (ground, air) = match unit_type {
+            // bunkers shoot with the marines inside, assume it's full
+            UnitType::Terran_Bunker => {
+                let marines = dps(WeaponType::Gauss_Rifle) * 4.0;
+                let range = WeaponType::Gauss_Rifle.max_range() + 32;
+                ((marines, range), (marines, range))
+            }
+            _ => {
+                let ground = unit_type.ground_weapon();
+                let air = unit_type.air_weapon();
+                (
+                    (dps(ground), ground.max_range()),
+                    (dps(air), air.max_range()),
+                )
+            }
+        };
+        if ground.0 > 0.0 {
+            self.spread(true, position, ground.1 + size + MARGIN, ground.0);
+        }
+        if air.0 > 0.0 {
+            self.spread(false, position, air.1 + size + MARGIN, air.0);
+        }
+    }
+
+    fn spread(&mut self, ground: bool, position: Position, range: i32, dps: f32) {
+        let tl = (position - Position { x: range, y: range }).to_tile_position();
+        let br = (position + Position { x: range, y: range }).to_tile_position();
+        for y in tl.y.max(0)..=br.y.min(self.height - 1) {
+            for x in tl.x.max(0)..=br.x.min(self.width - 1) {
+                let (dx, dy) = (x * 32 + 16 - position.x, y * 32 + 16 - position.y);
+                if dx * dx + dy * dy <= range * range {
+                    let i = (y * self.width + x) as usize;
+                    if ground {
+                        self.ground[i] += dps;
+                    } else {
+                        self.air[i] += dps;
+                    }
+                }
+            }
+        }
+    }
+
+    fn index(&self, tile: TilePosition) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 || tile.x >= self.width || tile.y >= self.height {
+            None
+        } else {
+            Some((tile.y * self.width + tile.x) as usize)
+        }
+    }
+
+    fn tile(&self, index: usize) -> TilePosition {
+        TilePosition {
+            x: index as i32 % self.width,
+            y: index as i32 / self.width,
+        }
+    }
+
+    /// enemy dps against ground units on this tile
+    pub fn ground(&self, tile: TilePosition) -> f32 {
+        self.index(tile).map_or(0.0, |i| self.ground[i])
+    }
+
+    /// enemy dps against air units on this tile
+    pub fn air(&self, tile: TilePosition) -> f32 {
+        self.index(tile).map_or(0.0, |i| self.air[i])
+    }
+
+    /// The cheapest ground path from every tile to one of our mineral lines
+    /// with no threat on it, weighing distance against threat. `lines` is one
+    /// patch per base, searched from once for all the drones that need to run
+    pub fn flee_paths(
+        &self,
+        lines: &[(UnitId, TilePosition)],
+        walkable: impl Fn(TilePosition) -> bool,
+    ) -> FleePaths {
+        let tiles = self.ground.len();
+        let mut cost = vec![i32::MAX; tiles];
+        let mut paths = FleePaths {
+            next: vec![usize::MAX; tiles],
+            patch: vec![None; tiles],
+        };
+        let mut open = BinaryHeap::new();
+        let mut lines = lines.to_vec();
+        lines.sort_by_key(|(id, _)| *id);
+        for (id, tile) in lines {
+            match self.index(tile) {
+                Some(i) if self.ground[i] <= 0.0 && cost[i] > 0 => {
+                    cost[i] = 0;
+                    paths.patch[i] = Some(id);
+                    open.push(Reverse((0, i)));
+                }
+                _ => {}
+            }
+        }
+        while let Some(Reverse((c, j))) = open.pop() {
+            let tile = self.tile(j);
+            if c > cost[j] || !walkable(tile) {
+                continue;
+            }
+            // walking onto `j` costs its threat
+            let threat = (self.ground[j] * THREAT_COST) as i32;
+            for (dx, dy) in NEIGHBOURS {
+                let i = match self.index(tile + TilePosition { x: dx, y: dy }) {
+                    Some(i) => i,
+                    None => continue,
+                };
+                let step = if dx != 0 && dy != 0 { 14 } else { 10 };
+                let c = c + step + threat;
+                if c < cost[i] {
+                    cost[i] = c;
+                    paths.next[i] = j;
+                    paths.patch[i] = paths.patch[j];
+                    open.push(Reverse((c, i)));
+                }
+            }
+        }
+        paths
+    }
+
+    /// Where a drone at `from` should run to along `paths`
+    pub fn flee_order(&self, from: TilePosition, paths: &FleePaths) -> FleeOrder {
+        let start = match self.index(from) {
+            Some(start) => start,
+            None => return FleeOrder::Stay,
+        };
+        let patch = match paths.patch[start] {
+            Some(patch) => patch,
+            None => return FleeOrder::Stay,
+        };
+        let mut path = vec![];
+        let mut i = start;
+        while path.len() < FLEE_STEP && paths.next[i] != usize::MAX {
+            i = paths.next[i];
+            path.push(self.tile(i));
+        }
+        match path.last() {
+            None => FleeOrder::Stay,
+            // boxed in, walking won't get us clear
+            Some(_) if path.iter().all(|t| self.ground(*t) > 0.0) => FleeOrder::MineralWalk(patch),
+            Some(to) => FleeOrder::Move(*to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::tp;
+
+    fn middle(tile: TilePosition) -> Position {
+        tile.to_position() + Position { x: 16, y: 16 }
+    }
+
+    #[test]
+    fn test_weapon_range() {
+        let mut map = ThreatMap::new(64, 64);
+        map.add(UnitType::Protoss_Zealot, middle(tp(10, 10)));
+        let zealot = map.ground(tp(10, 10));
+        assert!(zealot > 15.0 && zealot < 20.0, "two hits per swing");
+        assert_eq!(map.ground(tp(12, 10)), zealot, "zealots come to you");
+        assert_eq!(map.ground(tp(14, 10)), 0.0);
+        assert_eq!(map.air(tp(10, 10)), 0.0, "can't hit air");
+
+        map.add(UnitType::Terran_Marine, middle(tp(10, 10)));
+        assert!(map.ground(tp(10, 10)) > zealot, "threats add up");
+        assert!(map.ground(tp(15, 10)) > 0.0, "marines shoot further");
+        assert!(map.air(tp(15, 10)) > 0.0);
+
+        map.add(UnitType::Protoss_Pylon, middle(tp(40, 40)));
+        assert_eq!(map.ground(tp(40, 40)), 0.0, "harmless");
+        map.add(UnitType::Terran_Bunker, middle(tp(40, 40)));
+        assert!(map.ground(tp(40, 40)) > 0.0, "full of marines");
+    }
+
+    /// the whole way from `from` following `paths`
+    fn path(paths: &FleePaths, map: &ThreatMap, from: TilePosition) -> Vec<TilePosition> {
+        let mut path = vec![];
+        let mut i = map.index(from).unwrap();
+        while paths.next[i] != usize::MAX {
+            i = paths.next[i];
+            path.push(map.tile(i));
+        }
+        path
+    }
+
+    #[test]
+    fn test_flee_paths() {
+        let mut map = ThreatMap::new(32, 32);
+        let line = [(1, tp(20, 10))];
+        let paths = map.flee_paths(&line, |_| true);
+        let straight = path(&paths, &map, tp(0, 10));
+        assert_eq!(straight.len(), 20, "straight there");
+        assert_eq!(straight.last(), Some(&tp(20, 10)));
+
+        map.add(UnitType::Zerg_Sunken_Colony, middle(tp(10, 10)));
+        let paths = map.flee_paths(&line, |_| true);
+        let around = path(&paths, &map, tp(0, 10));
+        assert_eq!(around.last(), Some(&tp(20, 10)));
+        assert!(
+            around.iter().all(|t| map.ground(*t) == 0.0),
+            "out of the sunken's range"
+        );
+
+        let wall = |t: TilePosition| t.x != 5;
+        let paths = map.flee_paths(&line, wall);
+        assert_eq!(map.flee_order(tp(0, 10), &paths), FleeOrder::Stay);
+        assert_eq!(paths.patch[map.index(tp(6, 10)).unwrap()], Some(1));
+    }
+
+    #[test]
+    fn test_flee() {
+        let mut map = ThreatMap::new(64, 64);
+        let west = (1, tp(2, 20));
+        let east = (2, tp(36, 20));
+
+        map.add(UnitType::Protoss_Zealot, middle(tp(19, 20)));
+        let order = map.flee_order(tp(20, 20), &map.flee_paths(&[west, east], |_| true));
+        match order {
+            FleeOrder::Move(tile) => assert!(tile.x > 20, "away from the zealot {:?}", tile),
+            _ => panic!("expected a move, not {:?}", order),
+        }
+
+        // in the middle of a dragoon ball
+        for _ in 0..4 {
+            map.add(UnitType::Protoss_Dragoon, middle(tp(20, 20)));
+        }
+        assert_eq!(
+            map.flee_order(tp(20, 20), &map.flee_paths(&[west, east], |_| true)),
+            FleeOrder::MineralWalk(2)
+        );
+
+        let close = (3, tp(22, 20));
+        assert_eq!(
+            map.flee_order(tp(20, 20), &map.flee_paths(&[close], |_| true)),
+            FleeOrder::Stay,
+            "nowhere safe to go"
+        );
+    }
+}
@@ -1,49 +1,65 @@
+use crate::build_order::TechChecker;
+use crate::composition::Composition;
 use crate::counts::Counts;
-use crate::seen::HaveSeen;
-use rsbwapi::{Game, Player, Unit, UnitType};
+use crate::larva::LarvaPlanner;
+use crate::production::{Production, UnitCounts};
+use crate::strategy::Strategy;
+use crate::supply::SupplyPlanner;
+use rsbwapi::{Game, Player, UnitType};
 use std::collections::HashMap;
 
 pub(crate) struct UnitComp {
     unit_counts: HashMap<UnitType, i32>,
-    larva: Vec<Unit>,
+    morphing_counts: HashMap<UnitType, i32>,
 }
 
 // note: supply is doubled by BWAPI so that Zerglings can use an interger amount of supply
 impl UnitComp {
     pub fn new(game: &Game) -> Self {
-        UnitComp {
-            unit_counts: game.self_().unwrap().get_units().iter().fold(
-                HashMap::new(),
-                |mut acc, u| {
-                    let type_ = u.get_type();
-                    let type_ = match type_ {
-                        UnitType::Zerg_Larva | UnitType::Zerg_Egg if u.is_morphing() => {
-                            u.get_build_type()
-                        }
-                        _ => type_,
-                    };
-                    // count finished buildings only
-                    if !type_.is_building() || !u.is_morphing() {
-                        let increment = if type_.is_two_units_in_one_egg() {
-                            2
-                        } else {
-                            1
-                        };
-                        *acc.entry(type_).or_insert(0) += increment;
-                    }
-                    acc
-                },
-            ),
-            larva: vec![],
+        let mut comp = UnitComp {
+            unit_counts: HashMap::new(),
+            morphing_counts: HashMap::new(),
+        };
+        for u in game.self_().unwrap().get_units() {
+            let type_ = u.get_type();
+            match type_ {
+                // what it'll be, so a lurker or guardian in its egg counts as one
+                UnitType::Zerg_Larva
+                | UnitType::Zerg_Egg
+                | UnitType::Zerg_Lurker_Egg
+                | UnitType::Zerg_Cocoon
+                    if u.is_morphing() =>
+                {
+                    comp.add_morphing(u.get_build_type())
+                }
+                // count finished buildings only
+                _ if !type_.is_building() || !u.is_morphing() => {
+                    *comp.unit_counts.entry(type_).or_insert(0) += 1
+                }
+                _ => {}
+            }
         }
+        comp
     }
 
-    pub fn count_of(&self, u: UnitType) -> i32 {
-        *self.unit_counts.get(&u).unwrap_or(&0)
+    fn add_morphing(&mut self, type_: UnitType) {
+        let increment = if type_.is_two_units_in_one_egg() {
+            2
+        } else {
+            1
+        };
+        *self.unit_counts.entry(type_).or_insert(0) += increment;
+        *self.morphing_counts.entry(type_).or_insert(0) += increment;
     }
 
-    fn spawn_maybe(&mut self, counts: &mut Counts, utype: UnitType) -> Option<UnitType> {
-        if let Some(larva) = self.larva.pop() {
+    fn spawn_maybe(
+        &mut self,
+        game: &Game,
+        counts: &mut Counts,
+        planner: &mut LarvaPlanner,
+        utype: UnitType,
+    ) -> Option<UnitType> {
+        if let Some(larva) = planner.take_for(utype).and_then(|id| game.get_unit(id)) {
             let res = larva.train(utype);
             if let Ok(true) = res {
                 counts.bought(utype);
@@ -55,10 +71,9 @@ impl UnitComp {
                     counts.minerals(),
                     counts.gas(),
                 );
-                *self.unit_counts.entry(utype).or_insert(0) += 1;
+                self.add_morphing(utype);
                 return Some(utype);
             } else {
-                self.larva.push(larva);
                 println!(
                     "frame {} :: failed to spawn {:?} -> {:?}",
                     counts.frame(),
@@ -70,77 +85,80 @@ impl UnitComp {
         None
     }
 
-    fn get_next_unit(&self, counts: &Counts, self_: &Player) -> Option<UnitType> {
-        let used = counts.supply_used();
-        let max = counts.supply_max();
-
-        // check overlords first: first one at 9, rest 2 supply early
-        if (used <= 18 && used == max) || (used >= max - 4) {
-            let morphing_overlords = self_
+    fn morph_units(&mut self, counts: &mut Counts, self_: &Player, comp: &Composition) {
+        for (from, to) in comp.morphs_wanted(|ut| self.count_of(ut)) {
+            let extra_supply = to.supply_required() - from.supply_required();
+            if !counts.can_afford(to)
+                || !self_.has_prereqs(&to)
+                || counts.supply_used() + extra_supply > counts.supply_max()
+            {
+                continue;
+            }
+            let morpher = self_
                 .get_units()
-                .iter()
-                .filter(|u| {
-                    (u.get_type() == UnitType::Zerg_Egg || u.get_type() == UnitType::Zerg_Larva)
-                        && u.is_morphing()
-                        && u.get_build_type() == UnitType::Zerg_Overlord
-                })
-                .count();
-            let needed_overlords = if used < 60 { 1 } else { 2 };
-            if morphing_overlords < needed_overlords && counts.can_afford(UnitType::Zerg_Overlord) {
-                return Some(UnitType::Zerg_Overlord);
+                .into_iter()
+                .find(|u| u.get_type() == from && u.is_idle() && !u.is_morphing());
+            if let Some(morpher) = morpher {
+                if let Ok(true) = morpher.morph(to) {
+                    counts.morphed(from, to);
+                    println!(
+                        "frame {} :: morphing a {:?} into a {:?}",
+                        counts.frame(),
+                        from,
+                        to
+                    );
+                    *self.unit_counts.entry(to).or_insert(0) += 1;
+                    *self.unit_counts.entry(from).or_insert(0) -= 1;
+                }
             }
         }
-
-        if used == max {
-            return None; // no more supply
-        }
-
-        let has_pool = self.count_of(UnitType::Zerg_Spawning_Pool) >= 1;
-        let has_spire = self.count_of(UnitType::Zerg_Spire) >= 1;
-
-        // TODO:
-        // make declarative rather than list of spawn statements
-        // ratio of attacking units + number of drones/hatches req to support
-
-        if has_pool
-            && self.count_of(UnitType::Zerg_Zergling) < 8
-            && counts.can_afford(UnitType::Zerg_Zergling)
-        {
-            return Some(UnitType::Zerg_Zergling);
-        }
-
-        if self.count_of(UnitType::Zerg_Drone) < 18 && counts.can_afford(UnitType::Zerg_Drone) {
-            return Some(UnitType::Zerg_Drone);
-        }
-
-        if has_spire && counts.can_afford(UnitType::Zerg_Mutalisk) {
-            return Some(UnitType::Zerg_Mutalisk);
-        }
-
-        if self.count_of(UnitType::Zerg_Drone) < 60 && counts.can_afford(UnitType::Zerg_Drone) {
-            return Some(UnitType::Zerg_Drone);
-        }
-
-        None
     }
 
-    pub fn spawn_units(&mut self, game: &Game, counts: &mut Counts, _seen: &HaveSeen) {
+    pub fn spawn_units(
+        &mut self,
+        game: &Game,
+        counts: &mut Counts,
+        larva: &mut LarvaPlanner,
+        production: &Production,
+        comp: &Composition,
+        strategy: &Strategy,
+    ) {
         if let Some(self_) = game.self_() {
-            self.larva = self_
-                .get_units()
-                .into_iter()
-                .filter(|u| {
-                    u.get_type() == UnitType::Zerg_Larva
-                        && !u.is_morphing()
-                        && u.can_command().unwrap_or(false)
-                })
-                .collect();
-
-            while let Some(next) = self.get_next_unit(counts, &self_) {
-                if self.spawn_maybe(counts, next).is_none() {
+            // enough lings to hold a rush before anything else
+            let ling = UnitType::Zerg_Zergling;
+            while self.count_of(ling) < strategy.zerglings_needed()
+                && counts.can_afford(ling)
+                && (&self_).has_prereqs(&ling)
+            {
+                if self.spawn_maybe(game, counts, larva, ling).is_none() {
                     break;
                 }
             }
+            let supply = SupplyPlanner::new(counts, larva.larva_rate());
+            while let Some(next) =
+                production.next_unit(counts, &*self, &self_, comp, &supply, larva.available())
+            {
+                if self.spawn_maybe(game, counts, larva, next).is_none() {
+                    break;
+                }
+            }
+            if let Some(frames) = supply.frames_until_blocked(counts, larva.available()) {
+                game.draw_text_screen(
+                    (10, 20),
+                    &format!("{}, blocked in {} frames", counts.supply_string(), frames),
+                );
+            }
+            self.morph_units(counts, &self_, comp);
         }
     }
 }
+
+impl UnitCounts for UnitComp {
+    fn count_of(&self, unit_type: UnitType) -> i32 {
+        *self.unit_counts.get(&unit_type).unwrap_or(&0)
+    }
+
+    fn morphing(&self, unit_type: UnitType) -> i32 {
+        *self.morphing_counts.get(&unit_type).unwrap_or(&0)
+    }
+}
@@ -0,0 +1,380 @@
+use rsbwapi::{Position, Unit, UnitId, UnitType};
+use std::cmp::Reverse;
+
+// enemies this close to one of our hatcheries are a threat to the drones
+const DEFENSE_RADIUS: u32 = 32 * 10;
+// fewer workers than this are just scouting
+const WORKER_RUSH_SIZE: usize = 3;
+// hurt drones go back to mining before they die
+const ROTATE_HP: i32 = 15;
+// after this the army does the defending
+const WORKER_DEFENSE_FRAMES: i32 = 24 * 60 * 6;
+// never pull more than this many off the minerals
+const MAX_DEFENDERS: usize = 12;
+
+// our own army near the base stands in for this many drones
+const DRONES_PER_ARMY_UNIT: usize = 3;
+
+/// What worker defense needs to know about a unit
+pub(crate) trait CombatUnit {
+    fn id(&self) -> UnitId;
+    fn unit_type(&self) -> UnitType;
+    fn position(&self) -> Position;
+    /// hit points and shields
+    fn hit_points(&self) -> i32;
+    fn is_completed(&self) -> bool;
+}
+
+impl CombatUnit for Unit {
+    fn id(&self) -> UnitId {
+        self.get_id()
+    }
+    fn unit_type(&self) -> UnitType {
+        self.get_type()
+    }
+    fn position(&self) -> Position {
+        self.get_position()
+    }
+    fn hit_points(&self) -> i32 {
+        self.get_hit_points() + self.get_shields()
+    }
+    fn is_completed(&self) -> bool {
+        self.is_completed()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum WorkerThreat {
+    Nothing,
+    WorkerRush,
+    /// early zealots, marines or lings before we have an army
+    Proxy,
+    /// cannons or bunkers going up near our base
+    CannonRush,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum DefenseOrder {
+    Attack(UnitId, UnitId),
+    /// back to mining
+    Release(UnitId),
+}
+
+/// how many drones it takes to deal with one of these, a finished cannon or
+/// bunker shoots back
+fn drones_to_fight(unit_type: UnitType, completed: bool) -> usize {
+    match unit_type {
+        UnitType::Protoss_Photon_Cannon | UnitType::Terran_Bunker if completed => 0,
+        UnitType::Protoss_Zealot | UnitType::Protoss_Photon_Cannon | UnitType::Terran_Bunker => 4,
+        UnitType::Terran_Marine | UnitType::Zerg_Zergling => 3,
+        UnitType::Protoss_Pylon => 2,
+        _ if unit_type.is_worker() => 2,
+        // anything else drones can't fight
+        _ => 0,
+    }
+}
+
+/// kill workers first so they can't build more, pylons last
+fn target_priority(unit_type: UnitType) -> i32 {
+    match unit_type {
+        _ if unit_type.is_worker() => 0,
+        UnitType::Protoss_Pylon => 2,
+        _ => 1,
+    }
+}
+
+fn classify<E: CombatUnit>(enemies: &[&E]) -> WorkerThreat {
+    let types = || enemies.iter().map(|e| e.unit_type());
+    let rush_building = |t: UnitType| {
+        t == UnitType::Protoss_Pylon
+            || t == UnitType::Protoss_Photon_Cannon
+            || t == UnitType::Terran_Bunker
+    };
+    if types().any(rush_building) {
+        WorkerThreat::CannonRush
+    } else if types().any(|t| !t.is_worker()) {
+        WorkerThreat::Proxy
+    } else if types().filter(|t| t.is_worker()).count() >= WORKER_RUSH_SIZE {
+        WorkerThreat::WorkerRush
+    } else {
+        WorkerThreat::Nothing
+    }
+}
+
+/// Pulls drones off minerals as a group to deal with early aggression near
+/// our bases and sends them back once it's over
+pub(crate) struct WorkerDefense {
+    threat: WorkerThreat,
+    focus: Option<UnitId>,
+}
+
+impl WorkerDefense {
+    pub fn new() -> Self {
+        WorkerDefense {
+            threat: WorkerThreat::Nothing,
+            focus: None,
+        }
+    }
+
+    /// Orders for our drones given the enemies we can see. `miners` are
+    /// drones we could pull, `defenders` are already fighting and `army` are
+    /// our combat units that can help out. Only early on, later it's up to
+    /// the army
+    pub fn on_frame<D: CombatUnit, E: CombatUnit>(
+        &mut self,
+        frame: i32,
+        bases: &[Position],
+        miners: &[D],
+        defenders: &[D],
+        enemies: &[E],
+        army: &[D],
+    ) -> Vec<DefenseOrder> {
+        let near_base = |p: Position| {
+            bases
+                .iter()
+                .any(|b| b.chebyshev_distance(p) <= DEFENSE_RADIUS)
+        };
+        let near: Vec<&E> = enemies
+            .iter()
+            .filter(|e| {
+                frame < WORKER_DEFENSE_FRAMES
+                    && drones_to_fight(e.unit_type(), e.is_completed()) > 0
+                    && near_base(e.position())
+            })
+            .collect();
+        let army = army.iter().filter(|a| near_base(a.position())).count();
+        let threat = classify(&near);
+        if threat != self.threat {
+            println!("frame {} :: worker defense against {:?}", frame, threat);
+            self.threat = threat;
+        }
+        let needed = match threat {
+            WorkerThreat::Nothing => 0,
+            _ => near
+                .iter()
+                .map(|e| drones_to_fight(e.unit_type(), e.is_completed()))
+                .sum::<usize>()
+                .saturating_sub(army * DRONES_PER_ARMY_UNIT)
+                .min(MAX_DEFENDERS),
+        };
+
+        // rotate hurt drones out and let go of any we don't need
+        let mut orders = vec![];
+        let mut fighting: Vec<&D> = vec![];
+        for d in defenders {
+            if d.hit_points() < ROTATE_HP {
+                orders.push(DefenseOrder::Release(d.id()));
+            } else {
+                fighting.push(d);
+            }
+        }
+        fighting.sort_by_key(|d| (Reverse(d.hit_points()), d.id()));
+        while fighting.len() > needed {
+            if let Some(d) = fighting.pop() {
+                orders.push(DefenseOrder::Release(d.id()));
+            }
+        }
+        if needed == 0 {
+            self.focus = None;
+            return orders;
+        }
+
+        // everyone hits the same thing
+        let target = near
+            .iter()
+            .min_by_key(|e| (target_priority(e.unit_type()), e.hit_points(), e.id()));
+        let target = match target {
+            Some(target) => target,
+            None => return orders,
+        };
+        if self.focus != Some(target.id()) {
+            self.focus = Some(target.id());
+            for d in fighting.iter() {
+                orders.push(DefenseOrder::Attack(d.id(), target.id()));
+            }
+        }
+
+        let mut healthy: Vec<&D> = miners
+            .iter()
+            .filter(|d| d.hit_points() >= ROTATE_HP)
+            .collect();
+        healthy.sort_by_key(|d| {
+            (
+                d.position().chebyshev_distance(target.position()),
+                Reverse(d.hit_points()),
+                d.id(),
+            )
+        });
+        for d in healthy.into_iter().take(needed - fighting.len()) {
+            orders.push(DefenseOrder::Attack(d.id(), target.id()));
+        }
+        orders
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::FakeUnit;
+
+    fn unit(id: UnitId, unit_type: UnitType, x: i32, hit_points: i32) -> FakeUnit {
+        FakeUnit {
+            hit_points,
+            completed: false,
+            ..FakeUnit::new(id, unit_type, Position { x, y: 0 })
+        }
+    }
+
+    fn drones(ids: std::ops::Range<UnitId>) -> Vec<FakeUnit> {
+        ids.map(|id| unit(id, UnitType::Zerg_Drone, id as i32, 40))
+            .collect()
+    }
+
+    const BASE: [Position; 1] = [Position { x: 0, y: 0 }];
+
+    fn attackers(orders: &[DefenseOrder]) -> Vec<UnitId> {
+        orders
+            .iter()
+            .filter_map(|o| match o {
+                DefenseOrder::Attack(d, _) => Some(*d),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scout_ignored() {
+        let mut wd = WorkerDefense::new();
+        let scouts = vec![unit(100, UnitType::Protoss_Probe, 50, 40)];
+        let orders = wd.on_frame(0, &BASE, &drones(0..10), &[], &scouts, &[]);
+        assert_eq!(orders, vec![], "one probe is a scout");
+        assert_eq!(wd.threat, WorkerThreat::Nothing);
+    }
+
+    #[test]
+    fn test_worker_rush() {
+        let mut wd = WorkerDefense::new();
+        let rush: Vec<FakeUnit> = (100..104)
+            .map(|id| unit(id, UnitType::Terran_SCV, 100, 60))
+            .chain(std::iter::once(unit(104, UnitType::Terran_SCV, 100, 20)))
+            .collect();
+        let miners = drones(0..12);
+        let orders = wd.on_frame(0, &BASE, &miners, &[], &rush, &[]);
+        assert_eq!(wd.threat, WorkerThreat::WorkerRush);
+        assert_eq!(orders.len(), 10, "two drones per scv");
+        assert!(
+            orders
+                .iter()
+                .all(|o| matches!(o, DefenseOrder::Attack(_, 104))),
+            "focus the weakest scv"
+        );
+        assert_eq!(
+            attackers(&orders),
+            (2..12).rev().collect::<Vec<_>>(),
+            "closest drones first"
+        );
+
+        // the fight's on, one drone got hurt
+        let mut defenders = drones(2..12);
+        defenders[0].hit_points = 10;
+        let orders = wd.on_frame(1, &BASE, &drones(0..2), &defenders, &rush, &[]);
+        assert_eq!(
+            orders,
+            vec![DefenseOrder::Release(2), DefenseOrder::Attack(1, 104)],
+            "swap the hurt drone for a healthy one"
+        );
+
+        let orders = wd.on_frame(2, &BASE, &drones(0..2), &drones(2..12), &[], &[]);
+        assert_eq!(orders.len(), 10, "rush is over");
+        assert!(orders.iter().all(|o| matches!(o, DefenseOrder::Release(_))));
+    }
+
+    #[test]
+    fn test_proxy() {
+        let mut wd = WorkerDefense::new();
+        let zealots = vec![unit(100, UnitType::Protoss_Zealot, 100, 160)];
+        let orders = wd.on_frame(0, &BASE, &drones(0..12), &[], &zealots, &[]);
+        assert_eq!(wd.threat, WorkerThreat::Proxy);
+        assert_eq!(orders.len(), 4);
+
+        let lings = vec![unit(50, UnitType::Zerg_Zergling, 0, 35)];
+        let orders = wd.on_frame(0, &BASE, &drones(0..12), &[], &zealots, &lings);
+        assert_eq!(orders.len(), 1, "lings help out");
+
+        let far_away = vec![unit(100, UnitType::Protoss_Zealot, 2_000, 160)];
+        let orders = wd.on_frame(0, &BASE, &drones(0..12), &[], &far_away, &[]);
+        assert_eq!(orders, vec![], "not near our base");
+    }
+
+    #[test]
+    fn test_cannon_rush() {
+        let mut wd = WorkerDefense::new();
+        let rush = vec![
+            unit(100, UnitType::Protoss_Pylon, 200, 100),
+            unit(101, UnitType::Protoss_Photon_Cannon, 200, 50),
+            unit(102, UnitType::Protoss_Probe, 200, 40),
+        ];
+        let orders = wd.on_frame(0, &BASE, &drones(0..12), &[], &rush, &[]);
+        assert_eq!(wd.threat, WorkerThreat::CannonRush);
+        assert_eq!(orders.len(), 8);
+        assert!(
+            orders
+                .iter()
+                .all(|o| matches!(o, DefenseOrder::Attack(_, 102))),
+            "kill the probe first"
+        );
+
+        let orders = wd.on_frame(1, &BASE, &drones(0..4), &drones(4..12), &rush[..2], &[]);
+        assert_eq!(
+            orders[..2],
+            [DefenseOrder::Release(11), DefenseOrder::Release(10)],
+            "two drones no longer needed"
+        );
+        assert!(
+            orders[2..]
+                .iter()
+                .all(|o| matches!(o, DefenseOrder::Attack(_, 101))),
+            "then the cannon"
+        );
+        assert_eq!(attackers(&orders).len(), 6);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut wd = WorkerDefense::new();
+        let zealots: Vec<FakeUnit> = (100..105)
+            .map(|id| unit(id, UnitType::Protoss_Zealot, 100, 160))
+            .collect();
+        let orders = wd.on_frame(0, &BASE, &drones(0..30), &[], &zealots, &[]);
+        assert_eq!(orders.len(), MAX_DEFENDERS, "keep some drones mining");
+
+        let orders = wd.on_frame(
+            WORKER_DEFENSE_FRAMES,
+            &BASE,
+            &drones(0..18),
+            &drones(18..30),
+            &zealots,
+            &[],
+        );
+        assert_eq!(
+            orders.len(),
+            MAX_DEFENDERS,
+            "too late, everyone back to work"
+        );
+        assert!(orders.iter().all(|o| matches!(o, DefenseOrder::Release(_))));
+
+        let mut wd = WorkerDefense::new();
+        let mut cannon = unit(100, UnitType::Protoss_Photon_Cannon, 200, 200);
+        cannon.completed = true;
+        let orders = wd.on_frame(0, &BASE, &drones(0..12), &[], &[cannon], &[]);
+        assert_eq!(orders, vec![], "too late to stop this one");
+    }
+
+    #[test]
+    fn test_air_ignored() {
+        let mut wd = WorkerDefense::new();
+        let mutas = vec![unit(100, UnitType::Zerg_Mutalisk, 50, 120)];
+        let orders = wd.on_frame(0, &BASE, &drones(0..10), &[], &mutas, &[]);
+        assert_eq!(orders, vec![], "drones can't fight these");
+    }
+}